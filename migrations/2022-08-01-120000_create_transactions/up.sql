@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE transactions (
+  id VARCHAR(36) DEFAULT uuid_generate_v4() NOT NULL ,
+  portfolio_id VARCHAR(36) NOT NULL ,
+  ticker_id VARCHAR(36) NOT NULL ,
+  kind VARCHAR(4) NOT NULL ,
+  quantity DOUBLE PRECISION NOT NULL ,
+  price DOUBLE PRECISION NOT NULL ,
+  fees DOUBLE PRECISION NOT NULL ,
+  trade_date DATE NOT NULL ,
+  is_deleted BOOLEAN NOT NULL,
+  CONSTRAINT pk_transaction_id PRIMARY KEY ( id ),
+  CONSTRAINT fk_transaction_portfolio FOREIGN KEY (portfolio_id) REFERENCES portfolios(id),
+  CONSTRAINT fk_transaction_ticker FOREIGN KEY (ticker_id) REFERENCES tickers(id),
+  CONSTRAINT ck_transaction_kind CHECK ( kind IN ('buy', 'sell') )
+);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE portfolios DROP COLUMN cost_basis_method
\ No newline at end of file
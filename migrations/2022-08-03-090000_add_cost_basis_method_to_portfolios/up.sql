@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE portfolios
+  ADD COLUMN cost_basis_method VARCHAR(16) NOT NULL DEFAULT 'fifo',
+  ADD CONSTRAINT ck_portfolio_cost_basis_method CHECK ( cost_basis_method IN ('fifo', 'lifo', 'average_cost') );
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE price_bars (
+  symbol VARCHAR(255) NOT NULL ,
+  "interval" VARCHAR(8) NOT NULL ,
+  "timestamp" BIGINT NOT NULL ,
+  open DOUBLE PRECISION NOT NULL ,
+  high DOUBLE PRECISION NOT NULL ,
+  low DOUBLE PRECISION NOT NULL ,
+  close DOUBLE PRECISION NOT NULL ,
+  adjclose DOUBLE PRECISION NOT NULL ,
+  volume BIGINT NOT NULL ,
+  CONSTRAINT pk_price_bar PRIMARY KEY ( symbol, "interval", "timestamp" )
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE tickers DROP CONSTRAINT fk_ticker_instrument;
+DROP TABLE instruments
\ No newline at end of file
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TABLE instruments (
+  symbol VARCHAR(255) NOT NULL ,
+  short_name VARCHAR(255) NOT NULL ,
+  long_name VARCHAR(255) NOT NULL ,
+  exchange VARCHAR(64) NOT NULL ,
+  currency VARCHAR(16) NOT NULL ,
+  asset_type VARCHAR(64) NOT NULL ,
+  last_refreshed TIMESTAMP NOT NULL ,
+  CONSTRAINT pk_instrument PRIMARY KEY ( symbol )
+);
+
+-- Placeholder rows for symbols already held; the epoch timestamp marks them
+-- stale so the price sync fills in the real details.
+INSERT INTO instruments (symbol, short_name, long_name, exchange, currency, asset_type, last_refreshed)
+SELECT DISTINCT name, name, name, '', '', '', TIMESTAMP 'epoch'
+FROM tickers;
+
+ALTER TABLE tickers
+  ADD CONSTRAINT fk_ticker_instrument FOREIGN KEY (name) REFERENCES instruments(symbol);
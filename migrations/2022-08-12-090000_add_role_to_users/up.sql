@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Promote the first administrator by hand: UPDATE users SET role = 'admin' WHERE email = '...';
+ALTER TABLE users
+  ADD COLUMN role VARCHAR(16) NOT NULL DEFAULT 'user',
+  ADD CONSTRAINT ck_user_role CHECK ( role IN ('user', 'admin') );
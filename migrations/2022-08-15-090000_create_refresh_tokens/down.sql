@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE revoked_tokens;
+DROP TABLE refresh_tokens
\ No newline at end of file
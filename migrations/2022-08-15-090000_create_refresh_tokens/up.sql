@@ -0,0 +1,25 @@
+-- Your SQL goes here
+-- Only a SHA-256 hash of each refresh token is stored. Tokens rotated from the
+-- same login share a family_id so a replayed token can revoke the whole chain.
+CREATE TABLE refresh_tokens (
+  id VARCHAR(36) NOT NULL ,
+  user_id VARCHAR(36) NOT NULL ,
+  family_id VARCHAR(36) NOT NULL ,
+  token_hash VARCHAR(64) NOT NULL ,
+  expires_at TIMESTAMP NOT NULL ,
+  created_at TIMESTAMP NOT NULL ,
+  revoked_at TIMESTAMP ,
+  replaced_by VARCHAR(36) ,
+  CONSTRAINT pk_refresh_token PRIMARY KEY ( id ),
+  CONSTRAINT uq_refresh_token_hash UNIQUE ( token_hash ),
+  CONSTRAINT fk_user_refresh_token FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX ix_refresh_token_family ON refresh_tokens ( family_id );
+
+-- Access tokens revoked before they expire, keyed by their `jti` claim.
+CREATE TABLE revoked_tokens (
+  jti VARCHAR(36) NOT NULL ,
+  expires_at TIMESTAMP NOT NULL ,
+  CONSTRAINT pk_revoked_token PRIMARY KEY ( jti )
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE one_time_tokens;
+ALTER TABLE users DROP COLUMN email_verified
\ No newline at end of file
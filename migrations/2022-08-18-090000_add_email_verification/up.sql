@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- Accounts created before verification existed are treated as verified.
+ALTER TABLE users
+  ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE users
+  ALTER COLUMN email_verified SET DEFAULT FALSE;
+
+CREATE TABLE one_time_tokens (
+  token_hash VARCHAR(64) NOT NULL ,
+  user_id VARCHAR(36) NOT NULL ,
+  purpose VARCHAR(32) NOT NULL ,
+  expires_at TIMESTAMP NOT NULL ,
+  used_at TIMESTAMP ,
+  CONSTRAINT pk_one_time_token PRIMARY KEY ( token_hash ),
+  CONSTRAINT fk_user_one_time_token FOREIGN KEY (user_id) REFERENCES users(id)
+);
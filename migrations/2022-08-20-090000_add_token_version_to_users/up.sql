@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Access tokens carry the version they were issued under; bumping it rejects
+-- every token issued before, e.g. after a password reset.
+ALTER TABLE users
+  ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- totp_secret is set on enrollment but only enforced once totp_enabled is
+-- confirmed with a code. totp_last_step stops a code from being replayed.
+ALTER TABLE users
+  ADD COLUMN totp_secret VARCHAR(64) ,
+  ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT FALSE,
+  ADD COLUMN totp_last_step BIGINT ;
+
+CREATE TABLE recovery_codes (
+  id VARCHAR(36) NOT NULL ,
+  user_id VARCHAR(36) NOT NULL ,
+  code_hash VARCHAR(64) NOT NULL ,
+  used_at TIMESTAMP ,
+  CONSTRAINT pk_recovery_code PRIMARY KEY ( id ),
+  CONSTRAINT fk_user_recovery_code FOREIGN KEY (user_id) REFERENCES users(id)
+);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE api_keys (
+  id VARCHAR(36) NOT NULL ,
+  user_id VARCHAR(36) NOT NULL ,
+  name VARCHAR(255) NOT NULL ,
+  prefix VARCHAR(16) NOT NULL ,
+  secret_hash VARCHAR(64) NOT NULL ,
+  scopes TEXT[] NOT NULL ,
+  expires_at TIMESTAMP ,
+  last_used_at TIMESTAMP ,
+  created_at TIMESTAMP NOT NULL ,
+  revoked_at TIMESTAMP ,
+  CONSTRAINT pk_api_key PRIMARY KEY ( id ),
+  CONSTRAINT uq_api_key_prefix UNIQUE ( prefix ),
+  CONSTRAINT fk_user_api_key FOREIGN KEY (user_id) REFERENCES users(id)
+);
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+-- Audit trail of sign-in attempts, also used to throttle repeated failures.
+-- user_id stays NULL when the email did not match an account.
+CREATE TABLE login_events (
+  id VARCHAR(36) NOT NULL ,
+  user_id VARCHAR(36) ,
+  email VARCHAR(255) NOT NULL ,
+  ip VARCHAR(45) ,
+  user_agent TEXT ,
+  method VARCHAR(16) NOT NULL ,
+  success BOOLEAN NOT NULL ,
+  created_at TIMESTAMP NOT NULL ,
+  CONSTRAINT pk_login_event PRIMARY KEY ( id ),
+  CONSTRAINT fk_user_login_event FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX ix_login_event_email ON login_events ( email, created_at );
+CREATE INDEX ix_login_event_ip ON login_events ( ip, created_at );
+CREATE INDEX ix_login_event_user ON login_events ( user_id, created_at );
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE tickers DROP COLUMN created_at;
+ALTER TABLE users DROP COLUMN created_at
\ No newline at end of file
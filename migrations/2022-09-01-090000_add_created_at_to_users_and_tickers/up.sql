@@ -0,0 +1,7 @@
+-- Your SQL goes here
+-- Lets account and ticker lists be sorted by age. Existing rows get the time
+-- of the migration.
+ALTER TABLE users
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT now();
+ALTER TABLE tickers
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT now();
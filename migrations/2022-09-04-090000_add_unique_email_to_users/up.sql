@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- One live account per address: sign-in, password reset and throttling all look
+-- users up by email. Deleted accounts release their address so it can sign up
+-- again. Duplicates must be resolved before this can run.
+CREATE UNIQUE INDEX ux_user_email ON users ( email ) WHERE NOT is_deleted;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Orders trades made on the same day by when they were recorded. Existing rows
+-- share the time of the migration and fall back to their id.
+ALTER TABLE transactions
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT now();
\ No newline at end of file
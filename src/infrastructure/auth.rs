@@ -0,0 +1,166 @@
+use crate::infrastructure::error::ApiError;
+use crate::infrastructure::keys::KeySet;
+use crate::models::authentication::{self, Claims};
+use crate::models::role::Role;
+use crate::models::user::User;
+
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures::future::{ready, Ready};
+
+/// The caller identified by `LoggedGuard`. Only usable on routes wrapped in the
+/// guard; anywhere else it rejects the request as unauthenticated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedUser {
+    pub id: String,
+    pub email: String,
+    pub role: Role,
+}
+
+impl AuthenticatedUser {
+    pub fn owns(&self, user_id: &str) -> bool {
+        self.id == user_id
+    }
+
+    pub fn require(self, role: Role) -> Result<AuthenticatedUser, ApiError> {
+        if self.role == role {
+            Ok(self)
+        } else {
+            Err(ApiError::Forbidden(format!(
+                "This action requires the '{}' role.",
+                role.as_str()
+            )))
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user = match req.extensions().get::<User>() {
+            Some(user) => Ok(AuthenticatedUser {
+                id: user.id.clone(),
+                email: user.email.clone(),
+                role: user.role(),
+            }),
+            None => Err(ApiError::Unauthorized(String::from(
+                "Authentication required",
+            ))),
+        };
+
+        ready(user)
+    }
+}
+
+/// An `AuthenticatedUser` holding the admin role; anyone else gets a 403.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdminUser(pub AuthenticatedUser);
+
+impl FromRequest for AdminUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let admin = match AuthenticatedUser::from_request(req, payload).into_inner() {
+            Ok(user) => user.require(Role::Admin).map(AdminUser),
+            Err(err) => Err(err),
+        };
+
+        ready(admin)
+    }
+}
+
+/// Claims of the bearer token the request was made with, if it carries a valid one.
+pub fn bearer_claims(req: &HttpRequest, keys: &KeySet) -> Option<Claims> {
+    let header = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    let token = header.strip_prefix("Bearer ")?;
+
+    authentication::decode(keys, token.trim()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bearer_claims, AdminUser, AuthenticatedUser};
+    use crate::infrastructure::error::ApiError;
+    use crate::infrastructure::keys::KeySet;
+    use crate::models::role::Role;
+    use crate::models::user::User;
+    use actix_web::test::TestRequest;
+    use actix_web::{FromRequest, HttpMessage};
+
+    #[test]
+    fn test_authenticated_user_from_extension() {
+        let req = TestRequest::default().to_http_request();
+        req.extensions_mut().insert(User::new(
+            String::from("test@mail.com"),
+            String::from("hash"),
+        ));
+        let id = req.extensions().get::<User>().unwrap().id.clone();
+
+        let user = futures::executor::block_on(AuthenticatedUser::extract(&req)).unwrap();
+
+        assert_eq!(user.id, id);
+        assert_eq!(user.email, "test@mail.com");
+        assert!(user.owns(&id));
+    }
+
+    #[test]
+    fn test_authenticated_user_missing() {
+        let req = TestRequest::default().to_http_request();
+
+        let result = futures::executor::block_on(AuthenticatedUser::extract(&req));
+
+        assert!(matches!(result, Err(ApiError::Unauthorized(_))));
+    }
+
+    #[test]
+    fn test_admin_user_rejects_regular_user() {
+        let req = TestRequest::default().to_http_request();
+        req.extensions_mut().insert(User::new(
+            String::from("test@mail.com"),
+            String::from("hash"),
+        ));
+
+        let result = futures::executor::block_on(AdminUser::extract(&req));
+
+        assert!(matches!(result, Err(ApiError::Forbidden(_))));
+    }
+
+    #[test]
+    fn test_admin_user_accepts_admin() {
+        let req = TestRequest::default().to_http_request();
+        let mut user = User::new(String::from("admin@mail.com"), String::from("hash"));
+        user.role = String::from(Role::Admin.as_str());
+        req.extensions_mut().insert(user);
+
+        let admin = futures::executor::block_on(AdminUser::extract(&req)).unwrap();
+
+        assert_eq!(admin.0.role, Role::Admin);
+    }
+
+    #[test]
+    fn test_bearer_claims() {
+        let keys = KeySet::from_secret(
+            b"0123456789abcdef0123456789abcdef",
+            chrono::Duration::minutes(5),
+        )
+        .unwrap();
+        let user = User::new(String::from("user@example.com"), String::new());
+        let req = TestRequest::default()
+            .insert_header((
+                "Authorization",
+                format!("Bearer {}", user.generate_jwt(&keys).unwrap()),
+            ))
+            .to_http_request();
+
+        let claims = bearer_claims(&req, &keys).unwrap();
+        assert_eq!(claims.sub, user.id);
+        assert!(!claims.jti.is_empty());
+
+        let req = TestRequest::default().to_http_request();
+        assert!(bearer_claims(&req, &keys).is_none());
+    }
+}
@@ -0,0 +1,143 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+
+// 9:30 in New York during standard time; half an hour after the open in summer.
+const SESSION_OPEN_UTC: (u32, u32) = (14, 30);
+
+/// Whether US exchanges hold a regular session on `date`.
+pub fn is_trading_day(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !is_holiday(date)
+}
+
+/// Date of the most recent session that has opened by `now`. A daily bar from
+/// this date or later is as current as the market allows.
+pub fn last_session(now: DateTime<Utc>) -> NaiveDate {
+    let (hour, minute) = SESSION_OPEN_UTC;
+    let mut date = now.naive_utc().date();
+
+    if now.naive_utc() < date.and_hms(hour, minute, 0) {
+        date = date.pred();
+    }
+    while !is_trading_day(date) {
+        date = date.pred();
+    }
+
+    date
+}
+
+/// Full-day NYSE holidays. A holiday on a Sunday is observed on Monday and one
+/// on a Saturday on Friday, except New Year's Day, which is then not observed.
+fn is_holiday(date: NaiveDate) -> bool {
+    let year = date.year();
+
+    let mut holidays = vec![
+        nth_weekday(year, 1, Weekday::Mon, 3),
+        nth_weekday(year, 2, Weekday::Mon, 3),
+        easter(year) - Duration::days(2),
+        last_weekday(year, 5, Weekday::Mon),
+        observed(NaiveDate::from_ymd(year, 7, 4)),
+        nth_weekday(year, 9, Weekday::Mon, 1),
+        nth_weekday(year, 11, Weekday::Thu, 4),
+        observed(NaiveDate::from_ymd(year, 12, 25)),
+    ];
+    if year >= 2022 {
+        holidays.push(observed(NaiveDate::from_ymd(year, 6, 19)));
+    }
+    let new_year = NaiveDate::from_ymd(year, 1, 1);
+    if new_year.weekday() != Weekday::Sat {
+        holidays.push(observed(new_year));
+    }
+
+    holidays.contains(&date)
+}
+
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date.pred(),
+        Weekday::Sun => date.succ(),
+        _ => date,
+    }
+}
+
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: i64) -> NaiveDate {
+    let first = NaiveDate::from_ymd(year, month, 1);
+    let offset = (7 + weekday.num_days_from_monday() - first.weekday().num_days_from_monday()) % 7;
+
+    first + Duration::days(offset as i64 + 7 * (n - 1))
+}
+
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    let mut date = NaiveDate::from_ymd(year, month + 1, 1).pred();
+
+    while date.weekday() != weekday {
+        date = date.pred();
+    }
+
+    date
+}
+
+/// Easter Sunday in the Gregorian calendar (anonymous Gregorian algorithm).
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    NaiveDate::from_ymd(year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_trading_day, last_session};
+    use chrono::{DateTime, NaiveDate, Utc};
+
+    fn at(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        DateTime::<Utc>::from_utc(
+            NaiveDate::from_ymd(year, month, day).and_hms(hour, 0, 0),
+            Utc,
+        )
+    }
+
+    #[test]
+    fn test_holidays_are_not_trading_days() {
+        // Good Friday, Juneteenth observed on Monday, Independence Day, Thanksgiving.
+        assert!(!is_trading_day(NaiveDate::from_ymd(2022, 4, 15)));
+        assert!(!is_trading_day(NaiveDate::from_ymd(2022, 6, 20)));
+        assert!(!is_trading_day(NaiveDate::from_ymd(2022, 7, 4)));
+        assert!(!is_trading_day(NaiveDate::from_ymd(2022, 11, 24)));
+        // New Year's Day 2022 fell on a Saturday and was not observed.
+        assert!(is_trading_day(NaiveDate::from_ymd(2021, 12, 31)));
+        assert!(is_trading_day(NaiveDate::from_ymd(2022, 7, 5)));
+    }
+
+    #[test]
+    fn test_last_session_skips_weekend() {
+        let friday = NaiveDate::from_ymd(2022, 7, 29);
+
+        assert_eq!(last_session(at(2022, 7, 29, 20)), friday);
+        assert_eq!(last_session(at(2022, 7, 30, 12)), friday);
+        assert_eq!(last_session(at(2022, 8, 1, 9)), friday);
+        assert_eq!(
+            last_session(at(2022, 8, 1, 15)),
+            NaiveDate::from_ymd(2022, 8, 1)
+        );
+    }
+
+    #[test]
+    fn test_last_session_skips_holiday() {
+        // Labor Day
+        assert_eq!(
+            last_session(at(2022, 9, 5, 18)),
+            NaiveDate::from_ymd(2022, 9, 2)
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::infrastructure::error::ApiError;
+use crate::infrastructure::market_data::ProviderError;
+use crate::infrastructure::state::AppState;
+use crate::models::instrument::Instrument;
+
+/// Returns the catalog entry for `symbol`, fetching it from the provider when it
+/// is missing or stale. A stale entry is still returned if the refresh fails.
+pub async fn ensure_instrument(data: &AppState, symbol: &str) -> Result<Instrument, ApiError> {
+    match Instrument::get_by_symbol(&data.get_connection(), symbol)? {
+        Some(instrument) if !instrument.is_stale() => Ok(instrument),
+        Some(instrument) => match refresh_instrument(data, symbol).await {
+            Ok(refreshed) => Ok(refreshed),
+            Err(err) => {
+                log::warn!("Instrument refresh for {} failed: {}", symbol, err);
+                Ok(instrument)
+            }
+        },
+        None => refresh_instrument(data, symbol).await,
+    }
+}
+
+async fn refresh_instrument(data: &AppState, symbol: &str) -> Result<Instrument, ApiError> {
+    let info = match data.provider().instrument(symbol).await {
+        Ok(info) => info,
+        Err(ProviderError::NotFound(_)) => {
+            return Err(ApiError::NotFound(format!(
+                "Ticker '{}' does not exist.",
+                symbol
+            )))
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(Instrument::upsert(
+        &data.get_connection(),
+        &Instrument::from_info(info),
+    )?)
+}
+
+/// Re-fetches every stale catalog entry, returning the symbols that failed.
+pub async fn refresh_stale(data: &AppState) -> Result<Vec<String>, ApiError> {
+    let stale = Instrument::get_stale(&data.get_connection())?;
+
+    let mut failed: Vec<String> = Vec::new();
+    for instrument in stale {
+        if let Err(err) = refresh_instrument(data, &instrument.symbol).await {
+            log::warn!(
+                "Instrument refresh for {} failed: {}",
+                instrument.symbol,
+                err
+            );
+            failed.push(instrument.symbol);
+        }
+    }
+
+    Ok(failed)
+}
@@ -0,0 +1,754 @@
+use crate::infrastructure::quote_cache::CacheTtls;
+use crate::models::password::HashParams;
+
+use actix_web::http::Uri;
+use log::LevelFilter;
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer};
+use serde::Deserialize;
+use std::env;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Read when `CONFIG_FILE` is not set. Unlike a named file it may be missing.
+pub const DEFAULT_FILE: &str = "config.toml";
+
+#[derive(Debug)]
+pub struct ConfigError(String);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration: {}", self.0)
+    }
+}
+
+/// Everything the server reads at startup. Each section is a table of the
+/// TOML file; every setting can be overridden by the environment variable
+/// named next to it, so secrets need not be written to the file.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub jwt: JwtConfig,
+    pub cors: CorsConfig,
+    pub provider: ProviderConfig,
+    pub mail: MailConfig,
+    pub accounts: AccountsConfig,
+    pub login: LoginConfig,
+    pub session: SessionConfig,
+    pub argon2: HashParams,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// `BIND_ADDRESS`
+    pub bind: SocketAddr,
+    /// `WORKERS`; one per CPU core when left out.
+    pub workers: Option<usize>,
+    /// `JSON_LIMIT_BYTES`: larger request bodies are refused.
+    pub json_limit_bytes: usize,
+    /// `LOG_LEVEL`: `off`, `error`, `warn`, `info`, `debug` or `trace`.
+    #[serde(deserialize_with = "parsed")]
+    pub log_level: LevelFilter,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            bind: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            workers: None,
+            json_limit_bytes: 4096,
+            log_level: LevelFilter::Info,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// `DATABASE_URL`; required.
+    pub url: String,
+    /// `DATABASE_POOL_SIZE`
+    pub pool_size: u32,
+    /// `DATABASE_CONNECTION_TIMEOUT_SECONDS`: how long a request waits for a free connection.
+    pub connection_timeout_seconds: u64,
+    /// `DATABASE_IDLE_TIMEOUT_SECONDS`: idle connections are closed after this; zero keeps them.
+    pub idle_timeout_seconds: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> DatabaseConfig {
+        DatabaseConfig {
+            url: String::new(),
+            pool_size: 10,
+            connection_timeout_seconds: 30,
+            idle_timeout_seconds: 600,
+        }
+    }
+}
+
+/// Either `keyset` or `secret` must be set; a key set file wins.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    /// `JWT_SECRET`: HS256 secret of at least 32 bytes.
+    pub secret: Option<String>,
+    /// `JWT_KEYSET`: key set file, see `KeySet::from_file`.
+    pub keyset: Option<PathBuf>,
+    /// `JWT_LIFETIME_IN_SECONDS`
+    pub lifetime_seconds: i64,
+    /// `REFRESH_TOKEN_LIFETIME_IN_DAYS`
+    pub refresh_lifetime_days: i64,
+}
+
+impl Default for JwtConfig {
+    fn default() -> JwtConfig {
+        JwtConfig {
+            secret: None,
+            keyset: None,
+            lifetime_seconds: 300,
+            refresh_lifetime_days: 30,
+        }
+    }
+}
+
+impl JwtConfig {
+    pub fn access_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.lifetime_seconds)
+    }
+
+    pub fn refresh_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::days(self.refresh_lifetime_days)
+    }
+}
+
+/// Browser origins allowed to call the API. Without any, only same-origin
+/// pages can; `"*"` on its own admits every origin.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// `CORS_ALLOWED_ORIGINS`, comma-separated.
+    pub allowed_origins: Vec<String>,
+    /// `CORS_MAX_AGE_SECONDS`: how long browsers may cache a preflight answer.
+    pub max_age_seconds: usize,
+}
+
+impl Default for CorsConfig {
+    fn default() -> CorsConfig {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            max_age_seconds: 3600,
+        }
+    }
+}
+
+impl CorsConfig {
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins == ["*"]
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketDataSource {
+    Yahoo,
+    Mock,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderConfig {
+    /// `MARKET_DATA_PROVIDER`: `yahoo`, or `mock` for sample data.
+    pub source: MarketDataSource,
+    /// `PRICE_SYNC_INTERVAL_SECONDS`: zero disables the background sync.
+    pub sync_interval_seconds: u64,
+    /// `CACHE_NAMES_TTL_SECONDS`
+    pub names_ttl_seconds: u64,
+    /// `CACHE_QUOTES_TTL_SECONDS`
+    pub quotes_ttl_seconds: u64,
+    /// `CACHE_SERIES_TTL_SECONDS`
+    pub series_ttl_seconds: u64,
+}
+
+impl Default for ProviderConfig {
+    fn default() -> ProviderConfig {
+        ProviderConfig {
+            source: MarketDataSource::Yahoo,
+            sync_interval_seconds: 15 * 60,
+            // Names rarely change, quotes go stale within a minute.
+            names_ttl_seconds: 3 * 24 * 60 * 60,
+            quotes_ttl_seconds: 30,
+            series_ttl_seconds: 5 * 60,
+        }
+    }
+}
+
+impl ProviderConfig {
+    pub fn sync_interval(&self) -> Duration {
+        Duration::from_secs(self.sync_interval_seconds)
+    }
+
+    pub fn cache_ttls(&self) -> CacheTtls {
+        CacheTtls {
+            names: Duration::from_secs(self.names_ttl_seconds),
+            quotes: Duration::from_secs(self.quotes_ttl_seconds),
+            series: Duration::from_secs(self.series_ttl_seconds),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    /// Drops messages into `dir`, for development.
+    File,
+    Memory,
+    Smtp,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    /// `MAILER`
+    pub transport: MailTransport,
+    /// `MAIL_DIR`
+    pub dir: PathBuf,
+    /// `MAIL_FROM`
+    pub from: String,
+    /// `SMTP_HOST`
+    pub smtp_host: String,
+    /// `SMTP_PORT`
+    pub smtp_port: u16,
+    /// `SMTP_USERNAME`, together with `smtp_password`.
+    pub smtp_username: Option<String>,
+    /// `SMTP_PASSWORD`
+    pub smtp_password: Option<String>,
+}
+
+impl Default for MailConfig {
+    fn default() -> MailConfig {
+        MailConfig {
+            transport: MailTransport::File,
+            dir: PathBuf::from("mail"),
+            from: String::from("no-reply@localhost"),
+            smtp_host: String::from("localhost"),
+            smtp_port: 1025,
+            smtp_username: None,
+            smtp_password: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountsConfig {
+    /// `APP_URL`: base of the links sent by email.
+    pub app_url: String,
+    /// `EMAIL_VERIFICATION_LIFETIME_IN_HOURS`
+    pub email_verification_lifetime_hours: i64,
+    /// `PASSWORD_RESET_LIFETIME_IN_MINUTES`
+    pub password_reset_lifetime_minutes: i64,
+    /// `TOTP_ISSUER`: the name authenticator apps show.
+    pub totp_issuer: String,
+}
+
+impl Default for AccountsConfig {
+    fn default() -> AccountsConfig {
+        AccountsConfig {
+            app_url: String::from("http://localhost:8080"),
+            email_verification_lifetime_hours: 24,
+            password_reset_lifetime_minutes: 60,
+            totp_issuer: String::from("Stocks"),
+        }
+    }
+}
+
+/// See `LoginPolicy`.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoginConfig {
+    /// `LOGIN_BACKOFF_AFTER`
+    pub backoff_after: i64,
+    /// `LOGIN_LOCKOUT_AFTER`
+    pub lockout_after: i64,
+    /// `LOGIN_IP_LOCKOUT_AFTER`
+    pub ip_lockout_after: i64,
+    /// `LOGIN_LOCKOUT_MINUTES`
+    pub lockout_minutes: i64,
+}
+
+impl Default for LoginConfig {
+    fn default() -> LoginConfig {
+        LoginConfig {
+            backoff_after: 3,
+            lockout_after: 10,
+            ip_lockout_after: 50,
+            lockout_minutes: 15,
+        }
+    }
+}
+
+/// Key of the session cookie: at least 64 bytes, written as base64.
+#[derive(Clone)]
+pub struct SessionKey(pub Vec<u8>);
+
+impl FromStr for SessionKey {
+    type Err = String;
+
+    fn from_str(encoded: &str) -> Result<SessionKey, String> {
+        base64::decode(encoded.trim())
+            .ok()
+            .filter(|bytes| bytes.len() >= 64)
+            .map(SessionKey)
+            .ok_or_else(|| String::from("must be at least 64 bytes of base64"))
+    }
+}
+
+impl<'de> Deserialize<'de> for SessionKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SessionKey, D::Error> {
+        parsed(deserializer)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSitePolicy {
+    Strict,
+    Lax,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// `SESSION_KEY`. Without it a random key is used and every cookie
+    /// session ends when the server restarts.
+    pub key: Option<SessionKey>,
+    /// `SESSION_COOKIE_SECURE`: only turned off for plain-HTTP development.
+    pub cookie_secure: bool,
+    /// `SESSION_SAME_SITE`: `strict` or `lax`.
+    pub same_site: SameSitePolicy,
+    /// `SESSION_LIFETIME_IN_HOURS`
+    pub lifetime_hours: i64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> SessionConfig {
+        SessionConfig {
+            key: None,
+            cookie_secure: true,
+            same_site: SameSitePolicy::Strict,
+            lifetime_hours: 12,
+        }
+    }
+}
+
+fn parsed<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+/// Reads overrides through `lookup`, which is `std::env::var` outside of tests.
+struct Env<F> {
+    lookup: F,
+}
+
+impl<F: Fn(&str) -> Option<String>> Env<F> {
+    fn parse<T>(&self, name: &str, target: &mut T) -> Result<(), ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Some(value) = (self.lookup)(name) {
+            *target = value
+                .parse()
+                .map_err(|e| ConfigError(format!("{}: {}", name, e)))?;
+        }
+        Ok(())
+    }
+
+    fn parse_some<T>(&self, name: &str, target: &mut Option<T>) -> Result<(), ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        if let Some(value) = (self.lookup)(name) {
+            *target = Some(
+                value
+                    .parse()
+                    .map_err(|e| ConfigError(format!("{}: {}", name, e)))?,
+            );
+        }
+        Ok(())
+    }
+
+    /// A lowercase enum variant, spelled as in the file.
+    fn variant<T: DeserializeOwned>(&self, name: &str, target: &mut T) -> Result<(), ConfigError> {
+        if let Some(value) = (self.lookup)(name) {
+            *target = T::deserialize(value.as_str().into_deserializer())
+                .map_err(|e: de::value::Error| ConfigError(format!("{}: {}", name, e)))?;
+        }
+        Ok(())
+    }
+
+    fn list(&self, name: &str, target: &mut Vec<String>) {
+        if let Some(value) = (self.lookup)(name) {
+            *target = value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect();
+        }
+    }
+}
+
+fn http_uri(url: &str) -> Option<Uri> {
+    let uri = url.parse::<Uri>().ok()?;
+    let is_http = matches!(uri.scheme_str(), Some("http") | Some("https"));
+
+    (is_http && uri.authority().is_some()).then_some(uri)
+}
+
+/// Scheme, host and port only, as browsers send it in `Origin`.
+fn is_origin(origin: &str) -> bool {
+    !origin.ends_with('/')
+        && http_uri(origin)
+            .is_some_and(|uri| uri.path_and_query().is_none_or(|p| p.as_str() == "/"))
+}
+
+impl Config {
+    /// Reads `CONFIG_FILE` (or `config.toml` when present), then applies the
+    /// environment, `.env` included.
+    pub fn load() -> Result<Config, ConfigError> {
+        dotenv::dotenv().ok();
+        let (path, required) = match env::var("CONFIG_FILE") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_FILE), false),
+        };
+
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text, &path)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => Config::default(),
+            Err(e) => return Err(ConfigError(format!("{}: {}", path.display(), e))),
+        };
+        config.apply_env(|name| env::var(name).ok())?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn parse(text: &str, path: &Path) -> Result<Config, ConfigError> {
+        toml::from_str(text).map_err(|e| ConfigError(format!("{}: {}", path.display(), e)))
+    }
+
+    fn apply_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        let env = Env { lookup };
+
+        env.parse("BIND_ADDRESS", &mut self.server.bind)?;
+        env.parse_some("WORKERS", &mut self.server.workers)?;
+        env.parse("JSON_LIMIT_BYTES", &mut self.server.json_limit_bytes)?;
+        env.parse("LOG_LEVEL", &mut self.server.log_level)?;
+
+        env.parse("DATABASE_URL", &mut self.database.url)?;
+        env.parse("DATABASE_POOL_SIZE", &mut self.database.pool_size)?;
+        env.parse(
+            "DATABASE_CONNECTION_TIMEOUT_SECONDS",
+            &mut self.database.connection_timeout_seconds,
+        )?;
+        env.parse(
+            "DATABASE_IDLE_TIMEOUT_SECONDS",
+            &mut self.database.idle_timeout_seconds,
+        )?;
+
+        env.parse_some("JWT_SECRET", &mut self.jwt.secret)?;
+        env.parse_some("JWT_KEYSET", &mut self.jwt.keyset)?;
+        env.parse("JWT_LIFETIME_IN_SECONDS", &mut self.jwt.lifetime_seconds)?;
+        env.parse(
+            "REFRESH_TOKEN_LIFETIME_IN_DAYS",
+            &mut self.jwt.refresh_lifetime_days,
+        )?;
+
+        env.list("CORS_ALLOWED_ORIGINS", &mut self.cors.allowed_origins);
+        env.parse("CORS_MAX_AGE_SECONDS", &mut self.cors.max_age_seconds)?;
+
+        env.variant("MARKET_DATA_PROVIDER", &mut self.provider.source)?;
+        env.parse(
+            "PRICE_SYNC_INTERVAL_SECONDS",
+            &mut self.provider.sync_interval_seconds,
+        )?;
+        env.parse(
+            "CACHE_NAMES_TTL_SECONDS",
+            &mut self.provider.names_ttl_seconds,
+        )?;
+        env.parse(
+            "CACHE_QUOTES_TTL_SECONDS",
+            &mut self.provider.quotes_ttl_seconds,
+        )?;
+        env.parse(
+            "CACHE_SERIES_TTL_SECONDS",
+            &mut self.provider.series_ttl_seconds,
+        )?;
+
+        env.variant("MAILER", &mut self.mail.transport)?;
+        env.parse("MAIL_DIR", &mut self.mail.dir)?;
+        env.parse("MAIL_FROM", &mut self.mail.from)?;
+        env.parse("SMTP_HOST", &mut self.mail.smtp_host)?;
+        env.parse("SMTP_PORT", &mut self.mail.smtp_port)?;
+        env.parse_some("SMTP_USERNAME", &mut self.mail.smtp_username)?;
+        env.parse_some("SMTP_PASSWORD", &mut self.mail.smtp_password)?;
+
+        env.parse("APP_URL", &mut self.accounts.app_url)?;
+        env.parse(
+            "EMAIL_VERIFICATION_LIFETIME_IN_HOURS",
+            &mut self.accounts.email_verification_lifetime_hours,
+        )?;
+        env.parse(
+            "PASSWORD_RESET_LIFETIME_IN_MINUTES",
+            &mut self.accounts.password_reset_lifetime_minutes,
+        )?;
+        env.parse("TOTP_ISSUER", &mut self.accounts.totp_issuer)?;
+
+        env.parse("LOGIN_BACKOFF_AFTER", &mut self.login.backoff_after)?;
+        env.parse("LOGIN_LOCKOUT_AFTER", &mut self.login.lockout_after)?;
+        env.parse("LOGIN_IP_LOCKOUT_AFTER", &mut self.login.ip_lockout_after)?;
+        env.parse("LOGIN_LOCKOUT_MINUTES", &mut self.login.lockout_minutes)?;
+
+        env.parse_some("SESSION_KEY", &mut self.session.key)?;
+        env.parse("SESSION_COOKIE_SECURE", &mut self.session.cookie_secure)?;
+        env.variant("SESSION_SAME_SITE", &mut self.session.same_site)?;
+        env.parse(
+            "SESSION_LIFETIME_IN_HOURS",
+            &mut self.session.lifetime_hours,
+        )?;
+
+        env.parse("ARGON2_MEMORY_KIB", &mut self.argon2.memory_kib)?;
+        env.parse("ARGON2_ITERATIONS", &mut self.argon2.iterations)?;
+        env.parse("ARGON2_PARALLELISM", &mut self.argon2.parallelism)?;
+
+        Ok(())
+    }
+
+    /// Checks what parsing alone cannot, reporting every problem at once.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let mut positive = |value: i64, setting: &str| {
+            if value <= 0 {
+                problems.push(format!("{} must be positive", setting));
+            }
+        };
+
+        positive(
+            self.server.json_limit_bytes as i64,
+            "server.json_limit_bytes",
+        );
+        positive(self.server.workers.unwrap_or(1) as i64, "server.workers");
+        positive(self.database.pool_size as i64, "database.pool_size");
+        positive(
+            self.database.connection_timeout_seconds as i64,
+            "database.connection_timeout_seconds",
+        );
+        positive(self.jwt.lifetime_seconds, "jwt.lifetime_seconds");
+        positive(self.jwt.refresh_lifetime_days, "jwt.refresh_lifetime_days");
+        positive(
+            self.accounts.email_verification_lifetime_hours,
+            "accounts.email_verification_lifetime_hours",
+        );
+        positive(
+            self.accounts.password_reset_lifetime_minutes,
+            "accounts.password_reset_lifetime_minutes",
+        );
+        positive(self.login.lockout_after, "login.lockout_after");
+        positive(self.login.ip_lockout_after, "login.ip_lockout_after");
+        positive(self.login.lockout_minutes, "login.lockout_minutes");
+        positive(self.session.lifetime_hours, "session.lifetime_hours");
+
+        if self.database.url.is_empty() {
+            problems.push(String::from("database.url (DATABASE_URL) is required"));
+        }
+        if self.jwt.secret.is_none() && self.jwt.keyset.is_none() {
+            problems.push(String::from(
+                "set jwt.keyset (JWT_KEYSET) to a key set file or jwt.secret (JWT_SECRET) \
+                 to an HS256 secret",
+            ));
+        }
+        if !self.cors.allows_any_origin() {
+            for origin in &self.cors.allowed_origins {
+                if !is_origin(origin) {
+                    problems.push(format!(
+                        "cors.allowed_origins: '{}' is not an origin like https://example.com; \
+                         use \"*\" alone to allow any",
+                        origin
+                    ));
+                }
+            }
+        }
+        if self.mail.smtp_username.is_some() != self.mail.smtp_password.is_some() {
+            problems.push(String::from(
+                "mail.smtp_username and mail.smtp_password must be set together",
+            ));
+        }
+        if http_uri(&self.accounts.app_url).is_none() {
+            problems.push(format!(
+                "accounts.app_url: '{}' is not an http(s) URL",
+                self.accounts.app_url
+            ));
+        }
+        if let Err(e) = self.argon2.validate() {
+            problems.push(format!("argon2: {}", e));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(problems.join("; ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, MailTransport, MarketDataSource, SameSitePolicy};
+    use log::LevelFilter;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    const FILE: &str = r#"
+        [server]
+        bind = "0.0.0.0:9000"
+        workers = 4
+
+        [database]
+        url = "postgres://file@localhost/stocks"
+        pool_size = 20
+
+        [jwt]
+        secret = "0123456789abcdef0123456789abcdef"
+
+        [cors]
+        allowed_origins = ["https://app.example.com", "http://localhost:3000"]
+
+        [provider]
+        source = "mock"
+    "#;
+
+    fn config(text: &str, vars: &[(&str, &str)]) -> Result<Config, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        let mut config = Config::parse(text, Path::new("test.toml")).map_err(|e| e.to_string())?;
+        config
+            .apply_env(|name| vars.get(name).cloned())
+            .map_err(|e| e.to_string())?;
+        config.validate().map_err(|e| e.to_string())?;
+
+        Ok(config)
+    }
+
+    fn error(text: &str, vars: &[(&str, &str)]) -> String {
+        match config(text, vars) {
+            Ok(_) => panic!("configuration was accepted"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn test_example_file_is_valid() {
+        let example = include_str!("../../config.example.toml");
+        let config = config(
+            example,
+            &[("JWT_SECRET", "0123456789abcdef0123456789abcdef")],
+        )
+        .unwrap();
+
+        assert_eq!(config.server.bind.port(), 8080);
+        assert_eq!(
+            config.argon2,
+            crate::models::password::HashParams::default()
+        );
+    }
+
+    #[test]
+    fn test_file_with_defaults() {
+        let config = config(FILE, &[]).unwrap();
+
+        assert_eq!(config.server.bind.to_string(), "0.0.0.0:9000");
+        assert_eq!(config.server.workers, Some(4));
+        assert_eq!(config.server.json_limit_bytes, 4096);
+        assert_eq!(config.server.log_level, LevelFilter::Info);
+        assert_eq!(config.database.pool_size, 20);
+        assert_eq!(config.database.connection_timeout_seconds, 30);
+        assert_eq!(config.jwt.access_lifetime().num_seconds(), 300);
+        assert_eq!(config.cors.allowed_origins.len(), 2);
+        assert_eq!(config.provider.source, MarketDataSource::Mock);
+        assert_eq!(config.mail.transport, MailTransport::File);
+        assert_eq!(config.session.same_site, SameSitePolicy::Strict);
+    }
+
+    #[test]
+    fn test_environment_overrides_the_file() {
+        let config = config(
+            FILE,
+            &[
+                ("BIND_ADDRESS", "127.0.0.1:8081"),
+                ("DATABASE_URL", "postgres://env@localhost/stocks"),
+                ("JWT_LIFETIME_IN_SECONDS", "60"),
+                (
+                    "CORS_ALLOWED_ORIGINS",
+                    "https://a.example.com, https://b.example.com",
+                ),
+                ("MARKET_DATA_PROVIDER", "yahoo"),
+                ("MAILER", "memory"),
+                ("LOG_LEVEL", "debug"),
+                ("SESSION_SAME_SITE", "lax"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.server.bind.port(), 8081);
+        assert_eq!(config.database.url, "postgres://env@localhost/stocks");
+        assert_eq!(config.jwt.lifetime_seconds, 60);
+        assert_eq!(
+            config.cors.allowed_origins,
+            vec!["https://a.example.com", "https://b.example.com"]
+        );
+        assert_eq!(config.provider.source, MarketDataSource::Yahoo);
+        assert_eq!(config.mail.transport, MailTransport::Memory);
+        assert_eq!(config.server.log_level, LevelFilter::Debug);
+        assert_eq!(config.session.same_site, SameSitePolicy::Lax);
+    }
+
+    #[test]
+    fn test_bad_values_name_the_setting() {
+        assert!(error(FILE, &[("WORKERS", "many")]).contains("WORKERS"));
+        assert!(error(FILE, &[("MARKET_DATA_PROVIDER", "bloomberg")]).contains("bloomberg"));
+        assert!(error(FILE, &[("SESSION_KEY", "short")]).contains("64 bytes"));
+        assert!(error("[server]\nport = 80", &[]).contains("port"));
+    }
+
+    #[test]
+    fn test_validation_reports_every_problem() {
+        let message = error(
+            "[cors]\nallowed_origins = [\"https://app.example.com/\"]",
+            &[("DATABASE_POOL_SIZE", "0"), ("SMTP_USERNAME", "mailer")],
+        );
+
+        assert!(message.contains("database.url"));
+        assert!(message.contains("jwt.secret"));
+        assert!(message.contains("database.pool_size"));
+        assert!(message.contains("https://app.example.com/"));
+        assert!(message.contains("mail.smtp_password"));
+        assert!(!message.contains("jwt.lifetime_seconds"));
+    }
+}
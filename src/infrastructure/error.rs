@@ -0,0 +1,288 @@
+use crate::infrastructure::market_data::ProviderError;
+use crate::models::error::ModelError;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Every error a handler can return. Each variant maps to one HTTP status and one
+/// stable `code` that clients can switch on; `detail` is for humans only.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    NotFound(String),
+    Conflict(String),
+    Validation(String),
+    Unauthorized(String),
+    Forbidden(String),
+    /// Carries the number of seconds to wait, sent as `Retry-After`.
+    RateLimited(String, u64),
+    Upstream(String),
+    Internal(String),
+}
+
+/// RFC 7807 problem details body, extended with `code`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: String,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "not_found",
+            Self::Conflict(_) => "conflict",
+            Self::Validation(_) => "validation_failed",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::RateLimited(..) => "too_many_requests",
+            Self::Upstream(_) => "upstream_unavailable",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn detail(&self) -> &str {
+        match self {
+            Self::NotFound(detail)
+            | Self::Conflict(detail)
+            | Self::Validation(detail)
+            | Self::Unauthorized(detail)
+            | Self::Forbidden(detail)
+            | Self::RateLimited(detail, _)
+            | Self::Upstream(detail)
+            | Self::Internal(detail) => detail,
+        }
+    }
+
+    pub fn problem(&self) -> Problem {
+        let status = self.status_code();
+        Problem {
+            kind: String::from("about:blank"),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: self.detail().to_string(),
+            code: self.code().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.detail())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Validation(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::RateLimited(..) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        response.insert_header((header::CONTENT_TYPE, PROBLEM_JSON));
+        if let Self::RateLimited(_, retry_after) = self {
+            response.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+
+        response.json(self.problem())
+    }
+}
+
+impl From<DieselError> for ApiError {
+    fn from(err: DieselError) -> Self {
+        match err {
+            DieselError::NotFound => ApiError::NotFound(String::from("Resource does not exist.")),
+            // Postgres messages name constraints and indexes, so they stay in the log.
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                log::warn!("Unique violation: {}", info.message());
+                ApiError::Conflict(String::from("Resource already exists."))
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                log::warn!("Foreign key violation: {}", info.message());
+                ApiError::Validation(String::from("Referenced resource does not exist."))
+            }
+            err => {
+                log::error!("Database error: {:?}", err);
+                ApiError::Internal(String::from("Database error."))
+            }
+        }
+    }
+}
+
+impl From<ModelError> for ApiError {
+    fn from(err: ModelError) -> Self {
+        match err {
+            ModelError::Conflict(detail) => ApiError::Conflict(detail),
+            ModelError::InvalidToken(detail) => ApiError::Unauthorized(detail),
+            ModelError::Hash(err) => {
+                log::error!("{}", err);
+                ApiError::Internal(String::from("Unable to hash password."))
+            }
+            ModelError::Database(err) => err.into(),
+        }
+    }
+}
+
+impl From<ProviderError> for ApiError {
+    fn from(err: ProviderError) -> Self {
+        match err {
+            ProviderError::NotFound(_) | ProviderError::EmptyDataSet => {
+                ApiError::NotFound(err.to_string())
+            }
+            ProviderError::Upstream(_) => ApiError::Upstream(err.to_string()),
+        }
+    }
+}
+
+impl From<validator::ValidationErrors> for ApiError {
+    fn from(err: validator::ValidationErrors) -> Self {
+        ApiError::Validation(err.to_string())
+    }
+}
+
+/// Turns body, path and query extractor failures into problem responses.
+pub fn extractor_error<E: fmt::Display>(err: E) -> actix_web::Error {
+    ApiError::Validation(err.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiError, Problem, PROBLEM_JSON};
+    use crate::infrastructure::market_data::ProviderError;
+    use crate::models::error::ModelError;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::ResponseError;
+    use diesel::result::DatabaseErrorKind;
+
+    #[test]
+    fn test_api_error_status_codes() {
+        let cases = vec![
+            (ApiError::NotFound(String::new()), StatusCode::NOT_FOUND),
+            (ApiError::Conflict(String::new()), StatusCode::CONFLICT),
+            (ApiError::Validation(String::new()), StatusCode::BAD_REQUEST),
+            (
+                ApiError::Unauthorized(String::new()),
+                StatusCode::UNAUTHORIZED,
+            ),
+            (ApiError::Forbidden(String::new()), StatusCode::FORBIDDEN),
+            (
+                ApiError::RateLimited(String::new(), 1),
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+            (ApiError::Upstream(String::new()), StatusCode::BAD_GATEWAY),
+            (
+                ApiError::Internal(String::new()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+
+        for (error, status) in cases {
+            assert_eq!(error.status_code(), status);
+        }
+    }
+
+    #[test]
+    fn test_api_error_response_is_problem_json() {
+        let error = ApiError::Conflict(String::from("User already exists"));
+        let response = error.error_response();
+
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            PROBLEM_JSON
+        );
+
+        let body =
+            futures::executor::block_on(actix_web::body::to_bytes(response.into_body())).unwrap();
+        let problem: Problem = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(
+            problem,
+            Problem {
+                kind: String::from("about:blank"),
+                title: String::from("Conflict"),
+                status: 409,
+                detail: String::from("User already exists"),
+                code: String::from("conflict"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_rate_limited_sets_retry_after() {
+        let response = ApiError::RateLimited(String::from("Slow down"), 30).error_response();
+
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "30");
+    }
+
+    #[test]
+    fn test_api_error_from_provider_error() {
+        assert_eq!(
+            ApiError::from(ProviderError::NotFound(String::from("IBMZ"))).code(),
+            "not_found"
+        );
+        assert_eq!(
+            ApiError::from(ProviderError::Upstream(String::from("timeout"))).code(),
+            "upstream_unavailable"
+        );
+    }
+
+    #[test]
+    fn test_api_error_from_diesel_not_found() {
+        assert_eq!(
+            ApiError::from(diesel::result::Error::NotFound).code(),
+            "not_found"
+        );
+    }
+
+    #[test]
+    fn test_api_error_from_model_error() {
+        assert_eq!(
+            ApiError::from(ModelError::Conflict(String::from("Taken"))).code(),
+            "conflict"
+        );
+        assert_eq!(
+            ApiError::from(ModelError::InvalidToken(String::from("Expired"))).code(),
+            "unauthorized"
+        );
+        assert_eq!(
+            ApiError::from(ModelError::Database(diesel::result::Error::NotFound)).code(),
+            "not_found"
+        );
+    }
+
+    #[test]
+    fn test_api_error_from_diesel_hides_database_messages() {
+        let violation = |kind| {
+            diesel::result::Error::DatabaseError(
+                kind,
+                Box::new(String::from(
+                    "duplicate key value violates unique constraint \"ux_user_email\"",
+                )),
+            )
+        };
+
+        let conflict = ApiError::from(violation(DatabaseErrorKind::UniqueViolation));
+        let missing = ApiError::from(violation(DatabaseErrorKind::ForeignKeyViolation));
+
+        assert_eq!(conflict.detail(), "Resource already exists.");
+        assert_eq!(missing.detail(), "Referenced resource does not exist.");
+    }
+}
@@ -0,0 +1,288 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+pub const DEFAULT_INTERVAL: &str = "1d";
+pub const DEFAULT_RANGE: &str = "6mo";
+
+const INTERVALS: [&str; 13] = [
+    "1m", "2m", "5m", "15m", "30m", "60m", "90m", "1h", "1d", "5d", "1wk", "1mo", "3mo",
+];
+
+/// Longest span, in days, Yahoo serves for an interval. Daily and coarser bars are unlimited.
+fn max_days(interval: &str) -> Option<i64> {
+    match interval {
+        "1m" => Some(7),
+        "2m" | "5m" | "15m" | "30m" | "90m" => Some(60),
+        "60m" | "1h" => Some(730),
+        _ => None,
+    }
+}
+
+/// Calendar days covered by a range, or `None` for `max`.
+fn range_days(range: &str) -> Result<Option<i64>, String> {
+    match range {
+        "1d" => Ok(Some(1)),
+        "5d" => Ok(Some(5)),
+        "1mo" => Ok(Some(31)),
+        "3mo" => Ok(Some(92)),
+        "6mo" => Ok(Some(183)),
+        "1y" | "ytd" => Ok(Some(366)),
+        "2y" => Ok(Some(731)),
+        "5y" => Ok(Some(1827)),
+        "10y" => Ok(Some(3653)),
+        "max" => Ok(None),
+        _ => Err(format!("Range '{}' is not supported.", range)),
+    }
+}
+
+/// Query string of `/ticker/{symbol}/history`.
+#[derive(Deserialize, Serialize, Default, IntoParams)]
+pub struct HistoryQuery {
+    pub interval: Option<String>,
+    pub range: Option<String>,
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HistoryPeriod {
+    Range(String),
+    Between(DateTime<Utc>, DateTime<Utc>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HistoryRequest {
+    pub interval: String,
+    pub period: HistoryPeriod,
+}
+
+impl HistoryQuery {
+    /// Checks the interval against either the range or the start/end window and
+    /// resolves defaults. `now` closes an open-ended window.
+    pub fn validate(&self, now: DateTime<Utc>) -> Result<HistoryRequest, String> {
+        let interval = self
+            .interval
+            .clone()
+            .unwrap_or_else(|| String::from(DEFAULT_INTERVAL));
+
+        if !INTERVALS.contains(&interval.as_str()) {
+            return Err(format!("Interval '{}' is not supported.", interval));
+        }
+
+        let (period, days) = match (&self.range, self.start, self.end) {
+            (Some(_), Some(_), _) | (Some(_), _, Some(_)) => {
+                return Err(String::from("Use either range or start/end, not both."))
+            }
+            (_, None, Some(_)) => return Err(String::from("End requires a start date.")),
+            (_, Some(start), end) => {
+                let start = DateTime::<Utc>::from_utc(start.and_hms(0, 0, 0), Utc);
+                let end = match end {
+                    Some(end) => DateTime::<Utc>::from_utc(end.and_hms(23, 59, 59), Utc),
+                    None => now,
+                };
+                if start >= end {
+                    return Err(String::from("Start must be before end."));
+                }
+                let days = (end - start).num_days().max(1);
+                (HistoryPeriod::Between(start, end), Some(days))
+            }
+            (range, None, None) => {
+                let range = range.clone().unwrap_or_else(|| String::from(DEFAULT_RANGE));
+                let days = range_days(&range)?;
+                (HistoryPeriod::Range(range), days)
+            }
+        };
+
+        if let Some(limit) = max_days(&interval) {
+            let too_long = match days {
+                Some(days) => days > limit,
+                None => true,
+            };
+            let too_old = match &period {
+                HistoryPeriod::Between(start, _) => *start < now - Duration::days(limit),
+                HistoryPeriod::Range(_) => false,
+            };
+            if too_long || too_old {
+                return Err(format!(
+                    "Interval '{}' is only available for the last {} days.",
+                    interval, limit
+                ));
+            }
+        }
+
+        Ok(HistoryRequest { interval, period })
+    }
+}
+
+impl HistoryRequest {
+    /// Start and end of the requested period. The start is `None` for `max`.
+    pub fn window(&self, now: DateTime<Utc>) -> (Option<DateTime<Utc>>, DateTime<Utc>) {
+        match &self.period {
+            HistoryPeriod::Between(start, end) => (Some(*start), *end),
+            HistoryPeriod::Range(range) if range == "ytd" => (
+                Some(DateTime::<Utc>::from_utc(
+                    NaiveDate::from_ymd(now.year(), 1, 1).and_hms(0, 0, 0),
+                    Utc,
+                )),
+                now,
+            ),
+            HistoryPeriod::Range(range) => match range_days(range) {
+                Ok(Some(days)) => (Some(now - Duration::days(days)), now),
+                _ => (None, now),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct BarView {
+    pub date: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub adjclose: f64,
+    pub volume: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct HistoryView {
+    pub symbol: String,
+    pub interval: String,
+    pub bars: Vec<BarView>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HistoryPeriod, HistoryQuery, HistoryRequest};
+    use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+    fn now() -> DateTime<Utc> {
+        DateTime::<Utc>::from_utc(NaiveDate::from_ymd(2022, 8, 1).and_hms(12, 0, 0), Utc)
+    }
+
+    fn query(interval: Option<&str>, range: Option<&str>) -> HistoryQuery {
+        HistoryQuery {
+            interval: interval.map(String::from),
+            range: range.map(String::from),
+            ..HistoryQuery::default()
+        }
+    }
+
+    #[test]
+    fn test_history_defaults() {
+        let result = query(None, None).validate(now()).unwrap();
+
+        assert_eq!(
+            result,
+            HistoryRequest {
+                interval: String::from("1d"),
+                period: HistoryPeriod::Range(String::from("6mo")),
+            }
+        );
+    }
+
+    #[test]
+    fn test_history_unknown_interval() {
+        assert!(query(Some("7m"), None).validate(now()).is_err());
+    }
+
+    #[test]
+    fn test_history_unknown_range() {
+        assert!(query(None, Some("3w")).validate(now()).is_err());
+    }
+
+    #[test]
+    fn test_history_intraday_range_too_long() {
+        assert!(query(Some("1m"), Some("1mo")).validate(now()).is_err());
+        assert!(query(Some("1m"), Some("5d")).validate(now()).is_ok());
+        assert!(query(Some("1h"), Some("max")).validate(now()).is_err());
+    }
+
+    #[test]
+    fn test_history_start_end() {
+        let history = HistoryQuery {
+            start: Some(NaiveDate::from_ymd(2022, 7, 1)),
+            end: Some(NaiveDate::from_ymd(2022, 7, 29)),
+            ..HistoryQuery::default()
+        };
+
+        let result = history.validate(now()).unwrap();
+
+        assert_eq!(result.interval, "1d");
+        assert!(matches!(result.period, HistoryPeriod::Between(_, _)));
+    }
+
+    #[test]
+    fn test_history_start_after_end() {
+        let history = HistoryQuery {
+            start: Some(NaiveDate::from_ymd(2022, 7, 29)),
+            end: Some(NaiveDate::from_ymd(2022, 7, 1)),
+            ..HistoryQuery::default()
+        };
+
+        assert!(history.validate(now()).is_err());
+    }
+
+    #[test]
+    fn test_history_range_and_start() {
+        let history = HistoryQuery {
+            range: Some(String::from("1mo")),
+            start: Some(NaiveDate::from_ymd(2022, 7, 1)),
+            ..HistoryQuery::default()
+        };
+
+        assert!(history.validate(now()).is_err());
+    }
+
+    #[test]
+    fn test_history_intraday_start_too_old() {
+        let history = HistoryQuery {
+            interval: Some(String::from("5m")),
+            start: Some(NaiveDate::from_ymd(2022, 1, 3)),
+            end: Some(NaiveDate::from_ymd(2022, 1, 4)),
+            ..HistoryQuery::default()
+        };
+
+        assert!(history.validate(now()).is_err());
+    }
+
+    #[test]
+    fn test_history_window_between() {
+        let start = now() - Duration::days(10);
+        let end = now() - Duration::days(3);
+        let request = HistoryRequest {
+            interval: String::from("1d"),
+            period: HistoryPeriod::Between(start, end),
+        };
+
+        assert_eq!(request.window(now()), (Some(start), end));
+    }
+
+    #[test]
+    fn test_history_window_range() {
+        let request = query(None, Some("1mo")).validate(now()).unwrap();
+
+        assert_eq!(
+            request.window(now()),
+            (Some(now() - Duration::days(31)), now())
+        );
+    }
+
+    #[test]
+    fn test_history_window_ytd() {
+        let request = query(None, Some("ytd")).validate(now()).unwrap();
+        let new_year =
+            DateTime::<Utc>::from_utc(NaiveDate::from_ymd(2022, 1, 1).and_hms(0, 0, 0), Utc);
+
+        assert_eq!(request.window(now()), (Some(new_year), now()));
+    }
+
+    #[test]
+    fn test_history_window_max_is_open_ended() {
+        let request = query(None, Some("max")).validate(now()).unwrap();
+
+        assert_eq!(request.window(now()), (None, now()));
+    }
+}
@@ -0,0 +1,193 @@
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub enum MailError {
+    Address(String),
+    Transport(String),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailError::Address(e) => write!(f, "Invalid email address: {}", e),
+            MailError::Transport(e) => write!(f, "Could not deliver email: {}", e),
+        }
+    }
+}
+
+/// Outgoing mail. Implementations block until the message is handed off.
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+/// Delivers through an SMTP server without TLS, e.g. a local relay or MailHog.
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: u16,
+        from: &str,
+        credentials: Option<(String, String)>,
+    ) -> Result<SmtpMailer, MailError> {
+        let from = from
+            .parse::<Mailbox>()
+            .map_err(|e| MailError::Address(e.to_string()))?;
+
+        let mut builder = SmtpTransport::builder_dangerous(host).port(port);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let to = email
+            .to
+            .parse::<Mailbox>()
+            .map_err(|e| MailError::Address(e.to_string()))?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject.clone())
+            .body(email.body.clone())
+            .map_err(|e| MailError::Address(e.to_string()))?;
+
+        self.transport
+            .send(&message)
+            .map(|_| ())
+            .map_err(|e| MailError::Transport(e.to_string()))
+    }
+}
+
+/// Writes each message to its own file in `dir`, for development.
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf) -> FileMailer {
+        FileMailer { dir }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = self.dir.join(format!("{}-{}.eml", stamp, email.to));
+        let contents = format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            email.to, email.subject, email.body
+        );
+
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&path, contents))
+            .map_err(|e| MailError::Transport(e.to_string()))
+    }
+}
+
+/// Keeps sent messages in memory so tests can inspect them.
+#[derive(Default)]
+pub struct MemoryMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl MemoryMailer {
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+impl Mailer for MemoryMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        self.sent.lock().unwrap().push(email.clone());
+        Ok(())
+    }
+}
+
+pub fn verification_email(to: &str, link: &str, valid_hours: i64) -> Email {
+    Email {
+        to: to.to_string(),
+        subject: String::from("Verify your email address"),
+        body: format!(
+            "Confirm your email address by opening the link below:\n\n{}\n\nThe link expires in {} hours.",
+            link, valid_hours
+        ),
+    }
+}
+
+pub fn password_reset_email(to: &str, link: &str, valid_minutes: i64) -> Email {
+    Email {
+        to: to.to_string(),
+        subject: String::from("Reset your password"),
+        body: format!(
+            "Someone asked to reset the password for this account. If it was you, open the link below:\n\n{}\n\nThe link expires in {} minutes and can only be used once. If you did not ask for this, ignore this email.",
+            link, valid_minutes
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verification_email, FileMailer, Mailer, MemoryMailer};
+    use std::fs;
+
+    #[test]
+    fn test_memory_mailer_keeps_messages() {
+        let mailer = MemoryMailer::default();
+        let email = verification_email("user@example.com", "http://localhost/verify", 24);
+
+        mailer.send(&email).unwrap();
+
+        assert_eq!(mailer.sent(), vec![email]);
+    }
+
+    #[test]
+    fn test_file_mailer_writes_message() {
+        let dir = std::env::temp_dir().join(format!("mail-{}", uuid::Uuid::new_v4()));
+        let mailer = FileMailer::new(dir.clone());
+
+        mailer
+            .send(&verification_email(
+                "user@example.com",
+                "http://localhost/verify",
+                24,
+            ))
+            .unwrap();
+
+        let files = fs::read_dir(&dir).unwrap().collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        let contents = fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(contents.contains("To: user@example.com"));
+        assert!(contents.contains("http://localhost/verify"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,433 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use utoipa::ToSchema;
+use yahoo_finance_api as yahoo;
+
+pub use yahoo::{Dividend, Quote};
+
+/// Upper bound on provider requests in flight when fetching data for many tickers at once.
+pub const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub enum ProviderError {
+    NotFound(String),
+    EmptyDataSet,
+    Upstream(String),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(symbol) => write!(f, "ticker '{}' does not exist", symbol),
+            Self::EmptyDataSet => write!(f, "market data provider returned an empty data set"),
+            Self::Upstream(message) => write!(f, "market data provider failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl From<yahoo::YahooError> for ProviderError {
+    fn from(err: yahoo::YahooError) -> Self {
+        match err {
+            yahoo::YahooError::EmptyDataSet => ProviderError::EmptyDataSet,
+            err => ProviderError::Upstream(err.to_string()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct SearchedTicker {
+    pub symbol: String,
+    pub short_name: String,
+    pub long_name: String,
+}
+
+/// Descriptive data about a security, as opposed to its prices.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct InstrumentInfo {
+    pub symbol: String,
+    pub short_name: String,
+    pub long_name: String,
+    pub exchange: String,
+    pub currency: String,
+    pub asset_type: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct QuoteSeries {
+    pub quotes: Vec<Quote>,
+    pub dividends: Vec<Dividend>,
+}
+
+impl QuoteSeries {
+    pub fn last_quote(&self) -> Result<Quote, ProviderError> {
+        match self.quotes.last() {
+            Some(quote) => Ok(quote.clone()),
+            None => Err(ProviderError::EmptyDataSet),
+        }
+    }
+
+    /// Most recent dividend, or a zero amount dated at the epoch when there was none.
+    pub fn last_dividend(&self) -> Dividend {
+        match self.dividends.last() {
+            Some(dividend) => dividend.clone(),
+            None => Dividend {
+                amount: 0.0,
+                date: 0,
+            },
+        }
+    }
+}
+
+/// Source of quotes, dividends and symbol lookups used by the handlers.
+#[async_trait]
+pub trait MarketDataProvider: Send + Sync {
+    async fn search(&self, query: &str) -> Result<Vec<SearchedTicker>, ProviderError>;
+
+    async fn latest_quote(&self, symbol: &str) -> Result<Quote, ProviderError>;
+
+    /// Catalog data for an exact symbol; fails with `NotFound` for unknown symbols.
+    async fn instrument(&self, symbol: &str) -> Result<InstrumentInfo, ProviderError>;
+
+    async fn quote_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        range: &str,
+    ) -> Result<QuoteSeries, ProviderError>;
+
+    async fn quote_history(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<QuoteSeries, ProviderError>;
+
+    async fn dividends(
+        &self,
+        symbol: &str,
+        interval: &str,
+        range: &str,
+    ) -> Result<Vec<Dividend>, ProviderError> {
+        Ok(self.quote_range(symbol, interval, range).await?.dividends)
+    }
+}
+
+pub struct YahooProvider {
+    connector: yahoo::YahooConnector,
+}
+
+impl YahooProvider {
+    pub fn new() -> YahooProvider {
+        YahooProvider {
+            connector: yahoo::YahooConnector::new(),
+        }
+    }
+}
+
+impl Default for YahooProvider {
+    fn default() -> Self {
+        YahooProvider::new()
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for YahooProvider {
+    async fn search(&self, query: &str) -> Result<Vec<SearchedTicker>, ProviderError> {
+        let result = self.connector.search_ticker(query).await?;
+
+        if result.quotes.is_empty() {
+            return Err(ProviderError::NotFound(query.to_string()));
+        }
+
+        Ok(result
+            .quotes
+            .into_iter()
+            .map(|item| SearchedTicker {
+                symbol: item.symbol,
+                short_name: item.short_name,
+                long_name: item.long_name,
+            })
+            .collect())
+    }
+
+    async fn latest_quote(&self, symbol: &str) -> Result<Quote, ProviderError> {
+        match self.connector.get_latest_quotes(symbol, "1m").await {
+            Ok(response) => Ok(response.last_quote()?),
+            Err(_) => Err(ProviderError::NotFound(symbol.to_string())),
+        }
+    }
+
+    async fn instrument(&self, symbol: &str) -> Result<InstrumentInfo, ProviderError> {
+        let response = match self.connector.get_latest_quotes(symbol, "1d").await {
+            Ok(response) => response,
+            Err(_) => return Err(ProviderError::NotFound(symbol.to_string())),
+        };
+        let meta = match response.chart.result.into_iter().next() {
+            Some(block) => block.meta,
+            None => return Err(ProviderError::NotFound(symbol.to_string())),
+        };
+
+        // The chart endpoint has no display names, so take them from the search hit
+        // for the same symbol, falling back to the symbol itself.
+        let names = match self.search(&meta.symbol).await {
+            Ok(items) => items.into_iter().find(|item| item.symbol == meta.symbol),
+            Err(_) => None,
+        };
+        let (short_name, long_name) = match names {
+            Some(item) => (item.short_name, item.long_name),
+            None => (meta.symbol.clone(), meta.symbol.clone()),
+        };
+
+        Ok(InstrumentInfo {
+            symbol: meta.symbol,
+            short_name,
+            long_name,
+            exchange: meta.exchange_name,
+            currency: meta.currency,
+            asset_type: meta.instrument_type,
+        })
+    }
+
+    async fn quote_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        range: &str,
+    ) -> Result<QuoteSeries, ProviderError> {
+        let response = self
+            .connector
+            .get_quote_range(symbol, interval, range)
+            .await?;
+
+        Ok(QuoteSeries {
+            quotes: response.quotes()?,
+            dividends: response.dividends()?,
+        })
+    }
+
+    async fn quote_history(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<QuoteSeries, ProviderError> {
+        let response = self
+            .connector
+            .get_quote_history_interval(symbol, start, end, interval)
+            .await?;
+
+        Ok(QuoteSeries {
+            quotes: response.quotes()?,
+            dividends: response.dividends()?,
+        })
+    }
+}
+
+struct MockInstrument {
+    search: SearchedTicker,
+    quotes: Vec<Quote>,
+    dividends: Vec<Dividend>,
+}
+
+/// Deterministic in-memory provider for tests and offline development.
+///
+/// Every request for a known symbol returns the same fixture regardless of the
+/// requested interval or range, so results never depend on the time of day.
+/// History requests with explicit dates only trim the fixture to that window.
+#[derive(Default)]
+pub struct MockProvider {
+    instruments: HashMap<String, MockInstrument>,
+}
+
+impl MockProvider {
+    pub fn new() -> MockProvider {
+        MockProvider::default()
+    }
+
+    pub fn with_sample_data() -> MockProvider {
+        MockProvider::new()
+            .with_daily_series("AAPL", "Apple Inc.", "Apple Inc.", 150.0, 0.23)
+            .with_daily_series(
+                "GS",
+                "Goldman Sachs Group, Inc. (The)",
+                "The Goldman Sachs Group, Inc.",
+                320.0,
+                2.5,
+            )
+            .with_daily_series(
+                "MSFT",
+                "Microsoft Corporation",
+                "Microsoft Corporation",
+                260.0,
+                0.62,
+            )
+            .with_daily_series(
+                "IBM",
+                "International Business Machines",
+                "International Business Machines Corporation",
+                130.0,
+                1.65,
+            )
+    }
+
+    pub fn with_instrument(
+        mut self,
+        search: SearchedTicker,
+        quotes: Vec<Quote>,
+        dividends: Vec<Dividend>,
+    ) -> MockProvider {
+        self.instruments.insert(
+            search.symbol.to_uppercase(),
+            MockInstrument {
+                search,
+                quotes,
+                dividends,
+            },
+        );
+        self
+    }
+
+    /// Adds 30 daily bars ending 2022-07-29 drifting up from `base_price`,
+    /// plus a single dividend paid halfway through the series.
+    pub fn with_daily_series(
+        self,
+        symbol: &str,
+        short_name: &str,
+        long_name: &str,
+        base_price: f64,
+        dividend: f64,
+    ) -> MockProvider {
+        const DAY: u64 = 86_400;
+        const LAST_TIMESTAMP: u64 = 1_659_052_800;
+        const BARS: u64 = 30;
+
+        let quotes: Vec<Quote> = (0..BARS)
+            .map(|i| {
+                let open = base_price + i as f64;
+                Quote {
+                    timestamp: LAST_TIMESTAMP - (BARS - 1 - i) * DAY,
+                    open,
+                    high: open + 2.0,
+                    low: open - 1.0,
+                    volume: 1_000_000 + i * 1_000,
+                    close: open + 1.0,
+                    adjclose: open + 1.0,
+                }
+            })
+            .collect();
+
+        let dividends = vec![Dividend {
+            amount: dividend,
+            date: LAST_TIMESTAMP - (BARS / 2) * DAY,
+        }];
+
+        self.with_instrument(
+            SearchedTicker {
+                symbol: symbol.to_string(),
+                short_name: short_name.to_string(),
+                long_name: long_name.to_string(),
+            },
+            quotes,
+            dividends,
+        )
+    }
+
+    fn fixture(&self, symbol: &str) -> Result<&MockInstrument, ProviderError> {
+        match self.instruments.get(&symbol.to_uppercase()) {
+            Some(instrument) => Ok(instrument),
+            None => Err(ProviderError::NotFound(symbol.to_string())),
+        }
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for MockProvider {
+    async fn search(&self, query: &str) -> Result<Vec<SearchedTicker>, ProviderError> {
+        let query = query.to_lowercase();
+
+        let mut results: Vec<SearchedTicker> = self
+            .instruments
+            .values()
+            .filter(|instrument| {
+                instrument.search.symbol.to_lowercase().contains(&query)
+                    || instrument.search.long_name.to_lowercase().contains(&query)
+            })
+            .map(|instrument| instrument.search.clone())
+            .collect();
+        results.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+        if results.is_empty() {
+            return Err(ProviderError::NotFound(query));
+        }
+
+        Ok(results)
+    }
+
+    async fn latest_quote(&self, symbol: &str) -> Result<Quote, ProviderError> {
+        match self.fixture(symbol)?.quotes.last() {
+            Some(quote) => Ok(quote.clone()),
+            None => Err(ProviderError::EmptyDataSet),
+        }
+    }
+
+    async fn instrument(&self, symbol: &str) -> Result<InstrumentInfo, ProviderError> {
+        let instrument = self.fixture(symbol)?;
+
+        Ok(InstrumentInfo {
+            symbol: instrument.search.symbol.clone(),
+            short_name: instrument.search.short_name.clone(),
+            long_name: instrument.search.long_name.clone(),
+            exchange: String::from("NMS"),
+            currency: String::from("USD"),
+            asset_type: String::from("EQUITY"),
+        })
+    }
+
+    async fn quote_range(
+        &self,
+        symbol: &str,
+        _interval: &str,
+        _range: &str,
+    ) -> Result<QuoteSeries, ProviderError> {
+        let instrument = self.fixture(symbol)?;
+
+        Ok(QuoteSeries {
+            quotes: instrument.quotes.clone(),
+            dividends: instrument.dividends.clone(),
+        })
+    }
+
+    async fn quote_history(
+        &self,
+        symbol: &str,
+        _interval: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<QuoteSeries, ProviderError> {
+        let instrument = self.fixture(symbol)?;
+        let start = start.timestamp() as u64;
+        let end = end.timestamp() as u64;
+
+        Ok(QuoteSeries {
+            quotes: instrument
+                .quotes
+                .iter()
+                .filter(|quote| quote.timestamp >= start && quote.timestamp <= end)
+                .cloned()
+                .collect(),
+            dividends: instrument
+                .dividends
+                .iter()
+                .filter(|dividend| dividend.date >= start && dividend.date <= end)
+                .cloned()
+                .collect(),
+        })
+    }
+}
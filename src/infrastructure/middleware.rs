@@ -2,13 +2,28 @@ use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+use crate::infrastructure::error::ApiError;
+use crate::infrastructure::session::{self, CSRF_COOKIE, CSRF_HEADER};
 use crate::infrastructure::state::AppState;
+use crate::infrastructure::throttle::{self, ClientInfo};
+use crate::models::api_key::{ApiKey, ScopeArea};
 use crate::models::authentication::AuthUser;
+use crate::models::revoked_token::RevokedToken;
+use crate::models::user::User;
+use actix_identity::IdentityExt;
 use actix_service::{Service, Transform};
-use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error, HttpMessage};
+use actix_session::SessionExt;
+use actix_web::http::Method;
+use actix_web::{dev::ServiceRequest, dev::ServiceResponse, Error, HttpMessage, ResponseError};
 use futures::future::{ok, Ready};
+
+/// Admits signed-in users (Bearer, Basic or a cookie session). API keys are refused.
 pub struct LoggedGuard;
 
+/// Like `LoggedGuard`, but also admits API keys that hold the read scope (for
+/// GET and HEAD) or the write scope (anything else) of the given area.
+pub struct ScopedGuard(pub ScopeArea);
+
 impl<S> Transform<S, ServiceRequest> for LoggedGuard
 where
     S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
@@ -21,12 +36,35 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(LoggedGuardMiddleware { service })
+        ok(LoggedGuardMiddleware {
+            service,
+            area: None,
+        })
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for ScopedGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = LoggedGuardMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(LoggedGuardMiddleware {
+            service,
+            area: Some(self.0),
+        })
     }
 }
 
 pub struct LoggedGuardMiddleware<S> {
     service: S,
+    area: Option<ScopeArea>,
 }
 
 impl<S> Service<ServiceRequest> for LoggedGuardMiddleware<S>
@@ -43,7 +81,7 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        match is_logged(&req) {
+        match authorize(&req, self.area) {
             Ok(auth) => {
                 req.extensions_mut().insert(auth);
                 let fut = self.service.call(req);
@@ -54,17 +92,84 @@ where
             }
             Err(e) => {
                 println!("Got error: {}", e);
-                Box::pin(async move {
-                    Ok(ServiceResponse::new(
-                        req.into_parts().0,
-                        actix_web::HttpResponse::Unauthorized().body(e),
-                    ))
-                })
+                Box::pin(
+                    async move { Ok(ServiceResponse::new(req.into_parts().0, e.error_response())) },
+                )
             }
         }
     }
 }
 
+fn authorize(req: &ServiceRequest, area: Option<ScopeArea>) -> Result<User, ApiError> {
+    let user = if req.headers().contains_key("Authorization") {
+        is_logged(req).map_err(ApiError::Unauthorized)?
+    } else {
+        session_auth(req)?
+    };
+
+    if let Some(api_key) = req.extensions().get::<ApiKey>() {
+        let scope = match area {
+            Some(area) => area.scope_for(matches!(*req.method(), Method::GET | Method::HEAD)),
+            None => {
+                return Err(ApiError::Forbidden(String::from(
+                    "API keys cannot be used for this endpoint.",
+                )))
+            }
+        };
+        if !api_key.has_scope(scope) {
+            return Err(ApiError::Forbidden(format!(
+                "API key is missing the '{}' scope.",
+                scope.as_str()
+            )));
+        }
+    }
+
+    Ok(user)
+}
+
+/// A cookie session started by `login?session=cookie`. The browser sends the
+/// cookie on its own, so state-changing requests must also prove they were
+/// made by our frontend with the CSRF token.
+fn session_auth(req: &ServiceRequest) -> Result<User, ApiError> {
+    let unauthorized = || ApiError::Unauthorized(String::from("Couldn't retrieve header"));
+
+    let identity = req.get_identity().map_err(|_| unauthorized())?;
+    let session = req.get_session();
+    let (user_id, version) = session::session_user(&identity, &session).ok_or_else(unauthorized)?;
+
+    let state = match req.app_data::<actix_web::web::Data<AppState>>() {
+        Some(state) => state,
+        None => {
+            return Err(ApiError::Internal(String::from(
+                "Application state is not configured",
+            )))
+        }
+    };
+
+    // Same rule as for access tokens: password resets and deletions end the session.
+    match User::get_by_id(&state.get_connection(), &user_id) {
+        Ok(user) if !user.is_deleted && user.token_version == version => {
+            let header = req
+                .headers()
+                .get(CSRF_HEADER)
+                .and_then(|value| value.to_str().ok());
+            let cookie = req.cookie(CSRF_COOKIE);
+            session::check_csrf(
+                req.method(),
+                &session,
+                header,
+                cookie.as_ref().map(|c| c.value()),
+            )?;
+
+            Ok(user)
+        }
+        _ => {
+            identity.logout();
+            Err(ApiError::Unauthorized(String::from("Session has expired.")))
+        }
+    }
+}
+
 fn is_logged(req: &ServiceRequest) -> Result<crate::models::user::User, String> {
     let header = match &req.headers().get("Authorization") {
         Some(head) => match head.to_str().ok() {
@@ -79,29 +184,50 @@ fn is_logged(req: &ServiceRequest) -> Result<crate::models::user::User, String>
     let auth_type = split.next();
 
     if Some("Bearer") == auth_type {
-        bearer_auth(match split.next() {
-            Some(v) => v,
-            None => "",
-        })
+        bearer_auth(split.next().unwrap_or_default(), req)
     } else if Some("Basic") == auth_type {
-        basic_auth(
-            match split.next() {
-                Some(v) => v,
-                None => "",
-            },
-            req,
-        )
+        basic_auth(split.next().unwrap_or_default(), req)
+    } else if Some("ApiKey") == auth_type {
+        api_key_auth(split.next().unwrap_or_default(), req)
     } else {
         Err(String::from("Not valid authentication method"))
     }
 }
 
-fn bearer_auth(data: &str) -> Result<crate::models::user::User, String> {
-    match crate::models::authentication::verify(String::from(data)) {
-        Ok(user) => Ok(user),
+fn bearer_auth(data: &str, req: &ServiceRequest) -> Result<crate::models::user::User, String> {
+    let state = match req.app_data::<actix_web::web::Data<AppState>>() {
+        Some(state) => state,
+        None => return Err(String::from("Application state is not configured")),
+    };
+
+    let claims = match crate::models::authentication::decode(state.keys(), data) {
+        Ok(claims) => claims,
         Err(e) => {
             println!("Got error from jwt: {:?}", e);
-            Err(String::from("Something wrong with the signature"))
+            return Err(String::from("Something wrong with the signature"));
+        }
+    };
+
+    // A token without an id could never be revoked by signing out.
+    if claims.jti.is_empty() {
+        return Err(String::from("Token has been revoked"));
+    }
+    match RevokedToken::is_revoked(&state.get_connection(), &claims.jti) {
+        Ok(false) => (),
+        Ok(true) => return Err(String::from("Token has been revoked")),
+        Err(e) => {
+            log::error!("Revocation check error: {:?}", e);
+            return Err(String::from("Could not verify the token"));
+        }
+    }
+
+    // Loaded fresh so deletions, role changes and session resets apply at once.
+    match User::get_by_id(&state.get_connection(), &claims.sub) {
+        Ok(user) if !user.is_deleted && user.token_version == claims.ver => Ok(user),
+        Ok(_) => Err(String::from("Token has been revoked")),
+        Err(e) => {
+            log::warn!("Bearer auth error: {:?}", e);
+            Err(String::from("Token has been revoked"))
         }
     }
 }
@@ -119,23 +245,75 @@ fn basic_auth(data: &str, req: &ServiceRequest) -> Result<crate::models::user::U
 
     let mut decoded = decoded.split(":");
 
-    let email = match decoded.next() {
-        Some(v) => v,
-        None => "",
-    };
+    let email = decoded.next().unwrap_or_default();
 
-    let password = match decoded.next() {
-        Some(v) => v,
-        None => "",
+    let password = decoded.next().unwrap_or_default();
+
+    let state = match req.app_data::<actix_web::web::Data<AppState>>() {
+        Some(state) => state,
+        None => return Err(String::from("Application state is not configured")),
     };
 
-    let state = req.app_data::<AppState>().unwrap();
-    match AuthUser::authenticate(&state.get_connection(), email, password) {
-        Ok((user, _)) => Ok(user),
+    let client = ClientInfo::from_parts(req.peer_addr(), req.headers());
+    if let Err(e) = throttle::check(
+        &state.get_connection(),
+        state.login_policy(),
+        email,
+        &client,
+    ) {
+        return Err(e.detail().to_string());
+    }
+
+    // Only failures are recorded; scripts authenticate on every request.
+    match AuthUser::authenticate(
+        &state.get_connection(),
+        email,
+        password,
+        &state.config().argon2,
+    ) {
+        Ok(user) if user.totp_enabled => Err(String::from(
+            "Basic auth is not available with two-factor authentication enabled",
+        )),
+        Ok(user) => Ok(user),
         Err(e) => {
             println!("Basic auth error: {:?}", e);
+            let user_id = User::get_by_email(&state.get_connection(), &email.to_string())
+                .ok()
+                .map(|user| user.id);
+            throttle::record(
+                &state.get_connection(),
+                user_id,
+                email,
+                &client,
+                "basic",
+                false,
+            );
 
             Err(String::from("Invalid credentials for basic auth"))
         }
     }
 }
+
+fn api_key_auth(data: &str, req: &ServiceRequest) -> Result<crate::models::user::User, String> {
+    let state = match req.app_data::<actix_web::web::Data<AppState>>() {
+        Some(state) => state,
+        None => return Err(String::from("Application state is not configured")),
+    };
+
+    let api_key = match ApiKey::authenticate(&state.get_connection(), data) {
+        Ok(Some(api_key)) => api_key,
+        Ok(None) => return Err(String::from("Invalid API key")),
+        Err(e) => {
+            log::error!("API key auth error: {:?}", e);
+            return Err(String::from("Could not verify the API key"));
+        }
+    };
+
+    match User::get_by_id(&state.get_connection(), &api_key.user_id) {
+        Ok(user) if !user.is_deleted => {
+            req.extensions_mut().insert(api_key);
+            Ok(user)
+        }
+        _ => Err(String::from("Invalid API key")),
+    }
+}
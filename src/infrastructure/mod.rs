@@ -1,4 +1,20 @@
+pub mod auth;
+pub mod calendar;
+pub mod catalog;
+pub mod config;
+pub mod error;
+pub mod history;
+pub mod keys;
+pub mod mailer;
+pub mod market_data;
 pub mod middleware;
+pub mod openapi;
+pub mod pagination;
+pub mod price_store;
+pub mod quote_cache;
 pub mod routes;
+pub mod session;
 pub mod setup;
 pub mod state;
+pub mod throttle;
+pub mod views;
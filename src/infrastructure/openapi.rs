@@ -0,0 +1,453 @@
+use crate::infrastructure::error::{Problem, PROBLEM_JSON};
+use crate::infrastructure::routes::{self, Access, Endpoint};
+use crate::infrastructure::session::{CSRF_HEADER, SESSION_COOKIE};
+
+use actix_web::http::{Method, StatusCode};
+use std::collections::BTreeMap;
+use utoipa::openapi::path::{HttpMethod, Operation, Parameter, ParameterIn};
+use utoipa::openapi::request_body::RequestBody;
+use utoipa::openapi::security::{
+    ApiKey, ApiKeyValue, Http, HttpAuthScheme, SecurityRequirement, SecurityScheme,
+};
+use utoipa::openapi::{
+    Array, Components, Content, Deprecated, Info, Object, OpenApi, Paths, Ref, RefOr, Required,
+    Response, Schema,
+};
+use utoipa::{IntoParams, PartialSchema, ToSchema};
+
+type Schemas = Vec<(String, RefOr<Schema>)>;
+
+#[derive(Clone, Copy)]
+enum Payload {
+    Json {
+        schema: fn() -> RefOr<Schema>,
+        components: fn(&mut Schemas),
+    },
+    Text,
+}
+
+/// What the OpenAPI document says about one route. Every route in the table
+/// carries one, so a route cannot be registered without being documented.
+#[derive(Clone)]
+pub struct Doc {
+    summary: &'static str,
+    status: StatusCode,
+    body: Option<Payload>,
+    body_required: bool,
+    response: Option<Payload>,
+    query: Option<fn() -> Vec<Parameter>>,
+}
+
+pub fn doc(summary: &'static str) -> Doc {
+    Doc {
+        summary,
+        status: StatusCode::OK,
+        body: None,
+        body_required: false,
+        response: None,
+        query: None,
+    }
+}
+
+fn reference<T: ToSchema>() -> RefOr<Schema> {
+    Ref::from_schema_name(T::name()).into()
+}
+
+fn list<T: ToSchema>() -> RefOr<Schema> {
+    Array::new(reference::<T>()).into()
+}
+
+/// The `Page` envelope around a list of `T`.
+fn page<T: ToSchema>() -> RefOr<Schema> {
+    Object::builder()
+        .property("items", list::<T>())
+        .property("next_cursor", Option::<String>::schema())
+        .property("total", i64::schema())
+        .required("items")
+        .required("total")
+        .into()
+}
+
+fn components<T: ToSchema>(schemas: &mut Schemas) {
+    schemas.push((T::name().into(), T::schema()));
+    T::schemas(schemas);
+}
+
+fn query_parameters<Q: IntoParams>() -> Vec<Parameter> {
+    Q::into_params(|| Some(ParameterIn::Query))
+}
+
+impl Doc {
+    /// JSON request body.
+    pub fn body<T: ToSchema>(self) -> Doc {
+        Doc {
+            body_required: true,
+            ..self.optional_body::<T>()
+        }
+    }
+
+    pub fn optional_body<T: ToSchema>(mut self) -> Doc {
+        self.body = Some(Payload::Json {
+            schema: reference::<T>,
+            components: components::<T>,
+        });
+        self
+    }
+
+    pub fn query<Q: IntoParams>(mut self) -> Doc {
+        self.query = Some(query_parameters::<Q>);
+        self
+    }
+
+    pub fn returns<T: ToSchema>(mut self) -> Doc {
+        self.response = Some(Payload::Json {
+            schema: reference::<T>,
+            components: components::<T>,
+        });
+        self
+    }
+
+    pub fn returns_list<T: ToSchema>(mut self) -> Doc {
+        self.response = Some(Payload::Json {
+            schema: list::<T>,
+            components: components::<T>,
+        });
+        self
+    }
+
+    pub fn returns_page<T: ToSchema>(mut self) -> Doc {
+        self.response = Some(Payload::Json {
+            schema: page::<T>,
+            components: components::<T>,
+        });
+        self
+    }
+
+    /// Plain-text confirmation message.
+    pub fn returns_text(mut self) -> Doc {
+        self.response = Some(Payload::Text);
+        self
+    }
+
+    /// Success status, when it is not 200.
+    pub fn status(mut self, status: StatusCode) -> Doc {
+        self.status = status;
+        self
+    }
+}
+
+fn http_method(method: &Method) -> HttpMethod {
+    match *method {
+        Method::GET => HttpMethod::Get,
+        Method::POST => HttpMethod::Post,
+        Method::PUT => HttpMethod::Put,
+        Method::PATCH => HttpMethod::Patch,
+        Method::DELETE => HttpMethod::Delete,
+        Method::HEAD => HttpMethod::Head,
+        Method::OPTIONS => HttpMethod::Options,
+        _ => HttpMethod::Trace,
+    }
+}
+
+/// Names of the `{placeholders}` in a route path.
+pub fn path_parameters(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+        .collect()
+}
+
+fn content(payload: Payload, schemas: &mut Schemas) -> (&'static str, Content) {
+    match payload {
+        Payload::Json { schema, components } => {
+            components(schemas);
+            ("application/json", Content::new(Some(schema())))
+        }
+        Payload::Text => ("text/plain", Content::new(Some(String::schema()))),
+    }
+}
+
+fn security(access: Access) -> Vec<SecurityRequirement> {
+    let schemes = match access {
+        Access::Public => return Vec::new(),
+        Access::Logged => vec!["bearer", "basic", "session"],
+        Access::Scoped(_) => vec!["bearer", "basic", "session", "api_key"],
+    };
+
+    schemes
+        .into_iter()
+        .map(|name| SecurityRequirement::new::<_, [&str; 0], &str>(name, []))
+        .collect()
+}
+
+fn operation(endpoint: &Endpoint, schemas: &mut Schemas) -> Operation {
+    let doc = &endpoint.doc;
+    let mut operation = Operation::builder()
+        .tag(endpoint.tag)
+        .summary(Some(doc.summary))
+        .securities(Some(security(endpoint.access)));
+
+    for name in path_parameters(&endpoint.path) {
+        operation = operation.parameter(
+            Parameter::builder()
+                .name(name)
+                .parameter_in(ParameterIn::Path)
+                .required(Required::True)
+                .schema(Some(String::schema())),
+        );
+    }
+    if let Some(query) = doc.query {
+        operation = operation.parameters(Some(query()));
+    }
+    if let Some(body) = doc.body {
+        let (content_type, body) = content(body, schemas);
+        operation = operation.request_body(Some(
+            RequestBody::builder()
+                .content(content_type, body)
+                .required(Some(if doc.body_required {
+                    Required::True
+                } else {
+                    Required::False
+                }))
+                .build(),
+        ));
+    }
+
+    let mut success = Response::builder().description(
+        doc.status
+            .canonical_reason()
+            .unwrap_or_else(|| doc.status.as_str()),
+    );
+    if let Some(response) = doc.response {
+        let (content_type, response) = content(response, schemas);
+        success = success.content(content_type, response);
+    }
+    let failure = Response::builder()
+        .description("Problem details")
+        .content(PROBLEM_JSON, Content::new(Some(reference::<Problem>())));
+
+    operation = operation
+        .response(doc.status.as_str(), success.build())
+        .response("default", failure.build());
+    if endpoint.deprecated {
+        operation = operation.deprecated(Some(Deprecated::True));
+    }
+
+    operation.build()
+}
+
+fn security_schemes() -> Vec<(&'static str, SecurityScheme)> {
+    vec![
+        (
+            "bearer",
+            SecurityScheme::Http(
+                Http::builder()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        ),
+        (
+            "basic",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)),
+        ),
+        (
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "Authorization",
+                "`ApiKey <key>`; the key's scopes decide what it may read or change.",
+            ))),
+        ),
+        (
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                SESSION_COOKIE,
+                &format!(
+                    "Cookie session from a login with `?session=cookie`. Unsafe methods \
+                     also need the `{}` header.",
+                    CSRF_HEADER
+                ),
+            ))),
+        ),
+    ]
+}
+
+/// Builds the OpenAPI 3 document for the given routes.
+pub fn document(endpoints: &[Endpoint]) -> OpenApi {
+    let mut schemas = Vec::new();
+    components::<Problem>(&mut schemas);
+
+    let mut paths = Paths::new();
+    for endpoint in endpoints {
+        let operation = operation(endpoint, &mut schemas);
+        paths.add_path_operation(
+            &endpoint.path,
+            vec![http_method(&endpoint.method)],
+            operation,
+        );
+    }
+
+    // Sorted and de-duplicated: the same type shows up under many routes.
+    let schemas: BTreeMap<String, RefOr<Schema>> = schemas.into_iter().collect();
+    let mut components = Components::builder().schemas_from_iter(schemas);
+    for (name, scheme) in security_schemes() {
+        components = components.security_scheme(name, scheme);
+    }
+
+    OpenApi::builder()
+        .info(Info::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")))
+        .paths(paths)
+        .components(Some(components.build()))
+        .build()
+}
+
+/// The document for every route the application serves.
+pub fn api_doc() -> OpenApi {
+    document(&routes::endpoints())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{api_doc, path_parameters};
+    use crate::infrastructure::routes;
+    use crate::infrastructure::session::SessionSettings;
+    use actix_web::cookie::{Key, SameSite};
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::App;
+    use serde_json::Value;
+    use utoipa::openapi::path::{Operation, PathItem};
+
+    fn operation<'a>(item: &'a PathItem, method: &Method) -> Option<&'a Operation> {
+        match *method {
+            Method::GET => item.get.as_ref(),
+            Method::POST => item.post.as_ref(),
+            Method::PUT => item.put.as_ref(),
+            Method::PATCH => item.patch.as_ref(),
+            Method::DELETE => item.delete.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn references(value: &Value, found: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                for (key, nested) in map {
+                    match (key.as_str(), nested) {
+                        ("$ref", Value::String(target)) => found.push(target.clone()),
+                        _ => references(nested, found),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| references(item, found)),
+            _ => (),
+        }
+    }
+
+    fn sessions() -> SessionSettings {
+        SessionSettings {
+            key: Key::generate(),
+            secure: false,
+            same_site: SameSite::Strict,
+            lifetime: chrono::Duration::hours(1),
+        }
+    }
+
+    // Checked against the application itself: without app state every served
+    // route answers 401, 400 or 500, never 404 or 405.
+    #[actix_web::test]
+    async fn test_every_documented_route_is_served() {
+        let sessions = sessions();
+        let app = actix_web::test::init_service(
+            App::new()
+                .wrap(sessions.identity_middleware())
+                .wrap(sessions.session_middleware())
+                .configure(routes::configure),
+        )
+        .await;
+        let methods = [
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ];
+
+        for (path, item) in api_doc().paths.paths {
+            for method in &methods {
+                let operation = match operation(&item, method) {
+                    Some(operation) => operation,
+                    None => continue,
+                };
+
+                assert!(operation.summary.as_deref().is_some_and(|s| !s.is_empty()));
+                let documented: Vec<&str> = operation
+                    .parameters
+                    .iter()
+                    .flatten()
+                    .map(|parameter| parameter.name.as_str())
+                    .collect();
+                for name in path_parameters(&path) {
+                    assert!(
+                        documented.contains(&name),
+                        "{} {} does not document '{}'",
+                        method,
+                        path,
+                        name
+                    );
+                }
+
+                let uri: Vec<&str> = path
+                    .split('/')
+                    .map(|segment| match segment.starts_with('{') {
+                        true => "1",
+                        false => segment,
+                    })
+                    .collect();
+                let response = actix_web::test::call_service(
+                    &app,
+                    actix_web::test::TestRequest::default()
+                        .method(method.clone())
+                        .uri(&uri.join("/"))
+                        .to_request(),
+                )
+                .await;
+
+                assert!(
+                    !matches!(
+                        response.status(),
+                        StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED
+                    ),
+                    "{} {} is documented but not served",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_schema_reference_resolves() {
+        let spec = serde_json::to_value(api_doc()).unwrap();
+        let mut found = Vec::new();
+        references(&spec, &mut found);
+
+        assert!(!found.is_empty());
+        for target in found {
+            let name = target.trim_start_matches("#/components/schemas/");
+            assert!(
+                spec["components"]["schemas"].get(name).is_some(),
+                "{} does not resolve",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_only_legacy_routes_are_deprecated() {
+        let spec = serde_json::to_value(api_doc()).unwrap();
+
+        assert_eq!(spec["paths"]["/login"]["get"]["deprecated"], true);
+        assert!(spec["paths"]["/api/v1/sessions"]["post"]
+            .get("deprecated")
+            .is_none());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::infrastructure::error::ApiError;
+use crate::models::listing::{ListOptions, SortField};
+
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+/// Query string shared by the list endpoints.
+#[derive(Deserialize, Default, IntoParams)]
+pub struct PageQuery {
+    /// Items per page, 1 to 100; 50 when left out.
+    limit: Option<i64>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+    /// `name` or `created_at`, with a leading `-` for descending order.
+    sort: Option<String>,
+    /// Only items whose name contains this, ignoring case.
+    name: Option<String>,
+    /// Admins only: list deleted items too.
+    #[serde(default)]
+    include_deleted: bool,
+}
+
+/// One page of a list and where to continue from. `next_cursor` is `None` on
+/// the last page; `total` counts every item matching the filters.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub total: i64,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: i64, options: &ListOptions) -> Page<T> {
+        let end = options.offset + items.len() as i64;
+
+        Page {
+            next_cursor: (!items.is_empty() && end < total).then(|| encode_cursor(end)),
+            items,
+            total,
+        }
+    }
+
+    pub fn map<V>(self, f: impl FnMut(T) -> V) -> Page<V> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+            total: self.total,
+        }
+    }
+}
+
+fn encode_cursor(offset: i64) -> String {
+    base64::encode_config(offset.to_string(), base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_cursor(cursor: &str) -> Option<i64> {
+    let bytes = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD).ok()?;
+
+    String::from_utf8(bytes)
+        .ok()?
+        .parse()
+        .ok()
+        .filter(|offset| *offset >= 0)
+}
+
+fn parse_sort(sort: &str) -> Option<(SortField, bool)> {
+    let (field, descending) = match sort.strip_prefix('-') {
+        Some(field) => (field, true),
+        None => (sort, false),
+    };
+
+    match field {
+        "name" => Some((SortField::Name, descending)),
+        "created_at" => Some((SortField::CreatedAt, descending)),
+        _ => None,
+    }
+}
+
+impl PageQuery {
+    /// Checks the query and turns it into options for the model layer.
+    pub fn options(&self, is_admin: bool) -> Result<ListOptions, ApiError> {
+        if self.include_deleted && !is_admin {
+            return Err(ApiError::Forbidden(String::from(
+                "Only admins can list deleted items.",
+            )));
+        }
+
+        let offset = match self.cursor.as_deref() {
+            Some(cursor) => decode_cursor(cursor)
+                .ok_or_else(|| ApiError::Validation(String::from("Invalid cursor.")))?,
+            None => 0,
+        };
+        let (sort, descending) = match self.sort.as_deref() {
+            Some(sort) => parse_sort(sort).ok_or_else(|| {
+                ApiError::Validation(String::from(
+                    "sort must be 'name' or 'created_at', optionally prefixed with '-'.",
+                ))
+            })?,
+            None => (SortField::Name, false),
+        };
+
+        Ok(ListOptions {
+            limit: self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            offset,
+            sort,
+            descending,
+            name_contains: self.name.clone(),
+            include_deleted: self.include_deleted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_cursor, Page, PageQuery};
+    use crate::infrastructure::error::ApiError;
+    use crate::models::listing::{ListOptions, SortField};
+
+    #[test]
+    fn test_page_query_options() {
+        let query = PageQuery {
+            limit: Some(500),
+            cursor: Some(encode_cursor(40)),
+            sort: Some(String::from("-created_at")),
+            name: Some(String::from("tech")),
+            include_deleted: false,
+        };
+        let options = query.options(false).unwrap();
+
+        assert_eq!(options.limit, 100);
+        assert_eq!(options.offset, 40);
+        assert_eq!(options.sort, SortField::CreatedAt);
+        assert!(options.descending);
+        assert_eq!(options.name_contains.as_deref(), Some("tech"));
+
+        assert_eq!(
+            PageQuery::default().options(false).unwrap(),
+            ListOptions::default()
+        );
+    }
+
+    #[test]
+    fn test_page_query_rejects_bad_input() {
+        let bad_sort = PageQuery {
+            sort: Some(String::from("password")),
+            ..PageQuery::default()
+        };
+        assert!(matches!(
+            bad_sort.options(true),
+            Err(ApiError::Validation(_))
+        ));
+
+        let bad_cursor = PageQuery {
+            cursor: Some(String::from("not a cursor")),
+            ..PageQuery::default()
+        };
+        assert!(matches!(
+            bad_cursor.options(true),
+            Err(ApiError::Validation(_))
+        ));
+
+        let deleted = PageQuery {
+            include_deleted: true,
+            ..PageQuery::default()
+        };
+        assert!(matches!(
+            deleted.options(false),
+            Err(ApiError::Forbidden(_))
+        ));
+        assert!(deleted.options(true).unwrap().include_deleted);
+    }
+
+    #[test]
+    fn test_next_cursor_stops_at_the_last_page() {
+        let first = ListOptions {
+            limit: 2,
+            ..ListOptions::default()
+        };
+        let page = Page::new(vec![1, 2], 3, &first);
+        assert_eq!(page.next_cursor, Some(encode_cursor(2)));
+
+        let query = PageQuery {
+            limit: Some(2),
+            cursor: page.next_cursor,
+            ..PageQuery::default()
+        };
+        let second = query.options(false).unwrap();
+        assert_eq!(second.offset, 2);
+
+        let last = Page::new(vec![3], 3, &second).map(|n| n * 10);
+        assert_eq!(last.items, vec![30]);
+        assert_eq!(last.next_cursor, None);
+        assert_eq!(last.total, 3);
+    }
+}
@@ -0,0 +1,381 @@
+use crate::infrastructure::calendar;
+use crate::infrastructure::catalog;
+use crate::infrastructure::history::{HistoryPeriod, HistoryRequest};
+use crate::infrastructure::market_data::{
+    MarketDataProvider, ProviderError, Quote, MAX_CONCURRENT_REQUESTS,
+};
+use crate::infrastructure::state::AppState;
+use crate::models::price_bar::PriceBar;
+use crate::models::ticker::Ticker;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use utoipa::ToSchema;
+
+/// Only daily bars are persisted; finer intervals always go to the provider.
+pub const STORE_INTERVAL: &str = "1d";
+const BACKFILL_RANGE: &str = "10y";
+
+#[derive(Serialize, Deserialize, Default, ToSchema)]
+pub struct SyncReport {
+    pub symbols: usize,
+    pub bars: usize,
+    pub failed: Vec<String>,
+}
+
+/// Brings the stored daily series for `symbol` up to date.
+pub async fn sync_symbol(data: &AppState, symbol: &str) -> Result<usize, String> {
+    let latest = match PriceBar::get_latest(&data.get_connection(), symbol, STORE_INTERVAL) {
+        Ok(latest) => latest,
+        Err(err) => return Err(format!("{:?}", err)),
+    };
+
+    let bars = match fetch_bars(data.provider(), symbol, latest.as_ref(), Utc::now()).await {
+        Ok(bars) => bars,
+        Err(err) => return Err(err.to_string()),
+    };
+
+    match PriceBar::upsert_many(&data.get_connection(), &bars) {
+        Ok(written) => Ok(written),
+        Err(err) => Err(format!("{:?}", err)),
+    }
+}
+
+/// Bars to upsert for `symbol`. An empty series is backfilled; otherwise only
+/// bars from the last stored one onwards are fetched, so the last (possibly
+/// partial) session is refreshed too.
+async fn fetch_bars(
+    provider: &dyn MarketDataProvider,
+    symbol: &str,
+    latest: Option<&PriceBar>,
+    now: DateTime<Utc>,
+) -> Result<Vec<PriceBar>, ProviderError> {
+    let series = match latest {
+        Some(bar) => {
+            let start =
+                DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(bar.timestamp, 0), Utc);
+            provider
+                .quote_history(symbol, STORE_INTERVAL, start, now)
+                .await
+        }
+        None => {
+            provider
+                .quote_range(symbol, STORE_INTERVAL, BACKFILL_RANGE)
+                .await
+        }
+    };
+
+    match series {
+        Ok(series) => Ok(series
+            .quotes
+            .iter()
+            .map(|quote| PriceBar::from_quote(symbol, STORE_INTERVAL, quote))
+            .collect()),
+        Err(ProviderError::EmptyDataSet) => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Syncs every symbol referenced by a ticker, carrying on past failures.
+pub async fn sync_all(data: &AppState) -> Result<SyncReport, String> {
+    let symbols = match Ticker::get_all_symbols(&data.get_connection()) {
+        Ok(symbols) => symbols,
+        Err(err) => return Err(format!("{:?}", err)),
+    };
+
+    Ok(sync_each(
+        symbols,
+        |symbol| async move { sync_symbol(data, &symbol).await },
+    )
+    .await)
+}
+
+async fn sync_each<F, Fut>(symbols: Vec<String>, sync: F) -> SyncReport
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<usize, String>>,
+{
+    let mut report = SyncReport {
+        symbols: symbols.len(),
+        ..SyncReport::default()
+    };
+
+    for symbol in symbols {
+        match sync(symbol.clone()).await {
+            Ok(bars) => report.bars += bars,
+            Err(err) => {
+                log::warn!("Price sync for {} failed: {}", symbol, err);
+                report.failed.push(symbol);
+            }
+        }
+    }
+
+    report
+}
+
+/// A stored series is current once it holds a bar for the last session that
+/// has opened, so weekends and holidays do not trigger a fetch.
+fn is_fresh(latest: &PriceBar, now: DateTime<Utc>) -> bool {
+    NaiveDateTime::from_timestamp(latest.timestamp, 0).date() >= calendar::last_session(now)
+}
+
+async fn ensure_fresh(data: &AppState, symbol: &str) -> Result<PriceBar, String> {
+    let latest = match PriceBar::get_latest(&data.get_connection(), symbol, STORE_INTERVAL) {
+        Ok(latest) => latest,
+        Err(err) => return Err(format!("{:?}", err)),
+    };
+
+    match latest {
+        Some(bar) if is_fresh(&bar, Utc::now()) => return Ok(bar),
+        _ => sync_symbol(data, symbol).await?,
+    };
+
+    match PriceBar::get_latest(&data.get_connection(), symbol, STORE_INTERVAL) {
+        Ok(Some(bar)) => Ok(bar),
+        Ok(None) => Err(format!("No stored bars for {}", symbol)),
+        Err(err) => Err(format!("{:?}", err)),
+    }
+}
+
+/// Latest daily bar for `symbol`, read from the store and falling back to the
+/// provider when the store cannot answer.
+pub async fn latest_quote(data: &AppState, symbol: &str) -> Result<Quote, ProviderError> {
+    match ensure_fresh(data, symbol).await {
+        Ok(bar) => Ok(bar.to_quote()),
+        Err(err) => {
+            log::warn!("Price store miss for {}: {}", symbol, err);
+            data.provider()
+                .quote_range(symbol, STORE_INTERVAL, "5d")
+                .await?
+                .last_quote()
+        }
+    }
+}
+
+/// Latest quote for each ticker, in the same order, with a result per ticker.
+pub async fn latest_quotes(
+    data: &AppState,
+    tickers: &[Ticker],
+) -> Vec<Result<Quote, ProviderError>> {
+    stream::iter(tickers)
+        .map(|ticker| latest_quote(data, &ticker.name))
+        .buffered(MAX_CONCURRENT_REQUESTS)
+        .collect()
+        .await
+}
+
+/// Bars for a history request, served from the store when it covers the whole
+/// window and from the provider otherwise.
+pub async fn history(
+    data: &AppState,
+    symbol: &str,
+    request: &HistoryRequest,
+) -> Result<Vec<Quote>, ProviderError> {
+    if request.interval == STORE_INTERVAL {
+        match stored_history(data, symbol, request).await {
+            Ok(Some(quotes)) => return Ok(quotes),
+            Ok(None) => (),
+            Err(err) => log::warn!("Price store miss for {}: {}", symbol, err),
+        }
+    }
+
+    let series = match &request.period {
+        HistoryPeriod::Range(range) => {
+            data.provider()
+                .quote_range(symbol, &request.interval, range)
+                .await?
+        }
+        HistoryPeriod::Between(start, end) => {
+            data.provider()
+                .quote_history(symbol, &request.interval, *start, *end)
+                .await?
+        }
+    };
+
+    Ok(series.quotes)
+}
+
+async fn stored_history(
+    data: &AppState,
+    symbol: &str,
+    request: &HistoryRequest,
+) -> Result<Option<Vec<Quote>>, String> {
+    ensure_fresh(data, symbol).await?;
+
+    let earliest = match PriceBar::get_earliest(&data.get_connection(), symbol, STORE_INTERVAL) {
+        Ok(Some(bar)) => bar,
+        Ok(None) => return Ok(None),
+        Err(err) => return Err(format!("{:?}", err)),
+    };
+
+    let (start, end) = request.window(Utc::now());
+    let start = match start {
+        Some(start) if start.timestamp() >= earliest.timestamp => start.timestamp(),
+        _ => return Ok(None),
+    };
+
+    match PriceBar::get_range(
+        &data.get_connection(),
+        symbol,
+        STORE_INTERVAL,
+        Some(start),
+        Some(end.timestamp()),
+    ) {
+        Ok(bars) => Ok(Some(bars.iter().map(PriceBar::to_quote).collect())),
+        Err(err) => Err(format!("{:?}", err)),
+    }
+}
+
+/// Refreshes stale instruments and runs `sync_all` forever on a fixed period.
+pub async fn run_sync_loop(data: AppState, every: std::time::Duration) {
+    let mut interval = actix_web::rt::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        match catalog::refresh_stale(&data).await {
+            Ok(failed) if !failed.is_empty() => {
+                log::warn!("Instrument refresh: {} failed", failed.len())
+            }
+            Ok(_) => (),
+            Err(err) => log::error!("Instrument refresh failed: {}", err),
+        }
+        match sync_all(&data).await {
+            Ok(report) => log::info!(
+                "Price sync: {} symbols, {} bars, {} failed",
+                report.symbols,
+                report.bars,
+                report.failed.len()
+            ),
+            Err(err) => log::error!("Price sync failed: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fetch_bars, is_fresh, sync_each, STORE_INTERVAL};
+    use crate::infrastructure::market_data::MockProvider;
+    use crate::models::price_bar::PriceBar;
+    use chrono::{DateTime, NaiveDate, Utc};
+
+    fn at(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        DateTime::<Utc>::from_utc(
+            NaiveDate::from_ymd(year, month, day).and_hms(hour, 0, 0),
+            Utc,
+        )
+    }
+
+    fn bar_on(year: i32, month: u32, day: u32) -> PriceBar {
+        PriceBar {
+            symbol: String::from("AAPL"),
+            interval: String::from(STORE_INTERVAL),
+            timestamp: at(year, month, day, 0).timestamp(),
+            open: 150.0,
+            high: 152.0,
+            low: 149.0,
+            close: 151.0,
+            adjclose: 151.0,
+            volume: 1_000_000,
+        }
+    }
+
+    #[test]
+    fn test_fetch_bars_backfills_empty_series() {
+        let provider = MockProvider::with_sample_data();
+
+        let bars =
+            futures::executor::block_on(fetch_bars(&provider, "AAPL", None, at(2022, 8, 1, 12)))
+                .unwrap();
+
+        assert_eq!(bars.len(), 30);
+        assert!(bars
+            .iter()
+            .all(|bar| bar.symbol == "AAPL" && bar.interval == STORE_INTERVAL));
+    }
+
+    #[test]
+    fn test_fetch_bars_refetches_last_stored_bar() {
+        let provider = MockProvider::with_sample_data();
+        let latest = bar_on(2022, 7, 27);
+
+        let bars = futures::executor::block_on(fetch_bars(
+            &provider,
+            "AAPL",
+            Some(&latest),
+            at(2022, 8, 1, 12),
+        ))
+        .unwrap();
+
+        // The stored bar comes back with provider values, so the upsert overwrites it.
+        let timestamps: Vec<i64> = bars.iter().map(|bar| bar.timestamp).collect();
+        assert_eq!(
+            timestamps,
+            vec![
+                latest.timestamp,
+                at(2022, 7, 28, 0).timestamp(),
+                at(2022, 7, 29, 0).timestamp(),
+            ]
+        );
+        assert_ne!(bars[0].close, latest.close);
+    }
+
+    #[test]
+    fn test_fetch_bars_unknown_symbol() {
+        let provider = MockProvider::with_sample_data();
+
+        let result =
+            futures::executor::block_on(fetch_bars(&provider, "ZZZZ", None, at(2022, 8, 1, 12)));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sync_each_reports_failures_and_carries_on() {
+        let provider = MockProvider::with_sample_data();
+        let symbols = vec![
+            String::from("AAPL"),
+            String::from("ZZZZ"),
+            String::from("MSFT"),
+        ];
+
+        let report = futures::executor::block_on(sync_each(symbols, |symbol| {
+            let provider = &provider;
+            async move {
+                match fetch_bars(provider, &symbol, None, at(2022, 8, 1, 12)).await {
+                    Ok(bars) => Ok(bars.len()),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+        }));
+
+        assert_eq!(report.symbols, 3);
+        assert_eq!(report.bars, 60);
+        assert_eq!(report.failed, vec![String::from("ZZZZ")]);
+    }
+
+    #[test]
+    fn test_friday_bar_is_fresh_over_weekend() {
+        let friday = bar_on(2022, 7, 29);
+
+        assert!(is_fresh(&friday, at(2022, 7, 30, 12)));
+        assert!(is_fresh(&friday, at(2022, 7, 31, 23)));
+        assert!(is_fresh(&friday, at(2022, 8, 1, 9)));
+        assert!(!is_fresh(&friday, at(2022, 8, 1, 15)));
+    }
+
+    #[test]
+    fn test_bar_before_holiday_is_fresh() {
+        // Monday 2022-09-05 is Labor Day.
+        let friday = bar_on(2022, 9, 2);
+
+        assert!(is_fresh(&friday, at(2022, 9, 5, 18)));
+        assert!(!is_fresh(&friday, at(2022, 9, 6, 18)));
+    }
+
+    #[test]
+    fn test_older_bar_is_stale() {
+        assert!(!is_fresh(&bar_on(2022, 7, 28), at(2022, 7, 30, 12)));
+    }
+}
@@ -0,0 +1,431 @@
+use crate::infrastructure::market_data::{
+    InstrumentInfo, MarketDataProvider, ProviderError, Quote, QuoteSeries, SearchedTicker,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+type Fetch<V> = Shared<BoxFuture<'static, Result<V, ProviderError>>>;
+
+#[derive(Default)]
+pub struct KindStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, ToSchema)]
+pub struct KindStatsView {
+    pub hits: u64,
+    pub misses: u64,
+    pub coalesced: u64,
+}
+
+impl KindStats {
+    fn view(&self) -> KindStatsView {
+        KindStatsView {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Hit/miss counters for each kind of cached lookup.
+#[derive(Default)]
+pub struct CacheStats {
+    pub names: KindStats,
+    pub quotes: KindStats,
+    pub series: KindStats,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CacheStatsView {
+    pub names: KindStatsView,
+    pub quotes: KindStatsView,
+    pub series: KindStatsView,
+}
+
+impl CacheStats {
+    pub fn view(&self) -> CacheStatsView {
+        CacheStatsView {
+            names: self.names.view(),
+            quotes: self.quotes.view(),
+            series: self.series.view(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CacheTtls {
+    pub names: Duration,
+    pub quotes: Duration,
+    pub series: Duration,
+}
+
+enum Entry<V> {
+    Ready(V, Instant),
+    /// A fetch in flight, numbered so a waiter can tell it from a later one.
+    Pending(Fetch<V>, u64),
+}
+
+struct TtlCache<V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry<V>>>,
+    fetches: AtomicU64,
+}
+
+impl<V: Clone + Send + Sync + 'static> TtlCache<V> {
+    fn new(ttl: Duration) -> TtlCache<V> {
+        TtlCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            fetches: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns a fresh cached value, joins a fetch already in flight for the same
+    /// key, or starts `fetch` and shares it with anyone who asks meanwhile.
+    /// Errors are handed to every waiter but never cached.
+    ///
+    /// Whichever waiter sees the result first settles the entry: the caller
+    /// that started the fetch may have been dropped, and the next one to join
+    /// then drives the fetch to the end.
+    async fn get_or_fetch<F>(
+        &self,
+        key: String,
+        stats: &KindStats,
+        fetch: F,
+    ) -> Result<V, ProviderError>
+    where
+        F: FnOnce() -> BoxFuture<'static, Result<V, ProviderError>>,
+    {
+        let (shared, fetch_id) = {
+            let mut entries = self.entries.lock().unwrap();
+            match entries.get(&key) {
+                Some(Entry::Ready(value, at)) if at.elapsed() < self.ttl => {
+                    stats.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(value.clone());
+                }
+                Some(Entry::Pending(shared, id)) => {
+                    stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                    (shared.clone(), *id)
+                }
+                _ => {
+                    stats.misses.fetch_add(1, Ordering::Relaxed);
+                    let ttl = self.ttl;
+                    entries.retain(|_, entry| match entry {
+                        Entry::Ready(_, at) => at.elapsed() < ttl,
+                        Entry::Pending(..) => true,
+                    });
+                    let shared = fetch().shared();
+                    let id = self.fetches.fetch_add(1, Ordering::Relaxed);
+                    entries.insert(key.clone(), Entry::Pending(shared.clone(), id));
+                    (shared, id)
+                }
+            }
+        };
+
+        let result = shared.await;
+
+        let mut entries = self.entries.lock().unwrap();
+        let settles = matches!(
+            entries.get(&key),
+            Some(Entry::Pending(_, id)) if *id == fetch_id
+        );
+        if settles {
+            match &result {
+                Ok(value) => {
+                    entries.insert(key, Entry::Ready(value.clone(), Instant::now()));
+                }
+                Err(_) => {
+                    entries.remove(&key);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Provider decorator that caches lookups in memory and coalesces concurrent
+/// requests for the same key into a single upstream call.
+pub struct CachedProvider {
+    inner: Arc<dyn MarketDataProvider>,
+    stats: Arc<CacheStats>,
+    names: TtlCache<Vec<SearchedTicker>>,
+    instruments: TtlCache<InstrumentInfo>,
+    quotes: TtlCache<Quote>,
+    series: TtlCache<QuoteSeries>,
+}
+
+impl CachedProvider {
+    pub fn new(
+        inner: Arc<dyn MarketDataProvider>,
+        ttls: CacheTtls,
+        stats: Arc<CacheStats>,
+    ) -> CachedProvider {
+        CachedProvider {
+            inner,
+            stats,
+            names: TtlCache::new(ttls.names),
+            instruments: TtlCache::new(ttls.names),
+            quotes: TtlCache::new(ttls.quotes),
+            series: TtlCache::new(ttls.series),
+        }
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for CachedProvider {
+    async fn search(&self, query: &str) -> Result<Vec<SearchedTicker>, ProviderError> {
+        let inner = self.inner.clone();
+        let query = query.to_string();
+        self.names
+            .get_or_fetch(query.to_lowercase(), &self.stats.names, move || {
+                async move { inner.search(&query).await }.boxed()
+            })
+            .await
+    }
+
+    async fn latest_quote(&self, symbol: &str) -> Result<Quote, ProviderError> {
+        let inner = self.inner.clone();
+        let symbol = symbol.to_string();
+        self.quotes
+            .get_or_fetch(symbol.to_uppercase(), &self.stats.quotes, move || {
+                async move { inner.latest_quote(&symbol).await }.boxed()
+            })
+            .await
+    }
+
+    async fn instrument(&self, symbol: &str) -> Result<InstrumentInfo, ProviderError> {
+        let inner = self.inner.clone();
+        let symbol = symbol.to_string();
+        self.instruments
+            .get_or_fetch(symbol.to_uppercase(), &self.stats.names, move || {
+                async move { inner.instrument(&symbol).await }.boxed()
+            })
+            .await
+    }
+
+    async fn quote_range(
+        &self,
+        symbol: &str,
+        interval: &str,
+        range: &str,
+    ) -> Result<QuoteSeries, ProviderError> {
+        let inner = self.inner.clone();
+        let key = format!("range|{}|{}|{}", symbol.to_uppercase(), interval, range);
+        let (symbol, interval, range) =
+            (symbol.to_string(), interval.to_string(), range.to_string());
+        self.series
+            .get_or_fetch(key, &self.stats.series, move || {
+                async move { inner.quote_range(&symbol, &interval, &range).await }.boxed()
+            })
+            .await
+    }
+
+    async fn quote_history(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<QuoteSeries, ProviderError> {
+        let inner = self.inner.clone();
+        let key = format!(
+            "history|{}|{}|{}|{}",
+            symbol.to_uppercase(),
+            interval,
+            start.timestamp(),
+            end.timestamp()
+        );
+        let (symbol, interval) = (symbol.to_string(), interval.to_string());
+        self.series
+            .get_or_fetch(key, &self.stats.series, move || {
+                async move { inner.quote_history(&symbol, &interval, start, end).await }.boxed()
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheStats, CacheTtls, CachedProvider, KindStatsView};
+    use crate::infrastructure::market_data::{
+        InstrumentInfo, MarketDataProvider, MockProvider, ProviderError, Quote, QuoteSeries,
+        SearchedTicker,
+    };
+    use async_trait::async_trait;
+    use chrono::{DateTime, Utc};
+    use futures::FutureExt;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    /// Stays pending for one poll so concurrent callers overlap.
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    struct CountingProvider {
+        inner: MockProvider,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl MarketDataProvider for CountingProvider {
+        async fn search(&self, query: &str) -> Result<Vec<SearchedTicker>, ProviderError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            YieldOnce(false).await;
+            self.inner.search(query).await
+        }
+
+        async fn latest_quote(&self, symbol: &str) -> Result<Quote, ProviderError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            YieldOnce(false).await;
+            self.inner.latest_quote(symbol).await
+        }
+
+        async fn instrument(&self, symbol: &str) -> Result<InstrumentInfo, ProviderError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.inner.instrument(symbol).await
+        }
+
+        async fn quote_range(
+            &self,
+            symbol: &str,
+            interval: &str,
+            range: &str,
+        ) -> Result<QuoteSeries, ProviderError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.inner.quote_range(symbol, interval, range).await
+        }
+
+        async fn quote_history(
+            &self,
+            symbol: &str,
+            interval: &str,
+            start: DateTime<Utc>,
+            end: DateTime<Utc>,
+        ) -> Result<QuoteSeries, ProviderError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.inner.quote_history(symbol, interval, start, end).await
+        }
+    }
+
+    fn cached(ttl: Duration) -> (Arc<CountingProvider>, CachedProvider, Arc<CacheStats>) {
+        let counting = Arc::new(CountingProvider {
+            inner: MockProvider::with_sample_data(),
+            calls: AtomicUsize::new(0),
+        });
+        let stats = Arc::new(CacheStats::default());
+        let provider = CachedProvider::new(
+            counting.clone(),
+            CacheTtls {
+                names: ttl,
+                quotes: ttl,
+                series: ttl,
+            },
+            stats.clone(),
+        );
+        (counting, provider, stats)
+    }
+
+    #[test]
+    fn test_cache_hit() {
+        let (counting, provider, stats) = cached(Duration::from_secs(60));
+
+        let first = futures::executor::block_on(provider.latest_quote("AAPL")).unwrap();
+        let second = futures::executor::block_on(provider.latest_quote("aapl")).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            stats.quotes.view(),
+            KindStatsView {
+                hits: 1,
+                misses: 1,
+                coalesced: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_cache_expired() {
+        let (counting, provider, stats) = cached(Duration::from_secs(0));
+
+        futures::executor::block_on(provider.quote_range("MSFT", "1d", "6mo")).unwrap();
+        futures::executor::block_on(provider.quote_range("MSFT", "1d", "6mo")).unwrap();
+
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(stats.series.view().misses, 2);
+    }
+
+    #[test]
+    fn test_cache_coalesces_concurrent_lookups() {
+        let (counting, provider, stats) = cached(Duration::from_secs(60));
+
+        let (first, second) = futures::executor::block_on(async {
+            futures::join!(provider.search("GS"), provider.search("GS"))
+        });
+
+        assert_eq!(first.unwrap(), second.unwrap());
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(stats.names.view().coalesced, 1);
+    }
+
+    #[test]
+    fn test_cache_does_not_keep_errors() {
+        let (counting, provider, _) = cached(Duration::from_secs(60));
+
+        assert!(futures::executor::block_on(provider.latest_quote("IBMZ")).is_err());
+        assert!(futures::executor::block_on(provider.latest_quote("IBMZ")).is_err());
+
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_cache_recovers_when_the_first_caller_is_dropped() {
+        let (counting, provider, stats) = cached(Duration::from_secs(60));
+
+        // Polled once, then dropped, as actix does when the client goes away.
+        assert!(provider.latest_quote("AAPL").now_or_never().is_none());
+
+        let joined = futures::executor::block_on(provider.latest_quote("AAPL")).unwrap();
+        let cached = futures::executor::block_on(provider.latest_quote("AAPL")).unwrap();
+
+        assert_eq!(joined, cached);
+        assert_eq!(counting.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            stats.quotes.view(),
+            KindStatsView {
+                hits: 1,
+                misses: 1,
+                coalesced: 1
+            }
+        );
+    }
+}
@@ -1,86 +1,1087 @@
-use crate::infrastructure::setup;
+use crate::infrastructure::history::{HistoryQuery, HistoryView};
+use crate::infrastructure::keys::Jwks;
+use crate::infrastructure::market_data::SearchedTicker;
+use crate::infrastructure::middleware::{LoggedGuard, ScopedGuard};
+use crate::infrastructure::openapi::{self, doc, Doc};
+use crate::infrastructure::pagination::PageQuery;
+use crate::infrastructure::price_store::SyncReport;
+use crate::infrastructure::quote_cache::CacheStatsView;
+use crate::infrastructure::setup::{
+    self, CreatedApiKey, DeletedPortfolio, ForgotPassword, IdAndValue, IdOrSymbol,
+    LoginActivityQuery, LoginOptions, NewApiKey, PasswordChange, PortfolioChanges,
+    PortfolioSummary, PortfolioTickerView, PositionView, QuoteQuery, RecoveryCodes, RefreshRequest,
+    ResetPassword, SearchQuery, SingleValue, TickerQuoteView, TickerSymbol, TokenPair, TotpCode,
+    TotpEnrollment, TwoFactorLogin, VerifyEmailQuery,
+};
+use crate::infrastructure::views::{
+    ApiKeyView, LoginEventView, PortfolioView, TickerView, TransactionView, UserView,
+};
+use crate::models::api_key::ScopeArea;
+use crate::models::authentication::AuthUser;
+use crate::models::portfolio::NewPortfolio;
+use crate::models::ticker::NewTicker;
+use crate::models::transaction::NewTransaction;
+use crate::models::user::NewUser;
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::DefaultHeaders;
 use actix_web::web::{self};
+use actix_web::{FromRequest, Handler, Resource, Responder};
+use utoipa_swagger_ui::SwaggerUi;
 
-pub fn setup_routes(cfg: &mut web::ServiceConfig) {
+pub const API_PREFIX: &str = "/api/v1";
+
+/// Who may call a route.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Public,
+    /// Signed-in users; API keys are refused.
+    Logged,
+    /// Signed-in users and API keys holding a scope of the area.
+    Scoped(ScopeArea),
+}
+
+/// One method on one path, as it appears in the OpenAPI document.
+pub struct Endpoint {
+    pub method: Method,
+    pub path: String,
+    pub access: Access,
+    pub tag: &'static str,
+    pub deprecated: bool,
+    pub doc: Doc,
+}
+
+/// Registers routes and remembers each one, so the OpenAPI document is built
+/// from the same table the server answers from. Without a `ServiceConfig` it
+/// only lists them.
+pub struct Routes<'a> {
+    cfg: Option<&'a mut web::ServiceConfig>,
+    prefix: &'static str,
+    deprecated: bool,
+    tag: &'static str,
+    endpoints: Vec<Endpoint>,
+}
+
+impl<'a> Routes<'a> {
+    fn serving(cfg: &'a mut web::ServiceConfig) -> Routes<'a> {
+        Routes {
+            cfg: Some(cfg),
+            prefix: "",
+            deprecated: false,
+            tag: "",
+            endpoints: Vec::new(),
+        }
+    }
+
+    fn listing(prefix: &'static str, deprecated: bool) -> Routes<'static> {
+        Routes {
+            cfg: None,
+            prefix,
+            deprecated,
+            tag: "",
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// Groups the routes that follow under `tag` in the document.
+    pub fn tag(&mut self, tag: &'static str) {
+        self.tag = tag;
+    }
+
+    pub fn resource(&mut self, path: &'static str, access: Access) -> ResourceRoutes<'_, 'a> {
+        ResourceRoutes {
+            resource: web::resource(path),
+            routes: self,
+            path,
+            access,
+        }
+    }
+}
+
+/// The routes of one path. Nothing is served until `register` is called.
+#[must_use]
+pub struct ResourceRoutes<'r, 'a> {
+    routes: &'r mut Routes<'a>,
+    path: &'static str,
+    access: Access,
+    resource: Resource,
+}
+
+impl ResourceRoutes<'_, '_> {
+    pub fn route<F, Args>(self, method: Method, handler: F, doc: Doc) -> Self
+    where
+        F: Handler<Args>,
+        Args: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        let access = self.access;
+        self.route_as(access, method, handler, doc)
+    }
+
+    /// A route guarded differently from the rest of its path.
+    pub fn route_as<F, Args>(mut self, access: Access, method: Method, handler: F, doc: Doc) -> Self
+    where
+        F: Handler<Args>,
+        Args: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        let route = web::method(method.clone()).to(handler);
+        let route = match access {
+            Access::Public => route,
+            Access::Logged => route.wrap(LoggedGuard),
+            Access::Scoped(area) => route.wrap(ScopedGuard(area)),
+        };
+        self.resource = self.resource.route(route);
+
+        let routes = &mut *self.routes;
+        routes.endpoints.push(Endpoint {
+            method,
+            path: format!("{}{}", routes.prefix, self.path),
+            access,
+            tag: routes.tag,
+            deprecated: routes.deprecated,
+            doc,
+        });
+        self
+    }
+
+    pub fn register(self) {
+        if let Some(cfg) = self.routes.cfg.as_deref_mut() {
+            cfg.service(self.resource);
+        }
+    }
+}
+
+/// Every route of the application: the versioned API, the JWKS document, the
+/// API documentation and the original routes, which answer with a
+/// `Deprecation` header.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope(API_PREFIX).configure(|cfg| setup_v1_routes(&mut Routes::serving(cfg))));
+    setup_well_known_routes(&mut Routes::serving(cfg));
+    cfg.service(SwaggerUi::new("/docs/{_:.*}").url("/openapi.json", openapi::api_doc()));
+    // An empty prefix, not "/": that would put every route under "//".
+    cfg.service(
+        web::scope("")
+            .wrap(
+                DefaultHeaders::new()
+                    .add(("Deprecation", "true"))
+                    .add(("Link", "</api/v1>; rel=\"successor-version\"")),
+            )
+            .configure(|cfg| setup_routes(&mut Routes::serving(cfg))),
+    );
+}
+
+/// Every documented route, with the path it is served under.
+pub fn endpoints() -> Vec<Endpoint> {
+    let mut v1 = Routes::listing(API_PREFIX, false);
+    setup_v1_routes(&mut v1);
+    let mut well_known = Routes::listing("", false);
+    setup_well_known_routes(&mut well_known);
+    let mut legacy = Routes::listing("", true);
+    setup_routes(&mut legacy);
+
+    v1.endpoints
+        .into_iter()
+        .chain(well_known.endpoints)
+        .chain(legacy.endpoints)
+        .collect()
+}
+
+pub fn setup_well_known_routes(routes: &mut Routes) {
+    routes.tag("Keys");
+    routes
+        .resource("/.well-known/jwks.json", Access::Public)
+        .route(
+            Method::GET,
+            setup::jwks,
+            doc("Public keys for verifying access tokens").returns::<Jwks>(),
+        )
+        .register();
+}
+
+/// Resource-style routes: IDs in paths, reads take query parameters only.
+pub fn setup_v1_routes(routes: &mut Routes) {
+    // Accounts and sessions
+    routes.tag("Accounts");
+    routes
+        .resource("/accounts", Access::Public)
+        .route(
+            Method::POST,
+            setup::register,
+            doc("Create an account")
+                .body::<NewUser>()
+                .returns::<UserView>()
+                .status(StatusCode::CREATED),
+        )
+        .register();
+    routes
+        .resource("/sessions", Access::Public)
+        .route(
+            Method::POST,
+            setup::login,
+            doc("Sign in; answers 202 with a challenge when two factors are enabled")
+                .body::<AuthUser>()
+                .query::<LoginOptions>()
+                .returns::<UserView>(),
+        )
+        .route_as(
+            Access::Logged,
+            Method::DELETE,
+            setup::logout,
+            doc("Sign out and revoke the refresh token")
+                .optional_body::<RefreshRequest>()
+                .returns_text(),
+        )
+        .register();
+    routes
+        .resource("/sessions/2fa", Access::Public)
+        .route(
+            Method::POST,
+            setup::login_two_factor,
+            doc("Finish a two-factor sign-in")
+                .body::<TwoFactorLogin>()
+                .query::<LoginOptions>()
+                .returns::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/sessions/refresh", Access::Public)
+        .route(
+            Method::POST,
+            setup::refresh_token,
+            doc("Exchange a refresh token for new tokens")
+                .body::<RefreshRequest>()
+                .returns::<TokenPair>(),
+        )
+        .register();
+    routes
+        .resource("/verify-email", Access::Public)
+        .route(
+            Method::GET,
+            setup::verify_email_address,
+            doc("Verify an email address")
+                .query::<VerifyEmailQuery>()
+                .returns::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/verify-email/resend", Access::Logged)
+        .route(
+            Method::POST,
+            setup::resend_verification_email,
+            doc("Send the verification email again")
+                .returns_text()
+                .status(StatusCode::ACCEPTED),
+        )
+        .register();
+    routes
+        .resource("/password/forgot", Access::Public)
+        .route(
+            Method::POST,
+            setup::forgot_password,
+            doc("Email a password reset link")
+                .body::<ForgotPassword>()
+                .returns_text()
+                .status(StatusCode::ACCEPTED),
+        )
+        .register();
+    routes
+        .resource("/password/reset", Access::Public)
+        .route(
+            Method::POST,
+            setup::reset_password,
+            doc("Set a new password from a reset link")
+                .body::<ResetPassword>()
+                .returns_text(),
+        )
+        .register();
+
+    // Users (admin only)
+    routes.tag("Users");
+    routes
+        .resource("/users", Access::Logged)
+        .route(
+            Method::GET,
+            setup::list_users,
+            doc("List users")
+                .query::<PageQuery>()
+                .returns_page::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/users/{id}", Access::Logged)
+        .route(
+            Method::GET,
+            setup::get_user,
+            doc("Get a user").returns::<UserView>(),
+        )
+        .route(
+            Method::DELETE,
+            setup::delete_user,
+            doc("Delete a user").returns_text(),
+        )
+        .register();
+    routes
+        .resource("/users/{id}/email", Access::Logged)
+        .route(
+            Method::PUT,
+            setup::set_user_email,
+            doc("Change a user's email address")
+                .body::<SingleValue>()
+                .returns::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/users/{id}/password", Access::Logged)
+        .route(
+            Method::PUT,
+            setup::set_user_password,
+            doc("Change a user's password")
+                .body::<SingleValue>()
+                .returns_text(),
+        )
+        .register();
+    routes
+        .resource("/users/{id}/role", Access::Logged)
+        .route(
+            Method::PUT,
+            setup::set_user_role,
+            doc("Change a user's role")
+                .body::<SingleValue>()
+                .returns::<UserView>(),
+        )
+        .register();
+
+    setup_me_routes(routes);
+
+    // Portfolios
+    routes.tag("Portfolios");
+    routes
+        .resource("/portfolios", Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::GET,
+            setup::list_portfolios,
+            doc("List your portfolios")
+                .query::<PageQuery>()
+                .returns_page::<PortfolioView>(),
+        )
+        .route(
+            Method::POST,
+            setup::create_portfolio,
+            doc("Create a portfolio")
+                .body::<NewPortfolio>()
+                .returns::<PortfolioView>()
+                .status(StatusCode::CREATED),
+        )
+        .register();
+    routes
+        .resource("/portfolios/{id}", Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::GET,
+            setup::get_portfolio,
+            doc("Get a portfolio").returns::<PortfolioView>(),
+        )
+        .route(
+            Method::PATCH,
+            setup::update_portfolio,
+            doc("Rename a portfolio or change its cost basis method")
+                .body::<PortfolioChanges>()
+                .returns::<PortfolioView>(),
+        )
+        .route(
+            Method::DELETE,
+            setup::delete_portfolio,
+            doc("Delete a portfolio and its tickers").returns::<DeletedPortfolio>(),
+        )
+        .register();
+    routes
+        .resource(
+            "/portfolios/{id}/summary",
+            Access::Scoped(ScopeArea::Portfolios),
+        )
+        .route(
+            Method::GET,
+            setup::portfolio_summary,
+            doc("Cost basis, market value and profit of a portfolio").returns::<PortfolioSummary>(),
+        )
+        .register();
+    routes
+        .resource(
+            "/portfolios/{id}/positions",
+            Access::Scoped(ScopeArea::Portfolios),
+        )
+        .route(
+            Method::GET,
+            setup::get_positions,
+            doc("Shares held per ticker").returns_list::<PositionView>(),
+        )
+        .register();
+    setup_transaction_routes(
+        routes,
+        "/portfolios/{id}/transactions",
+        "/portfolios/{id}/transactions/{transaction_id}",
+    );
+
+    // Tickers held in a portfolio
+    routes.tag("Tickers");
+    routes
+        .resource(
+            "/portfolios/{id}/tickers",
+            Access::Scoped(ScopeArea::Tickers),
+        )
+        .route(
+            Method::GET,
+            setup::list_portfolio_tickers,
+            doc("Tickers of a portfolio with prices and profit")
+                .query::<PageQuery>()
+                .returns_page::<PortfolioTickerView>(),
+        )
+        .route(
+            Method::POST,
+            setup::add_portfolio_ticker,
+            doc("Add a ticker to a portfolio")
+                .body::<TickerSymbol>()
+                .returns::<TickerView>()
+                .status(StatusCode::CREATED),
+        )
+        .register();
+    routes
+        .resource(
+            "/portfolios/{id}/tickers/{ticker_id}",
+            Access::Scoped(ScopeArea::Tickers),
+        )
+        .route(
+            Method::DELETE,
+            setup::delete_portfolio_ticker,
+            doc("Remove a ticker and its transactions").returns::<TickerView>(),
+        )
+        .register();
+
+    // Market data
+    routes.tag("Market data");
+    routes
+        .resource("/tickers/search", Access::Public)
+        .route(
+            Method::GET,
+            setup::search_tickers,
+            doc("Search for symbols")
+                .query::<SearchQuery>()
+                .returns_list::<SearchedTicker>(),
+        )
+        .register();
+    routes
+        .resource(
+            "/tickers/{symbol}/quote",
+            Access::Scoped(ScopeArea::Tickers),
+        )
+        .route(
+            Method::GET,
+            setup::get_ticker_quote,
+            doc("Latest quote and dividend of a symbol")
+                .query::<QuoteQuery>()
+                .returns::<TickerQuoteView>(),
+        )
+        .register();
+    routes
+        .resource(
+            "/tickers/{symbol}/history",
+            Access::Scoped(ScopeArea::Tickers),
+        )
+        .route(
+            Method::GET,
+            setup::get_ticker_history,
+            doc("Price history of a symbol")
+                .query::<HistoryQuery>()
+                .returns::<HistoryView>(),
+        )
+        .register();
+
+    setup_admin_routes(routes);
+}
+
+/// The caller's own account; the same under both route sets.
+fn setup_me_routes(routes: &mut Routes) {
+    routes.tag("Own account");
+    routes
+        .resource("/me", Access::Logged)
+        .route(
+            Method::GET,
+            setup::get_me,
+            doc("Get your account").returns::<UserView>(),
+        )
+        .route(
+            Method::DELETE,
+            setup::delete_me,
+            doc("Delete your account").returns_text(),
+        )
+        .register();
+    routes
+        .resource("/me/email", Access::Logged)
+        .route(
+            Method::PUT,
+            setup::update_my_email,
+            doc("Change your email address")
+                .body::<SingleValue>()
+                .returns::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/me/password", Access::Logged)
+        .route(
+            Method::PUT,
+            setup::update_my_password,
+            doc("Change your password; signs out every session")
+                .body::<PasswordChange>()
+                .returns_text(),
+        )
+        .register();
+    routes
+        .resource("/me/2fa/enroll", Access::Logged)
+        .route(
+            Method::POST,
+            setup::enroll_totp,
+            doc("Start two-factor enrollment").returns::<TotpEnrollment>(),
+        )
+        .register();
+    routes
+        .resource("/me/2fa/confirm", Access::Logged)
+        .route(
+            Method::POST,
+            setup::confirm_totp,
+            doc("Confirm two-factor enrollment with a code")
+                .body::<TotpCode>()
+                .returns::<RecoveryCodes>(),
+        )
+        .register();
+    routes
+        .resource("/me/2fa/recovery-codes", Access::Logged)
+        .route(
+            Method::POST,
+            setup::regenerate_recovery_codes,
+            doc("Replace your recovery codes")
+                .body::<TotpCode>()
+                .returns::<RecoveryCodes>(),
+        )
+        .register();
+    routes
+        .resource("/me/2fa/disable", Access::Logged)
+        .route(
+            Method::POST,
+            setup::disable_totp,
+            doc("Turn off two-factor authentication")
+                .body::<TotpCode>()
+                .returns::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/me/login-activity", Access::Logged)
+        .route(
+            Method::GET,
+            setup::get_login_activity,
+            doc("Your recent sign-in attempts")
+                .query::<LoginActivityQuery>()
+                .returns_list::<LoginEventView>(),
+        )
+        .register();
+    routes
+        .resource("/me/api-keys", Access::Logged)
+        .route(
+            Method::GET,
+            setup::get_api_keys,
+            doc("List your API keys").returns_list::<ApiKeyView>(),
+        )
+        .route(
+            Method::POST,
+            setup::create_api_key,
+            doc("Create an API key; the key is shown only once")
+                .body::<NewApiKey>()
+                .returns::<CreatedApiKey>()
+                .status(StatusCode::CREATED),
+        )
+        .register();
+    routes
+        .resource("/me/api-keys/{id}", Access::Logged)
+        .route(
+            Method::DELETE,
+            setup::revoke_api_key,
+            doc("Revoke an API key").returns::<ApiKeyView>(),
+        )
+        .register();
+}
+
+fn setup_transaction_routes(
+    routes: &mut Routes,
+    transactions: &'static str,
+    transaction: &'static str,
+) {
+    routes.tag("Transactions");
+    routes
+        .resource(transactions, Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::GET,
+            setup::get_transactions,
+            doc("List the transactions of a portfolio").returns_list::<TransactionView>(),
+        )
+        .route(
+            Method::POST,
+            setup::create_transaction,
+            doc("Record a transaction")
+                .body::<NewTransaction>()
+                .returns::<TransactionView>()
+                .status(StatusCode::CREATED),
+        )
+        .register();
+    routes
+        .resource(transaction, Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::GET,
+            setup::get_transaction_by_id,
+            doc("Get a transaction").returns::<TransactionView>(),
+        )
+        .route(
+            Method::PUT,
+            setup::update_transaction,
+            doc("Replace a transaction")
+                .body::<NewTransaction>()
+                .returns::<TransactionView>(),
+        )
+        .route(
+            Method::DELETE,
+            setup::delete_transaction,
+            doc("Delete a transaction").returns::<TransactionView>(),
+        )
+        .register();
+}
+
+fn setup_admin_routes(routes: &mut Routes) {
+    routes.tag("Administration");
+    routes
+        .resource("/prices/sync", Access::Logged)
+        .route(
+            Method::POST,
+            setup::sync_prices,
+            doc("Bring stored price history up to date (admin only)").returns::<SyncReport>(),
+        )
+        .register();
+    routes
+        .resource("/metrics/cache", Access::Logged)
+        .route(
+            Method::GET,
+            setup::cache_stats,
+            doc("Market data cache counters (admin only)").returns::<CacheStatsView>(),
+        )
+        .register();
+}
+
+/// The original routes, kept for existing clients until they move to `/api/v1`.
+pub fn setup_routes(routes: &mut Routes) {
     // Register
-    cfg.service(web::resource("/register").route(web::post().to(setup::register)));
+    routes.tag("Accounts");
+    routes
+        .resource("/register", Access::Public)
+        .route(
+            Method::POST,
+            setup::register,
+            doc("Create an account")
+                .body::<NewUser>()
+                .returns::<UserView>()
+                .status(StatusCode::CREATED),
+        )
+        .register();
     // Login
-    cfg.service(web::resource("/login").route(web::get().to(setup::login)));
+    routes
+        .resource("/login", Access::Public)
+        .route(
+            Method::GET,
+            setup::login,
+            doc("Sign in; answers 202 with a challenge when two factors are enabled")
+                .body::<AuthUser>()
+                .query::<LoginOptions>()
+                .returns::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/login/2fa", Access::Public)
+        .route(
+            Method::POST,
+            setup::login_two_factor,
+            doc("Finish a two-factor sign-in")
+                .body::<TwoFactorLogin>()
+                .query::<LoginOptions>()
+                .returns::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/verify-email", Access::Public)
+        .route(
+            Method::GET,
+            setup::verify_email_address,
+            doc("Verify an email address")
+                .query::<VerifyEmailQuery>()
+                .returns::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/verify-email/resend", Access::Logged)
+        .route(
+            Method::POST,
+            setup::resend_verification_email,
+            doc("Send the verification email again")
+                .returns_text()
+                .status(StatusCode::ACCEPTED),
+        )
+        .register();
+    routes
+        .resource("/password/forgot", Access::Public)
+        .route(
+            Method::POST,
+            setup::forgot_password,
+            doc("Email a password reset link")
+                .body::<ForgotPassword>()
+                .returns_text()
+                .status(StatusCode::ACCEPTED),
+        )
+        .register();
+    routes
+        .resource("/password/reset", Access::Public)
+        .route(
+            Method::POST,
+            setup::reset_password,
+            doc("Set a new password from a reset link")
+                .body::<ResetPassword>()
+                .returns_text(),
+        )
+        .register();
+    routes
+        .resource("/token/refresh", Access::Public)
+        .route(
+            Method::POST,
+            setup::refresh_token,
+            doc("Exchange a refresh token for new tokens")
+                .body::<RefreshRequest>()
+                .returns::<TokenPair>(),
+        )
+        .register();
+    routes
+        .resource("/logout", Access::Logged)
+        .route(
+            Method::POST,
+            setup::logout,
+            doc("Sign out and revoke the refresh token")
+                .optional_body::<RefreshRequest>()
+                .returns_text(),
+        )
+        .register();
 
-    // User
+    // User (admin only)
+    routes.tag("Users");
     //GET
-    cfg.service(web::resource("/users").route(web::get().to(setup::get_all_users)));
-    cfg.service(web::resource("/user").route(web::get().to(setup::get_user_by_id)));
+    routes
+        .resource("/users", Access::Logged)
+        .route(
+            Method::GET,
+            setup::get_all_users,
+            doc("First page of users").returns_list::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/user", Access::Logged)
+        .route(
+            Method::GET,
+            setup::get_user_by_id,
+            doc("Get a user").body::<IdAndValue>().returns::<UserView>(),
+        )
+        .register();
     // PUT
-    cfg.service(web::resource("/user/email").route(web::put().to(setup::update_user_email)));
-    cfg.service(web::resource("/user/password").route(web::put().to(setup::update_user_password)));
+    routes
+        .resource("/user/email", Access::Logged)
+        .route(
+            Method::PUT,
+            setup::update_user_email,
+            doc("Change a user's email address")
+                .body::<IdAndValue>()
+                .returns::<UserView>(),
+        )
+        .register();
+    routes
+        .resource("/user/password", Access::Logged)
+        .route(
+            Method::PUT,
+            setup::update_user_password,
+            doc("Change a user's password")
+                .body::<IdAndValue>()
+                .returns_text(),
+        )
+        .register();
+    routes
+        .resource("/user/role", Access::Logged)
+        .route(
+            Method::PUT,
+            setup::update_user_role,
+            doc("Change a user's role")
+                .body::<IdAndValue>()
+                .returns::<UserView>(),
+        )
+        .register();
     // DELETE
-    cfg.service(web::resource("/user/{id}").route(web::put().to(setup::delete_user)));
+    routes
+        .resource("/user/{id}", Access::Logged)
+        .route(
+            Method::PUT,
+            setup::delete_user,
+            doc("Delete a user").returns_text(),
+        )
+        .register();
+
+    setup_me_routes(routes);
 
     //Portfolio
+    routes.tag("Portfolios");
     //GET
-    cfg.service(
-        web::resource("/portfolios")
-            .route(web::get().to(setup::get_portfolios))
-            .wrap(crate::infrastructure::middleware::LoggedGuard),
-    );
+    routes
+        .resource("/portfolios", Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::GET,
+            setup::get_portfolios,
+            doc("List your portfolios").returns_list::<PortfolioView>(),
+        )
+        .register();
 
     //ovo nije potrebno
-    cfg.service(
-        web::resource("/portfolio")
-            .route(web::get().to(setup::get_portfolio_by_id))
-            .wrap(crate::infrastructure::middleware::LoggedGuard),
-    );
+    routes
+        .resource("/portfolio", Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::GET,
+            setup::get_portfolio_by_id,
+            doc("Get a portfolio")
+                .body::<IdAndValue>()
+                .returns::<PortfolioView>(),
+        )
+        .register();
     //POST
-    cfg.service(
-        web::resource("portfolio/new")
-            .route(web::post().to(setup::create_portfolio))
-            .wrap(crate::infrastructure::middleware::LoggedGuard),
-    );
+    routes
+        .resource("/portfolio/new", Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::POST,
+            setup::create_portfolio,
+            doc("Create a portfolio")
+                .body::<NewPortfolio>()
+                .returns::<PortfolioView>()
+                .status(StatusCode::CREATED),
+        )
+        .register();
     //PUT
-    cfg.service(
-        web::resource("portfolio/name")
-            .route(web::put().to(setup::update_portfolio_name))
-            .wrap(crate::infrastructure::middleware::LoggedGuard),
-    );
+    routes
+        .resource("/portfolio/name", Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::PUT,
+            setup::update_portfolio_name,
+            doc("Rename a portfolio")
+                .body::<IdAndValue>()
+                .returns::<PortfolioView>(),
+        )
+        .register();
+    routes
+        .resource("/portfolio/method", Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::PUT,
+            setup::update_portfolio_cost_basis_method,
+            doc("Change the cost basis method of a portfolio")
+                .body::<IdAndValue>()
+                .returns::<PortfolioView>(),
+        )
+        .register();
     //DELETE
-    cfg.service(
-        web::resource("portfolio/{id}")
-            .route(web::put().to(setup::delete_portfolio))
-            .wrap(crate::infrastructure::middleware::LoggedGuard),
+    routes
+        .resource("/portfolio/{id}", Access::Scoped(ScopeArea::Portfolios))
+        .route(
+            Method::PUT,
+            setup::delete_portfolio,
+            doc("Delete a portfolio and its tickers").returns::<DeletedPortfolio>(),
+        )
+        .register();
+
+    //Transactions
+    setup_transaction_routes(
+        routes,
+        "/portfolio/{id}/transactions",
+        "/portfolio/{id}/transactions/{transaction_id}",
     );
+    routes.tag("Portfolios");
+    routes
+        .resource(
+            "/portfolio/{id}/summary",
+            Access::Scoped(ScopeArea::Portfolios),
+        )
+        .route(
+            Method::GET,
+            setup::portfolio_summary,
+            doc("Cost basis, market value and profit of a portfolio").returns::<PortfolioSummary>(),
+        )
+        .register();
+    routes
+        .resource(
+            "/portfolio/{id}/positions",
+            Access::Scoped(ScopeArea::Portfolios),
+        )
+        .route(
+            Method::GET,
+            setup::get_positions,
+            doc("Shares held per ticker").returns_list::<PositionView>(),
+        )
+        .register();
 
     //Ticker
+    routes.tag("Tickers");
     //GET
-    cfg.service(
-        web::resource("/ticker/info")
-            .route(web::get().to(setup::get_latest_ticker_info))
-            .wrap(crate::infrastructure::middleware::LoggedGuard),
-    );
+    routes
+        .resource("/ticker/info", Access::Scoped(ScopeArea::Tickers))
+        .route(
+            Method::GET,
+            setup::get_latest_ticker_info,
+            doc("Latest quote and dividend of a ticker")
+                .body::<IdOrSymbol>()
+                .returns::<TickerQuoteView>(),
+        )
+        .register();
     //POST
-    cfg.service(
-        web::resource("/ticker/new")
-            .route(web::post().to(setup::add_ticker))
-            .wrap(crate::infrastructure::middleware::LoggedGuard),
-    );
+    routes
+        .resource("/ticker/new", Access::Scoped(ScopeArea::Tickers))
+        .route(
+            Method::POST,
+            setup::add_ticker,
+            doc("Add a ticker to a portfolio")
+                .body::<NewTicker>()
+                .returns::<TickerView>()
+                .status(StatusCode::CREATED),
+        )
+        .register();
     //PUT
     //DELETE
-    cfg.service(
-        web::resource("/ticker/{ticker}")
-            .route(web::put().to(setup::delete_ticker))
-            .wrap(crate::infrastructure::middleware::LoggedGuard),
-    );
+    routes
+        .resource("/ticker/{ticker}", Access::Scoped(ScopeArea::Tickers))
+        .route(
+            Method::PUT,
+            setup::delete_ticker,
+            doc("Remove a ticker and its transactions").returns::<TickerView>(),
+        )
+        .register();
 
-    //cfg.service(web::resource("/tickers/{portfolio_id}").route(web::get().to(setup::tickers_from_portfolio)).wrap(crate::infrastructure::middleware::LoggedGuard));
+    routes
+        .resource(
+            "/tickers/{portfolio_id}",
+            Access::Scoped(ScopeArea::Tickers),
+        )
+        .route(
+            Method::GET,
+            setup::tickers_from_portfolio,
+            doc("Tickers of a portfolio with prices and profit")
+                .returns_list::<PortfolioTickerView>(),
+        )
+        .register();
+    routes.tag("Market data");
+    routes
+        .resource("/ticker/search/{name}", Access::Public)
+        .route(
+            Method::GET,
+            setup::ticker_search,
+            doc("Best match for a symbol search").returns::<SearchedTicker>(),
+        )
+        .register();
+    routes
+        .resource("/ticker/search/{name}/extended", Access::Public)
+        .route(
+            Method::GET,
+            setup::ticker_extensive_search,
+            doc("Every match for a symbol search").returns_list::<SearchedTicker>(),
+        )
+        .register();
+    routes
+        .resource(
+            "/ticker/{symbol}/history",
+            Access::Scoped(ScopeArea::Tickers),
+        )
+        .route(
+            Method::GET,
+            setup::get_ticker_history,
+            doc("Price history of a symbol")
+                .query::<HistoryQuery>()
+                .returns::<HistoryView>(),
+        )
+        .register();
 
-    cfg.service(
-        web::resource("/tickers/{portfolio_id}")
-            .route(web::get().to(setup::tickers_from_portfolio))
-            .wrap(crate::infrastructure::middleware::LoggedGuard),
-    );
-    cfg.service(web::resource("/ticker/search/{name}").route(web::get().to(setup::ticker_search)));
-    cfg.service(
-        web::resource("/ticker/search/{name}/extended")
-            .route(web::get().to(setup::ticker_extensive_search)),
-    );
+    setup_admin_routes(routes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::configure;
+    use crate::infrastructure::session::SessionSettings;
+    use actix_web::cookie::{Key, SameSite};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use serde_json::Value;
+
+    fn sessions() -> SessionSettings {
+        SessionSettings {
+            key: Key::generate(),
+            secure: false,
+            same_site: SameSite::Strict,
+            lifetime: chrono::Duration::hours(1),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_only_legacy_routes_are_deprecated() {
+        let sessions = sessions();
+        let app = test::init_service(
+            App::new()
+                .wrap(sessions.identity_middleware())
+                .wrap(sessions.session_middleware())
+                .configure(configure),
+        )
+        .await;
+
+        let legacy = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/portfolios").to_request(),
+        )
+        .await;
+        assert_eq!(legacy.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(legacy.headers().get("Deprecation").unwrap(), "true");
+
+        let current = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/api/v1/portfolios")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(current.status(), StatusCode::UNAUTHORIZED);
+        assert!(current.headers().get("Deprecation").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_openapi_document_is_served() {
+        let sessions = sessions();
+        let app = test::init_service(
+            App::new()
+                .wrap(sessions.identity_middleware())
+                .wrap(sessions.session_middleware())
+                .configure(configure),
+        )
+        .await;
+
+        let spec: Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::get().uri("/openapi.json").to_request(),
+        )
+        .await;
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(spec["paths"]["/api/v1/portfolios/{id}"]["patch"].is_object());
+
+        let ui =
+            test::call_service(&app, test::TestRequest::get().uri("/docs/").to_request()).await;
+        assert_eq!(ui.status(), StatusCode::OK);
+        assert!(ui.headers().get("Deprecation").is_none());
+    }
 }
@@ -0,0 +1,174 @@
+use crate::infrastructure::config::{SameSitePolicy, SessionConfig};
+use crate::infrastructure::error::ApiError;
+use crate::models::authentication::random_token;
+use crate::models::user::User;
+
+use actix_identity::{Identity, IdentityMiddleware};
+use actix_session::config::PersistentSession;
+use actix_session::storage::CookieSessionStore;
+use actix_session::{Session, SessionExt, SessionMiddleware};
+use actix_web::cookie::{time, Cookie, Key, SameSite};
+use actix_web::http::Method;
+use actix_web::{HttpMessage, HttpRequest};
+
+pub const SESSION_COOKIE: &str = "session";
+/// Readable by the frontend, which echoes it back in `CSRF_HEADER`.
+pub const CSRF_COOKIE: &str = "csrf-token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+const VERSION_KEY: &str = "ver";
+const CSRF_KEY: &str = "csrf";
+
+/// Cookie sessions for the browser frontend. The session cookie is encrypted
+/// with `key` and never readable from scripts.
+#[derive(Clone)]
+pub struct SessionSettings {
+    pub key: Key,
+    pub secure: bool,
+    pub same_site: SameSite,
+    pub lifetime: chrono::Duration,
+}
+
+impl SessionSettings {
+    pub fn from_config(config: &SessionConfig) -> SessionSettings {
+        let key = match &config.key {
+            Some(key) => Key::from(&key.0),
+            None => {
+                log::warn!("SESSION_KEY is not set; cookie sessions will not survive a restart");
+                Key::generate()
+            }
+        };
+
+        SessionSettings {
+            key,
+            secure: config.cookie_secure,
+            same_site: match config.same_site {
+                SameSitePolicy::Strict => SameSite::Strict,
+                SameSitePolicy::Lax => SameSite::Lax,
+            },
+            lifetime: chrono::Duration::hours(config.lifetime_hours),
+        }
+    }
+
+    pub fn session_middleware(&self) -> SessionMiddleware<CookieSessionStore> {
+        SessionMiddleware::builder(CookieSessionStore::default(), self.key.clone())
+            .cookie_name(String::from(SESSION_COOKIE))
+            .cookie_http_only(true)
+            .cookie_secure(self.secure)
+            .cookie_same_site(self.same_site)
+            .session_lifecycle(
+                PersistentSession::default()
+                    .session_ttl(time::Duration::seconds(self.lifetime.num_seconds())),
+            )
+            .build()
+    }
+
+    pub fn identity_middleware(&self) -> IdentityMiddleware {
+        IdentityMiddleware::builder()
+            .login_deadline(self.lifetime.to_std().ok())
+            .build()
+    }
+
+    fn csrf_cookie(&self, token: String) -> Cookie<'static> {
+        Cookie::build(CSRF_COOKIE, token)
+            .path("/")
+            .http_only(false)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .max_age(time::Duration::seconds(self.lifetime.num_seconds()))
+            .finish()
+    }
+}
+
+fn session_error<E: std::fmt::Display>(e: E) -> ApiError {
+    log::error!("Session error: {}", e);
+    ApiError::Internal(String::from("Could not start the session."))
+}
+
+/// Signs the user into a cookie session and returns the CSRF cookie to set.
+/// The token is kept in the session too, so a cookie planted by another site
+/// cannot stand in for it.
+pub fn start(
+    req: &HttpRequest,
+    settings: &SessionSettings,
+    user: &User,
+) -> Result<Cookie<'static>, ApiError> {
+    Identity::login(&req.extensions(), user.id.clone()).map_err(session_error)?;
+
+    let csrf = random_token();
+    let session = req.get_session();
+    session
+        .insert(VERSION_KEY, user.token_version)
+        .map_err(session_error)?;
+    session.insert(CSRF_KEY, &csrf).map_err(session_error)?;
+
+    Ok(settings.csrf_cookie(csrf))
+}
+
+/// Ends the cookie session and returns the cookie that clears the CSRF token.
+pub fn end(identity: Identity) -> Cookie<'static> {
+    identity.logout();
+
+    let mut cookie = Cookie::build(CSRF_COOKIE, "").path("/").finish();
+    cookie.make_removal();
+    cookie
+}
+
+/// The user id and token version the session was started with.
+pub fn session_user(identity: &Identity, session: &Session) -> Option<(String, i32)> {
+    let id = identity.id().ok()?;
+    let version = session.get::<i32>(VERSION_KEY).ok()??;
+
+    Some((id, version))
+}
+
+/// Safe methods need no CSRF token; anything else must echo the session's
+/// token in both the header and the cookie.
+pub fn check_csrf(
+    method: &Method,
+    session: &Session,
+    header: Option<&str>,
+    cookie: Option<&str>,
+) -> Result<(), ApiError> {
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(());
+    }
+
+    let expected = session.get::<String>(CSRF_KEY).ok().flatten();
+    if csrf_matches(expected.as_deref(), header, cookie) {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden(String::from(
+            "Missing or invalid CSRF token.",
+        )))
+    }
+}
+
+fn csrf_matches(expected: Option<&str>, header: Option<&str>, cookie: Option<&str>) -> bool {
+    match (expected, header, cookie) {
+        (Some(expected), Some(header), Some(cookie)) => {
+            ring::constant_time::verify_slices_are_equal(expected.as_bytes(), header.as_bytes())
+                .is_ok()
+                && ring::constant_time::verify_slices_are_equal(
+                    expected.as_bytes(),
+                    cookie.as_bytes(),
+                )
+                .is_ok()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::csrf_matches;
+
+    #[test]
+    fn test_csrf_double_submit() {
+        assert!(csrf_matches(Some("abc"), Some("abc"), Some("abc")));
+        assert!(!csrf_matches(Some("abc"), Some("abc"), Some("abd")));
+        assert!(!csrf_matches(Some("abc"), Some("abd"), Some("abc")));
+        assert!(!csrf_matches(Some("abc"), None, Some("abc")));
+        assert!(!csrf_matches(None, Some("abc"), Some("abc")));
+    }
+}
@@ -1,286 +1,1447 @@
 use crate::infrastructure;
-use crate::models::authentication::AuthUser;
+use crate::infrastructure::auth::{bearer_claims, AdminUser, AuthenticatedUser};
+use crate::infrastructure::catalog;
+use crate::infrastructure::error::{ApiError, Problem};
+use crate::infrastructure::history::{BarView, HistoryQuery, HistoryView};
+use crate::infrastructure::mailer;
+use crate::infrastructure::market_data::{ProviderError, SearchedTicker};
+use crate::infrastructure::pagination::{Page, PageQuery};
+use crate::infrastructure::price_store;
+use crate::infrastructure::session::{self, CSRF_HEADER};
+use crate::infrastructure::throttle::{self, ClientInfo};
+use crate::infrastructure::views::{
+    self, ApiKeyView, LoginEventView, PortfolioView, TickerView, TransactionView, UserView,
+};
+use crate::models::accounting::{self, CostBasisMethod, PositionCost};
+use crate::models::api_key::{ApiKey, Scope};
+use crate::models::authentication::{self, AuthUser};
+use crate::models::instrument::Instrument;
+use crate::models::listing::ListOptions;
+use crate::models::login_event::LoginEvent;
+use crate::models::one_time_token::{OneTimeToken, TokenPurpose};
+use crate::models::password;
 use crate::models::portfolio::NewPortfolio;
 use crate::models::portfolio::Portfolio;
+use crate::models::recovery_code::RecoveryCode;
+use crate::models::refresh_token::RefreshToken;
+use crate::models::revoked_token::RevokedToken;
+use crate::models::role::Role;
 use crate::models::ticker::NewTicker;
 use crate::models::ticker::Ticker;
+use crate::models::totp;
+use crate::models::transaction::{NewTransaction, Position, Transaction};
 use crate::models::user::NewUser;
 use crate::models::user::User;
-use actix_web::{web, HttpResponse, Responder};
+use actix_identity::Identity;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use chrono::prelude::*;
 use chrono::{DateTime, Utc};
+use diesel::Connection;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use yahoo::{Dividend, Quote, YahooError};
-use yahoo_finance_api as yahoo;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
 
-pub async fn get_all_users(data: web::Data<infrastructure::state::AppState>) -> impl Responder {
-    match crate::models::user::User::get_all(&data.get_connection()) {
-        Ok(results) => HttpResponse::Ok().json(results),
-        Err(_) => HttpResponse::NotFound().finish(),
+type HandlerResult = Result<HttpResponse, ApiError>;
+
+fn portfolio_not_found(_: diesel::result::Error) -> ApiError {
+    ApiError::NotFound(String::from("Portfolio with that ID does not exist."))
+}
+
+fn user_not_found(_: diesel::result::Error) -> ApiError {
+    ApiError::NotFound(String::from("User ID does not exist."))
+}
+
+fn transaction_not_found(_: diesel::result::Error) -> ApiError {
+    ApiError::NotFound(String::from("Transaction with that ID does not exist."))
+}
+
+fn ticker_not_found(err: diesel::result::Error) -> ApiError {
+    match err {
+        diesel::result::Error::NotFound => {
+            ApiError::NotFound(String::from("Ticker with that ID does not exist."))
+        }
+        err => err.into(),
+    }
+}
+
+fn signing_failed(err: jsonwebtoken::errors::Error) -> ApiError {
+    log::error!("Could not sign token: {}", err);
+    ApiError::Internal(String::from("Unable to issue a token."))
+}
+
+/// Loads a portfolio owned by `user`: 404 when it does not exist, 403 when it
+/// belongs to someone else.
+fn owned_portfolio(
+    data: &infrastructure::state::AppState,
+    user: &AuthenticatedUser,
+    portfolio_id: &str,
+) -> Result<Portfolio, ApiError> {
+    if let Some(portfolio) =
+        Portfolio::get_for_owner(&data.get_connection(), portfolio_id, &user.id)?
+    {
+        return Ok(portfolio);
+    }
+
+    match Portfolio::get_by_id(&data.get_connection(), portfolio_id.to_string()) {
+        Ok(_) => Err(ApiError::Forbidden(String::from(
+            "Portfolio belongs to another user.",
+        ))),
+        Err(err) => Err(portfolio_not_found(err)),
+    }
+}
+
+/// Loads a ticker whose portfolio is owned by `user`, with the same 404/403 rules.
+fn owned_ticker(
+    data: &infrastructure::state::AppState,
+    user: &AuthenticatedUser,
+    ticker_id: &String,
+) -> Result<Ticker, ApiError> {
+    if let Some(ticker) = Ticker::get_for_owner(&data.get_connection(), ticker_id, &user.id)? {
+        return Ok(ticker);
+    }
+
+    match Ticker::get_by_id(&data.get_connection(), ticker_id)? {
+        Some(_) => Err(ApiError::Forbidden(String::from(
+            "Ticker belongs to another user.",
+        ))),
+        None => Err(ApiError::NotFound(String::from(
+            "Ticker with that ID does not exist.",
+        ))),
     }
 }
 
+/// Legacy listing: the first page only, as a plain array. `/api/v1/users` pages on.
+pub async fn get_all_users(
+    _admin: AdminUser,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let (users, _) = User::page(&data.get_connection(), &ListOptions::default())?;
+
+    Ok(HttpResponse::Ok().json(views::all::<_, UserView>(users)))
+}
+
+pub async fn list_users(
+    _admin: AdminUser,
+    query: web::Query<PageQuery>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let options = query.options(true)?;
+    let (users, total) = User::page(&data.get_connection(), &options)?;
+
+    Ok(HttpResponse::Ok().json(Page::new(users, total, &options).map(UserView::from)))
+}
+
 pub async fn register(
     data: web::Data<infrastructure::state::AppState>,
     new_user: web::Json<NewUser>,
-) -> impl Responder {
-    if verify_email(new_user.email.clone()) == true {
-        if verify_password(new_user.password.clone()) == true {
-            match crate::models::user::NewUser::create(
-                new_user.email.clone(),
-                new_user.password.clone(),
-                &data.get_connection(),
-            ) {
-                Ok(user) => HttpResponse::Created().json(user),
-                Err(_) => HttpResponse::BadRequest().body("User already exists"),
-            }
-        } else {
-            HttpResponse::BadRequest().body("Password is not strong enough")
-        }
-    } else {
-        HttpResponse::BadRequest().body("Not valid email format")
+) -> HandlerResult {
+    if !verify_email(new_user.email.clone()) {
+        return Err(ApiError::Validation(String::from("Not valid email format")));
+    }
+    if !verify_password(new_user.password.clone()) {
+        return Err(ApiError::Validation(String::from(
+            "Password is not strong enough",
+        )));
+    }
+
+    let user = crate::models::user::NewUser::create(
+        new_user.email.clone(),
+        new_user.password.clone(),
+        &data.config().argon2,
+        &data.get_connection(),
+    )?;
+
+    // The account exists either way; a lost email can be sent again.
+    if let Err(e) = send_verification_email(&data, &user) {
+        log::error!("Could not send verification email to {}: {}", user.email, e);
     }
+
+    Ok(HttpResponse::Created().json(UserView::from(user)))
+}
+
+/// Absolute link into the application for emails, based on `APP_URL`.
+fn app_link(data: &infrastructure::state::AppState, path: &str) -> String {
+    let app_url = &data.config().accounts.app_url;
+
+    format!("{}{}", app_url.trim_end_matches('/'), path)
+}
+
+fn send_verification_email(
+    data: &infrastructure::state::AppState,
+    user: &User,
+) -> Result<(), ApiError> {
+    let hours = data.config().accounts.email_verification_lifetime_hours;
+    let token = OneTimeToken::issue(
+        &data.get_connection(),
+        &user.id,
+        TokenPurpose::VerifyEmail,
+        chrono::Duration::hours(hours),
+    )?;
+
+    let link = app_link(data, &format!("/verify-email?token={}", token));
+
+    data.mailer()
+        .send(&mailer::verification_email(&user.email, &link, hours))
+        .map_err(|e| ApiError::Upstream(e.to_string()))
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+pub struct VerifyEmailQuery {
+    token: String,
+}
+
+pub async fn verify_email_address(
+    query: web::Query<VerifyEmailQuery>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let token = OneTimeToken::consume(
+        &data.get_connection(),
+        &query.token,
+        TokenPurpose::VerifyEmail,
+    )?
+    .ok_or_else(|| {
+        ApiError::Validation(String::from("Verification link is invalid or has expired."))
+    })?;
+
+    let user = User::mark_email_verified(&data.get_connection(), &token.user_id)?;
+
+    Ok(HttpResponse::Ok().json(UserView::from(user)))
+}
+
+pub async fn resend_verification_email(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let me = User::get_by_id(&data.get_connection(), &user.id).map_err(user_not_found)?;
+    if me.email_verified {
+        return Err(ApiError::Conflict(String::from(
+            "Email address is already verified.",
+        )));
+    }
+
+    send_verification_email(&data, &me)?;
+
+    Ok(HttpResponse::Accepted().body("Verification email sent"))
+}
+
+/// How a login hands out credentials: tokens in response headers, or a cookie
+/// session for the browser frontend.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionMode {
+    #[default]
+    Token,
+    Cookie,
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+pub struct LoginOptions {
+    #[serde(default)]
+    #[param(inline)]
+    session: SessionMode,
 }
 
 pub async fn login(
+    req: HttpRequest,
     user_auth: web::Json<AuthUser>,
+    options: web::Query<LoginOptions>,
     data: web::Data<infrastructure::state::AppState>,
-) -> impl Responder {
-    match AuthUser::authenticate(
+) -> HandlerResult {
+    let client = ClientInfo::from_request(&req);
+    throttle::check(
+        &data.get_connection(),
+        data.login_policy(),
+        &user_auth.email,
+        &client,
+    )?;
+
+    let authenticated = match AuthUser::authenticate(
         &data.get_connection(),
         &user_auth.email,
         &user_auth.password,
+        &data.config().argon2,
     ) {
-        Ok((authenticated, token)) => HttpResponse::Ok()
-            .append_header(("jwt", token))
-            .json(authenticated),
-        Err(_) => HttpResponse::NotFound().finish(),
+        Ok(user) => user,
+        Err(_) => {
+            let user_id = User::get_by_email(&data.get_connection(), &user_auth.email)
+                .ok()
+                .map(|user| user.id);
+            throttle::record(
+                &data.get_connection(),
+                user_id,
+                &user_auth.email,
+                &client,
+                "password",
+                false,
+            );
+            return Err(ApiError::Unauthorized(String::from(
+                "Invalid email or password.",
+            )));
+        }
+    };
+
+    // With two factors the attempt only counts as a success after the second step.
+    if authenticated.totp_enabled {
+        return Ok(HttpResponse::Accepted().json(TwoFactorChallenge {
+            challenge_token: authentication::generate_challenge(data.keys(), &authenticated)
+                .map_err(signing_failed)?,
+            expires_in: authentication::CHALLENGE_LIFETIME_SECONDS,
+        }));
+    }
+
+    throttle::record(
+        &data.get_connection(),
+        Some(authenticated.id.clone()),
+        &authenticated.email,
+        &client,
+        "password",
+        true,
+    );
+
+    signed_in(&req, &data, authenticated, options.session)
+}
+
+/// Issues the access and refresh tokens, or starts a cookie session, once every
+/// login step has passed.
+fn signed_in(
+    req: &HttpRequest,
+    data: &infrastructure::state::AppState,
+    user: User,
+    mode: SessionMode,
+) -> HandlerResult {
+    if mode == SessionMode::Cookie {
+        let csrf = session::start(req, data.session_settings(), &user)?;
+
+        return Ok(HttpResponse::Ok()
+            .append_header((CSRF_HEADER, csrf.value().to_string()))
+            .cookie(csrf)
+            .json(UserView::from(user)));
+    }
+
+    let access_token = user.generate_jwt(data.keys()).map_err(signing_failed)?;
+    let refresh_token = RefreshToken::issue(
+        &data.get_connection(),
+        &user.id,
+        data.config().jwt.refresh_lifetime(),
+    )?;
+
+    Ok(HttpResponse::Ok()
+        .append_header(("jwt", access_token))
+        .append_header(("refresh-token", refresh_token))
+        .json(UserView::from(user)))
+}
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema)]
+pub struct TwoFactorChallenge {
+    pub challenge_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct TwoFactorLogin {
+    challenge_token: String,
+    code: String,
+}
+
+/// Second login step: a TOTP or recovery code exchanged for the usual tokens.
+pub async fn login_two_factor(
+    req: HttpRequest,
+    request: web::Json<TwoFactorLogin>,
+    options: web::Query<LoginOptions>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let invalid = || ApiError::Unauthorized(String::from("Invalid or expired login challenge."));
+
+    let claims = authentication::decode_challenge(data.keys(), &request.challenge_token)
+        .map_err(|_| invalid())?;
+    let user = match User::get_by_id(&data.get_connection(), &claims.sub) {
+        Ok(user) if !user.is_deleted && user.totp_enabled => user,
+        _ => return Err(invalid()),
+    };
+
+    let client = ClientInfo::from_request(&req);
+    throttle::check(
+        &data.get_connection(),
+        data.login_policy(),
+        &user.email,
+        &client,
+    )?;
+
+    let passed = user.check_second_factor(&data.get_connection(), &request.code)?;
+    throttle::record(
+        &data.get_connection(),
+        Some(user.id.clone()),
+        &user.email,
+        &client,
+        "totp",
+        passed,
+    );
+    if !passed {
+        return Err(ApiError::Unauthorized(String::from(
+            "Invalid authentication code.",
+        )));
+    }
+
+    signed_in(&req, &data, user, options.session)
+}
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct TotpCode {
+    code: String,
+}
+
+/// Starts enrollment. The secret is not enforced until it is confirmed with a code.
+pub async fn enroll_totp(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let me = User::get_by_id(&data.get_connection(), &user.id).map_err(user_not_found)?;
+    if me.totp_enabled {
+        return Err(ApiError::Conflict(String::from(
+            "Two-factor authentication is already enabled.",
+        )));
+    }
+
+    let secret = totp::generate_secret();
+    User::start_totp_enrollment(&data.get_connection(), &user.id, &secret)?;
+
+    let issuer = &data.config().accounts.totp_issuer;
+
+    Ok(HttpResponse::Ok().json(TotpEnrollment {
+        otpauth_uri: totp::otpauth_uri(&secret, &me.email, issuer),
+        secret,
+    }))
+}
+
+/// Finishes enrollment and hands out the recovery codes, which are shown only once.
+pub async fn confirm_totp(
+    user: AuthenticatedUser,
+    request: web::Json<TotpCode>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let me = User::get_by_id(&data.get_connection(), &user.id).map_err(user_not_found)?;
+    if me.totp_enabled {
+        return Err(ApiError::Conflict(String::from(
+            "Two-factor authentication is already enabled.",
+        )));
+    }
+    if me.totp_secret.is_none() {
+        return Err(ApiError::Validation(String::from(
+            "Start two-factor enrollment first.",
+        )));
+    }
+    if !me.check_totp(&data.get_connection(), &request.code)? {
+        return Err(ApiError::Validation(String::from(
+            "Invalid authentication code.",
+        )));
+    }
+
+    User::enable_totp(&data.get_connection(), &user.id)?;
+    let recovery_codes = RecoveryCode::regenerate(&data.get_connection(), &user.id)?;
+
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
+
+pub async fn regenerate_recovery_codes(
+    user: AuthenticatedUser,
+    request: web::Json<TotpCode>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let me = User::get_by_id(&data.get_connection(), &user.id).map_err(user_not_found)?;
+    if !me.totp_enabled {
+        return Err(ApiError::Validation(String::from(
+            "Two-factor authentication is not enabled.",
+        )));
+    }
+    if !me.check_totp(&data.get_connection(), &request.code)? {
+        return Err(ApiError::Validation(String::from(
+            "Invalid authentication code.",
+        )));
+    }
+
+    let recovery_codes = RecoveryCode::regenerate(&data.get_connection(), &user.id)?;
+
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes }))
+}
+
+pub async fn disable_totp(
+    user: AuthenticatedUser,
+    request: web::Json<TotpCode>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let me = User::get_by_id(&data.get_connection(), &user.id).map_err(user_not_found)?;
+    if !me.totp_enabled {
+        return Err(ApiError::Validation(String::from(
+            "Two-factor authentication is not enabled.",
+        )));
+    }
+    if !me.check_second_factor(&data.get_connection(), &request.code)? {
+        return Err(ApiError::Validation(String::from(
+            "Invalid authentication code.",
+        )));
+    }
+
+    let updated = User::disable_totp(&data.get_connection(), &user.id)?;
+
+    Ok(HttpResponse::Ok().json(UserView::from(updated)))
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    refresh_token: String,
+}
+
+/// Public keys for verifying access tokens, in JWKS form. HS256 secrets are never listed.
+pub async fn jwks(data: web::Data<infrastructure::state::AppState>) -> HandlerResult {
+    Ok(HttpResponse::Ok().json(data.keys().jwks()))
+}
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+}
+
+pub async fn refresh_token(
+    request: web::Json<RefreshRequest>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let (rotated, refresh_token) = RefreshToken::rotate(
+        &data.get_connection(),
+        &request.refresh_token,
+        data.config().jwt.refresh_lifetime(),
+    )?;
+
+    let user = match User::get_by_id(&data.get_connection(), &rotated.user_id) {
+        Ok(user) if !user.is_deleted => user,
+        _ => {
+            RefreshToken::revoke_family(&data.get_connection(), &rotated.family_id)?;
+            return Err(ApiError::Unauthorized(String::from(
+                "Invalid refresh token.",
+            )));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(TokenPair {
+        access_token: user.generate_jwt(data.keys()).map_err(signing_failed)?,
+        refresh_token,
+        token_type: String::from("Bearer"),
+        expires_in: data.keys().lifetime().num_seconds(),
+    }))
+}
+
+/// Revokes the access token used for the call and the refresh token family it
+/// names; without a refresh token every session of the user is ended.
+pub async fn logout(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    request: Option<web::Json<RefreshRequest>>,
+    identity: Option<Identity>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    // A cookie session only ends itself; the user's other sessions stay open.
+    if let Some(identity) = identity {
+        if !req.headers().contains_key(header::AUTHORIZATION) {
+            return Ok(HttpResponse::Ok()
+                .cookie(session::end(identity))
+                .body("Logged out"));
+        }
+    }
+
+    if let Some(claims) = bearer_claims(&req, data.keys()) {
+        if !claims.jti.is_empty() {
+            RevokedToken::revoke(&data.get_connection(), &claims.jti, claims.exp)?;
+        }
+    }
+
+    match request {
+        Some(request) => {
+            match RefreshToken::get_by_token(&data.get_connection(), &request.refresh_token)? {
+                Some(token) if user.owns(&token.user_id) => {
+                    RefreshToken::revoke_family(&data.get_connection(), &token.family_id)?;
+                }
+                _ => {
+                    return Err(ApiError::Unauthorized(String::from(
+                        "Invalid refresh token.",
+                    )))
+                }
+            }
+        }
+        None => {
+            RefreshToken::revoke_all_for_user(&data.get_connection(), &user.id)?;
+        }
+    }
+
+    Ok(HttpResponse::Ok().body("Logged out"))
+}
+#[derive(serde::Deserialize, ToSchema)]
+pub struct ForgotPassword {
+    email: String,
+}
+
+/// Always answers the same way so the endpoint cannot be used to probe for accounts.
+pub async fn forgot_password(
+    request: web::Json<ForgotPassword>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    if let Ok(user) = User::get_by_email(&data.get_connection(), &request.email) {
+        if !user.is_deleted {
+            let minutes = data.config().accounts.password_reset_lifetime_minutes;
+            let sent = OneTimeToken::issue(
+                &data.get_connection(),
+                &user.id,
+                TokenPurpose::ResetPassword,
+                chrono::Duration::minutes(minutes),
+            )
+            .map_err(ApiError::from)
+            .and_then(|token| {
+                let link = app_link(&data, &format!("/password/reset?token={}", token));
+                data.mailer()
+                    .send(&mailer::password_reset_email(&user.email, &link, minutes))
+                    .map_err(|e| ApiError::Upstream(e.to_string()))
+            });
+
+            if let Err(e) = sent {
+                log::error!(
+                    "Could not send password reset email to {}: {}",
+                    user.email,
+                    e
+                );
+            }
+        }
+    }
+
+    Ok(HttpResponse::Accepted()
+        .body("If that address belongs to an account, a reset link has been sent"))
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct ResetPassword {
+    token: String,
+    password: String,
+}
+
+pub async fn reset_password(
+    request: web::Json<ResetPassword>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let request = request.into_inner();
+
+    if !verify_password(request.password.clone()) {
+        return Err(ApiError::Validation(String::from(
+            "Password is not strong enough",
+        )));
+    }
+
+    let token = OneTimeToken::consume(
+        &data.get_connection(),
+        &request.token,
+        TokenPurpose::ResetPassword,
+    )?
+    .ok_or_else(|| ApiError::Validation(String::from("Reset link is invalid or has expired.")))?;
+
+    let user = User::get_by_id(&data.get_connection(), &token.user_id).map_err(user_not_found)?;
+    user.update_password(
+        &data.get_connection(),
+        request.password,
+        &data.config().argon2,
+    )?;
+    User::invalidate_sessions(&data.get_connection(), &token.user_id)?;
+
+    Ok(HttpResponse::Ok().body("Password has been reset; please sign in again"))
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct NewApiKey {
+    name: String,
+    scopes: Vec<String>,
+    expires_in_days: Option<i64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, ToSchema)]
+pub struct CreatedApiKey {
+    /// The full key. It is not stored and cannot be shown again.
+    pub key: String,
+    pub api_key: ApiKeyView,
+}
+
+pub async fn create_api_key(
+    user: AuthenticatedUser,
+    request: web::Json<NewApiKey>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let request = request.into_inner();
+
+    if request.name.trim().is_empty() {
+        return Err(ApiError::Validation(String::from(
+            "API key name must not be empty.",
+        )));
+    }
+    if request.scopes.is_empty() {
+        return Err(ApiError::Validation(String::from(
+            "API key needs at least one scope.",
+        )));
+    }
+    let mut scopes = Vec::new();
+    for scope in &request.scopes {
+        match Scope::parse(scope) {
+            Some(scope) => scopes.push(scope),
+            None => return Err(ApiError::Validation(format!("Unknown scope '{}'.", scope))),
+        }
     }
+    let lifetime = match request.expires_in_days {
+        Some(days) if days <= 0 => {
+            return Err(ApiError::Validation(String::from(
+                "expires_in_days must be positive.",
+            )))
+        }
+        Some(days) => Some(chrono::Duration::days(days)),
+        None => None,
+    };
+
+    let (api_key, key) = ApiKey::new(&user.id, request.name.trim(), &scopes, lifetime);
+    let api_key = ApiKey::create(&data.get_connection(), &api_key)?;
+
+    Ok(HttpResponse::Created().json(CreatedApiKey {
+        key,
+        api_key: ApiKeyView::from(api_key),
+    }))
+}
+
+pub async fn get_api_keys(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let api_keys = ApiKey::get_all_for_user(&data.get_connection(), &user.id)?;
+
+    Ok(HttpResponse::Ok().json(views::all::<_, ApiKeyView>(api_keys)))
 }
-#[derive(serde::Deserialize)]
+
+pub async fn revoke_api_key(
+    user: AuthenticatedUser,
+    id: web::Path<String>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let revoked = ApiKey::revoke(&data.get_connection(), &id, &user.id).map_err(|_| {
+        ApiError::NotFound(String::from("Active API key with that ID does not exist."))
+    })?;
+
+    Ok(HttpResponse::Ok().json(ApiKeyView::from(revoked)))
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+pub struct LoginActivityQuery {
+    limit: Option<i64>,
+}
+
+/// The caller's most recent sign-in attempts, newest first.
+pub async fn get_login_activity(
+    user: AuthenticatedUser,
+    query: web::Query<LoginActivityQuery>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let events = LoginEvent::recent_for_user(&data.get_connection(), &user.id, limit)?;
+
+    Ok(HttpResponse::Ok().json(views::all::<_, LoginEventView>(events)))
+}
+
+#[derive(serde::Deserialize, ToSchema)]
 pub struct IdAndValue {
     id: String,
     value: String,
 }
 
+#[derive(serde::Deserialize, ToSchema)]
+pub struct SingleValue {
+    value: String,
+}
+
+fn change_email(
+    data: &infrastructure::state::AppState,
+    id: &String,
+    email: String,
+) -> HandlerResult {
+    if !verify_email(email.clone()) {
+        return Err(ApiError::Validation(String::from("Not valid email format")));
+    }
+
+    match User::get_by_email(&data.get_connection(), &email) {
+        Ok(owner) if owner.id != *id => {
+            return Err(ApiError::Conflict(String::from(
+                "Email is already used by another account.",
+            )))
+        }
+        Ok(_) | Err(diesel::result::Error::NotFound) => (),
+        Err(err) => return Err(err.into()),
+    }
+
+    let user = User::get_by_id(&data.get_connection(), id).map_err(user_not_found)?;
+    let updated = user.update_email(&data.get_connection(), email)?;
+
+    if let Err(e) = send_verification_email(data, &updated) {
+        log::error!(
+            "Could not send verification email to {}: {}",
+            updated.email,
+            e
+        );
+    }
+
+    Ok(HttpResponse::Ok().json(UserView::from(updated)))
+}
+
+fn change_password(
+    data: &infrastructure::state::AppState,
+    id: &String,
+    password: String,
+) -> HandlerResult {
+    if !verify_password(password.clone()) {
+        return Err(ApiError::Validation(String::from(
+            "Password is not strong enough",
+        )));
+    }
+
+    let user = User::get_by_id(&data.get_connection(), id).map_err(user_not_found)?;
+    user.update_password(&data.get_connection(), password, &data.config().argon2)?;
+    // Whoever knew the old password is signed out everywhere.
+    User::invalidate_sessions(&data.get_connection(), id)?;
+
+    Ok(HttpResponse::Ok().body("Password successfully updated; please sign in again"))
+}
+
+/// Soft-deletes the user along with their portfolios, tickers and transactions,
+/// in one transaction so a deleted user never keeps live portfolios.
+fn remove_user(data: &infrastructure::state::AppState, id: &String) -> HandlerResult {
+    let connection = data.get_connection();
+    connection.transaction::<_, ApiError, _>(|| {
+        let portfolios = Portfolio::get_all_from_user(&connection, id)?;
+
+        User::delete_user(&connection, id).map_err(user_not_found)?;
+        RefreshToken::revoke_all_for_user(&connection, id)?;
+
+        for portfolio in portfolios {
+            let portfolio = Portfolio::delete_portfolio(&connection, &portfolio.id)?;
+            Transaction::delete_transactions(&connection, &portfolio.id)?;
+            Ticker::delete_tickers(&connection, &portfolio.id)?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(HttpResponse::Ok().body("User successfully deleted"))
+}
+
 pub async fn get_user_by_id(
+    _admin: AdminUser,
     id_and_value: web::Json<IdAndValue>,
     data: web::Data<infrastructure::state::AppState>,
-) -> impl Responder {
+) -> HandlerResult {
+    let id = id_and_value.into_inner().id;
+
+    find_user(&data, &id)
+}
+
+pub async fn get_user(
+    _admin: AdminUser,
+    id: web::Path<String>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    find_user(&data, &id)
+}
+
+fn find_user(data: &infrastructure::state::AppState, id: &String) -> HandlerResult {
+    let user = User::get_by_id(&data.get_connection(), id).map_err(user_not_found)?;
+
+    Ok(HttpResponse::Ok().json(UserView::from(user)))
+}
+
+pub async fn update_user_email(
+    _admin: AdminUser,
+    id_and_value: web::Json<IdAndValue>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let id_and_email = id_and_value.into_inner();
+
+    change_email(&data, &id_and_email.id, id_and_email.value)
+}
+
+pub async fn set_user_email(
+    _admin: AdminUser,
+    id: web::Path<String>,
+    email: web::Json<SingleValue>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    change_email(&data, &id, email.into_inner().value)
+}
+
+pub async fn update_user_password(
+    _admin: AdminUser,
+    id_and_value: web::Json<IdAndValue>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let id_and_password = id_and_value.into_inner();
+
+    change_password(&data, &id_and_password.id, id_and_password.value)
+}
+
+pub async fn set_user_password(
+    _admin: AdminUser,
+    id: web::Path<String>,
+    password: web::Json<SingleValue>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    change_password(&data, &id, password.into_inner().value)
+}
+
+pub async fn update_user_role(
+    _admin: AdminUser,
+    id_and_value: web::Json<IdAndValue>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let id_and_role = id_and_value.into_inner();
+
+    change_role(&data, &id_and_role.id, &id_and_role.value)
+}
+
+pub async fn set_user_role(
+    _admin: AdminUser,
+    id: web::Path<String>,
+    role: web::Json<SingleValue>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    change_role(&data, &id, &role.into_inner().value)
+}
+
+fn change_role(data: &infrastructure::state::AppState, id: &String, role: &str) -> HandlerResult {
+    let role = match Role::parse(role) {
+        Some(role) => role,
+        None => {
+            return Err(ApiError::Validation(String::from(
+                "Role must be 'user' or 'admin'.",
+            )))
+        }
+    };
+
+    let user = User::get_by_id(&data.get_connection(), id).map_err(user_not_found)?;
+    let updated = user.update_role(&data.get_connection(), role)?;
+
+    Ok(HttpResponse::Ok().json(UserView::from(updated)))
+}
+
+pub async fn delete_user(
+    _admin: AdminUser,
+    id: web::Path<String>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    remove_user(&data, &id)
+}
+
+pub async fn get_me(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let me = User::get_by_id(&data.get_connection(), &user.id).map_err(user_not_found)?;
+
+    Ok(HttpResponse::Ok().json(UserView::from(me)))
+}
+
+pub async fn update_my_email(
+    user: AuthenticatedUser,
+    email: web::Json<SingleValue>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    change_email(&data, &user.id, email.into_inner().value)
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+/// A stolen access token alone is not enough to take over the account.
+pub async fn update_my_password(
+    user: AuthenticatedUser,
+    request: web::Json<PasswordChange>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let request = request.into_inner();
+
+    let account = User::get_by_id(&data.get_connection(), &user.id).map_err(user_not_found)?;
+    if !password::verify(&request.current_password, &account.password) {
+        return Err(ApiError::Forbidden(String::from(
+            "Current password is incorrect.",
+        )));
+    }
+
+    change_password(&data, &user.id, request.new_password)
+}
+
+pub async fn delete_me(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    remove_user(&data, &user.id)
+}
+
+pub fn verify_email(email: String) -> bool {
+    let email_regex = Regex::new(
+        r"^([a-z0-9_+]([a-z0-9_+.]*[a-z0-9_+])?)@([a-z0-9]+([\-\.]{1}[a-z0-9]+)*\.[a-z]{2,6})",
+    )
+    .unwrap();
+    email_regex.is_match(&email)
+}
+
+pub fn verify_password(passwrod: String) -> bool {
+    let password_regex = Regex::new(
+        r"^(\P{Ll}*\p{Ll})(\P{Lu}*\p{Lu})(\P{N}*\p{N})([\p{L}\p{N}]*[^\p{L}\p{N}])[\s\S]{8,}$",
+    )
+    .unwrap();
+
+    //let password_regex = Regex::new(r"^([^\s{2}].)[\s\S]{6,}$").unwrap();
+    password_regex.is_match(&passwrod)
+}
+
+pub async fn create_portfolio(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+    portfolio: web::Json<NewPortfolio>,
+) -> HandlerResult {
+    if let Some(user_id) = &portfolio.user_id {
+        if !user.owns(user_id) {
+            return Err(ApiError::Forbidden(String::from(
+                "Portfolios can only be created for yourself.",
+            )));
+        }
+    }
+
+    let owner = User::get_by_id(&data.get_connection(), &user.id).map_err(user_not_found)?;
+    if !owner.email_verified {
+        return Err(ApiError::Forbidden(String::from(
+            "Verify your email address before creating portfolios.",
+        )));
+    }
+
+    let created = NewPortfolio::create(
+        portfolio.name.clone(),
+        user.id.clone(),
+        portfolio.cost_basis_method.unwrap_or(CostBasisMethod::Fifo),
+        &data.get_connection(),
+    )?;
+
+    Ok(HttpResponse::Created().json(PortfolioView::from(created)))
+}
+
+pub async fn get_portfolios(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let portfolios =
+        crate::models::portfolio::Portfolio::get_all_from_user(&data.get_connection(), &user.id)?;
+
+    Ok(HttpResponse::Ok().json(views::all::<_, PortfolioView>(portfolios)))
+}
+
+pub async fn list_portfolios(
+    user: AuthenticatedUser,
+    query: web::Query<PageQuery>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let options = query.options(user.role == Role::Admin)?;
+    let (portfolios, total) =
+        Portfolio::page_from_user(&data.get_connection(), &user.id, &options)?;
+
+    Ok(HttpResponse::Ok().json(Page::new(portfolios, total, &options).map(PortfolioView::from)))
+}
+
+pub async fn get_portfolio_by_id(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+    id_and_value: web::Json<IdAndValue>,
+) -> HandlerResult {
     let id = id_and_value.into_inner().id;
 
-    let user = match User::get_by_id(&data.get_connection(), &id) {
-        Ok(result) => result,
-        Err(_) => return HttpResponse::BadRequest().body("User ID does not exist."),
-    };
+    let portfolio = owned_portfolio(&data, &user, &id)?;
+
+    Ok(HttpResponse::Ok().json(PortfolioView::from(portfolio)))
+}
+
+pub async fn get_portfolio(
+    user: AuthenticatedUser,
+    id: web::Path<String>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let portfolio = owned_portfolio(&data, &user, &id)?;
+
+    Ok(HttpResponse::Ok().json(PortfolioView::from(portfolio)))
+}
+
+/// Fields of a portfolio that can be changed; absent ones are left alone.
+#[derive(serde::Deserialize, ToSchema)]
+pub struct PortfolioChanges {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    cost_basis_method: Option<String>,
+}
+
+pub async fn update_portfolio(
+    user: AuthenticatedUser,
+    id: web::Path<String>,
+    changes: web::Json<PortfolioChanges>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let changes = changes.into_inner();
+    let method = match &changes.cost_basis_method {
+        Some(method) => Some(parse_cost_basis_method(method)?),
+        None => None,
+    };
+
+    let mut portfolio = owned_portfolio(&data, &user, &id)?;
+    if let Some(name) = changes.name {
+        portfolio = portfolio.update_name(&data.get_connection(), name)?;
+    }
+    if let Some(method) = method {
+        portfolio = portfolio.update_cost_basis_method(&data.get_connection(), method)?;
+    }
+
+    Ok(HttpResponse::Ok().json(PortfolioView::from(portfolio)))
+}
+
+fn parse_cost_basis_method(method: &str) -> Result<CostBasisMethod, ApiError> {
+    CostBasisMethod::parse(method).ok_or_else(|| {
+        ApiError::Validation(String::from(
+            "Cost basis method must be 'fifo', 'lifo' or 'average_cost'.",
+        ))
+    })
+}
+
+pub async fn update_portfolio_name(
+    user: AuthenticatedUser,
+    id_and_value: web::Json<IdAndValue>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let id_and_name = id_and_value.into_inner();
+    let id = id_and_name.id;
+    let name = id_and_name.value;
+
+    let portfolio = owned_portfolio(&data, &user, &id)?;
+
+    let updated = portfolio.update_name(&data.get_connection(), name)?;
 
-    HttpResponse::Ok().json(user)
+    Ok(HttpResponse::Ok().json(PortfolioView::from(updated)))
 }
 
-pub async fn update_user_email(
+pub async fn update_portfolio_cost_basis_method(
+    user: AuthenticatedUser,
     id_and_value: web::Json<IdAndValue>,
     data: web::Data<infrastructure::state::AppState>,
-) -> impl Responder {
-    let id_and_email = id_and_value.into_inner();
-    let id = id_and_email.id;
-    let email = id_and_email.value;
-    if verify_email(email.clone()) == true {
-        let user = match User::get_by_id(&data.get_connection(), &id) {
-            Ok(result) => result,
-            Err(_) => return HttpResponse::BadRequest().body("User ID does not exist."),
-        };
+) -> HandlerResult {
+    let id_and_method = id_and_value.into_inner();
+    let id = id_and_method.id;
 
-        match user.update_email(&data.get_connection(), email) {
-            Ok(result) => HttpResponse::Ok().json(result),
-            Err(_) => HttpResponse::InternalServerError().body("Invalid email address"),
-        }
-    } else {
-        HttpResponse::BadRequest().body("Not valid email format")
-    }
+    let method = parse_cost_basis_method(&id_and_method.value)?;
+
+    let portfolio = owned_portfolio(&data, &user, &id)?;
+    let updated = portfolio.update_cost_basis_method(&data.get_connection(), method)?;
+
+    Ok(HttpResponse::Ok().json(PortfolioView::from(updated)))
 }
 
-pub async fn update_user_password(
-    id_and_value: web::Json<IdAndValue>,
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct DeletedPortfolio {
+    pub portfolio: PortfolioView,
+    pub tickers: Vec<TickerView>,
+}
+
+pub async fn delete_portfolio(
+    user: AuthenticatedUser,
+    portfolio_id: web::Path<String>,
     data: web::Data<infrastructure::state::AppState>,
-) -> impl Responder {
-    let id_and_password = id_and_value.into_inner();
-    let id = id_and_password.id;
-    let password = id_and_password.value;
+) -> HandlerResult {
+    owned_portfolio(&data, &user, &portfolio_id)?;
+
+    let portfolio = Portfolio::delete_portfolio(&data.get_connection(), &portfolio_id)
+        .map_err(portfolio_not_found)?;
+    Transaction::delete_transactions(&data.get_connection(), &portfolio_id)?;
+    let tickers = Ticker::delete_tickers(&data.get_connection(), &portfolio_id)?;
+
+    Ok(HttpResponse::Ok().json(DeletedPortfolio {
+        portfolio: PortfolioView::from(portfolio),
+        tickers: views::all(tickers),
+    }))
+}
 
-    if verify_password(password.clone()) == true {
-        let user = match User::get_by_id(&data.get_connection(), &id) {
-            Ok(result) => result,
-            Err(_) => return HttpResponse::BadRequest().body("User ID does not exist."),
-        };
+pub async fn add_ticker(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+    ticker: web::Json<NewTicker>,
+) -> HandlerResult {
+    create_ticker(&data, &user, &ticker.portfolio_id, &ticker.name).await
+}
 
-        match user.update_password(&data.get_connection(), password) {
-            Ok(_) => HttpResponse::Ok().body("Password successfully updated"),
-            Err(_) => HttpResponse::InternalServerError().body("Invalid password"),
-        }
-    } else {
-        HttpResponse::BadRequest().body("Password is not strong enough")
-    }
+#[derive(serde::Deserialize, ToSchema)]
+pub struct TickerSymbol {
+    name: String,
 }
 
-pub async fn delete_user(
-    id: web::Path<String>,
+pub async fn add_portfolio_ticker(
+    user: AuthenticatedUser,
+    portfolio_id: web::Path<String>,
+    ticker: web::Json<TickerSymbol>,
     data: web::Data<infrastructure::state::AppState>,
-) -> impl Responder {
-    let portfolios = match Portfolio::get_all_from_user(&data.get_connection(), &id) {
-        Ok(result) => result,
-        Err(err) => return HttpResponse::BadGateway().body(format!("{:?}", err)),
-    };
-
-    match User::delete_user(&data.get_connection(), &id) {
-        Ok(user) => {
-            for portfolio in portfolios {
-                match Portfolio::delete_portfolio(&data.get_connection(), &portfolio.id) {
-                    Ok(portfolio) => {
-                        match Ticker::delete_tickers(&data.get_connection(), &portfolio.id) {
-                            Ok(tickers) => (),
-                            Err(err) => {
-                                return HttpResponse::BadRequest().body(format!("{:?}", err))
-                            }
-                        }
-                    }
-                    Err(err) => return HttpResponse::BadRequest().body(format!("{:?}", err)),
-                }
-            }
-            HttpResponse::Ok().body("User successfully deleted")
-        }
-        Err(_) => HttpResponse::BadRequest().finish(),
-    }
+) -> HandlerResult {
+    create_ticker(&data, &user, &portfolio_id, &ticker.name).await
 }
 
-pub fn verify_email(email: String) -> bool {
-    let email_regex = Regex::new(
-        r"^([a-z0-9_+]([a-z0-9_+.]*[a-z0-9_+])?)@([a-z0-9]+([\-\.]{1}[a-z0-9]+)*\.[a-z]{2,6})",
-    )
-    .unwrap();
-    email_regex.is_match(&email) == true
+async fn create_ticker(
+    data: &infrastructure::state::AppState,
+    user: &AuthenticatedUser,
+    portfolio_id: &str,
+    symbol: &str,
+) -> HandlerResult {
+    owned_portfolio(data, user, portfolio_id)?;
+
+    let instrument = catalog::ensure_instrument(data, symbol).await?;
+
+    // Stored under the provider's canonical symbol so it matches the catalog.
+    let created = NewTicker::create(
+        instrument.symbol,
+        portfolio_id.to_string(),
+        &data.get_connection(),
+    )?;
+
+    Ok(HttpResponse::Created().json(TickerView::from(created)))
 }
 
-pub fn verify_password(passwrod: String) -> bool {
-    let password_regex = Regex::new(
-        r"^(\P{Ll}*\p{Ll})(\P{Lu}*\p{Lu})(\P{N}*\p{N})([\p{L}\p{N}]*[^\p{L}\p{N}])[\s\S]{8,}$",
-    )
-    .unwrap();
+pub async fn delete_ticker(
+    user: AuthenticatedUser,
+    data: web::Data<infrastructure::state::AppState>,
+    ticker_id: web::Path<String>,
+) -> HandlerResult {
+    owned_ticker(&data, &user, &ticker_id)?;
 
-    //let password_regex = Regex::new(r"^([^\s{2}].)[\s\S]{6,}$").unwrap();
-    password_regex.is_match(&passwrod) == true
+    remove_ticker(&data, &ticker_id)
 }
 
-pub async fn create_portfolio(
+pub async fn delete_portfolio_ticker(
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
     data: web::Data<infrastructure::state::AppState>,
-    portfolio: web::Json<NewPortfolio>,
-) -> impl Responder {
-    match NewPortfolio::create(
-        portfolio.name.clone(),
-        portfolio.user_id.clone(),
-        &data.get_connection(),
-    ) {
-        Ok(created) => HttpResponse::Created().json(created),
-        Err(_) => HttpResponse::BadRequest().body("Portfolio with that name already exists"),
+) -> HandlerResult {
+    let (portfolio_id, ticker_id) = path.into_inner();
+
+    let ticker = owned_ticker(&data, &user, &ticker_id)?;
+    if ticker.portfolio_id != portfolio_id {
+        return Err(ApiError::NotFound(String::from(
+            "Ticker with that ID does not exist.",
+        )));
     }
+
+    remove_ticker(&data, &ticker_id)
 }
 
-pub async fn get_portfolios(
-    data: web::Data<infrastructure::state::AppState>,
-    id_and_value: web::Json<IdAndValue>,
-) -> impl Responder {
-    match crate::models::portfolio::Portfolio::get_all_from_user(
-        &data.get_connection(),
-        &id_and_value.into_inner().id,
-    ) {
-        Ok(results) => HttpResponse::Ok().json(results),
-        Err(_) => HttpResponse::BadRequest().body("User ID does not exist."),
+fn remove_ticker(data: &infrastructure::state::AppState, ticker_id: &String) -> HandlerResult {
+    let ticker =
+        Ticker::delete_ticker(&data.get_connection(), ticker_id).map_err(ticker_not_found)?;
+    Transaction::delete_from_ticker(&data.get_connection(), &ticker.id)?;
+
+    Ok(HttpResponse::Ok().json(TickerView::from(ticker)))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PositionView {
+    ticker_id: String,
+    symbol: String,
+    quantity: f64,
+}
+
+/// Rejects a ticker that is missing or belongs to a different portfolio.
+fn ticker_in_portfolio(
+    data: &infrastructure::state::AppState,
+    ticker_id: &String,
+    portfolio_id: &String,
+) -> Result<Ticker, ApiError> {
+    match Ticker::get_by_id(&data.get_connection(), ticker_id)? {
+        Some(ticker) if &ticker.portfolio_id == portfolio_id => Ok(ticker),
+        _ => Err(ApiError::Validation(String::from(
+            "Ticker is not part of that portfolio.",
+        ))),
     }
 }
 
-pub async fn get_portfolio_by_id(
+fn oversold(transaction_id: String) -> ApiError {
+    ApiError::Validation(format!(
+        "Transaction '{}' sells more shares than the portfolio holds.",
+        transaction_id
+    ))
+}
+
+/// Checks that the ledger never sells more than it holds once `ledger` is applied.
+fn check_ledger(ledger: &[Transaction]) -> Result<Vec<Position>, ApiError> {
+    Position::from_transactions(ledger).map_err(oversold)
+}
+
+pub async fn get_transactions(
+    user: AuthenticatedUser,
+    portfolio_id: web::Path<String>,
     data: web::Data<infrastructure::state::AppState>,
-    id_and_value: web::Json<IdAndValue>,
-) -> impl Responder {
-    let id = id_and_value.into_inner().id;
+) -> HandlerResult {
+    owned_portfolio(&data, &user, &portfolio_id)?;
 
-    let portfolio = match Portfolio::get_by_id(&data.get_connection(), id) {
-        Ok(result) => result,
-        Err(_) => return HttpResponse::BadRequest().body("Portfolio with that ID does not exist."),
-    };
+    let transactions = Transaction::get_all_from_portfolio(&data.get_connection(), &portfolio_id)?;
 
-    HttpResponse::Ok().json(portfolio)
+    Ok(HttpResponse::Ok().json(views::all::<_, TransactionView>(transactions)))
 }
 
-pub async fn update_portfolio_name(
-    id_and_value: web::Json<IdAndValue>,
+pub async fn get_transaction_by_id(
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
     data: web::Data<infrastructure::state::AppState>,
-) -> impl Responder {
-    let id_and_name = id_and_value.into_inner();
-    let id = id_and_name.id;
-    let name = id_and_name.value;
+) -> HandlerResult {
+    let (portfolio_id, transaction_id) = path.into_inner();
+    owned_portfolio(&data, &user, &portfolio_id)?;
 
-    let portfolio = match Portfolio::get_by_id(&data.get_connection(), id) {
-        Ok(result) => result,
-        Err(_) => return HttpResponse::BadRequest().body("Portfolio with that ID does not exist."),
-    };
+    let transaction =
+        Transaction::get_by_id(&data.get_connection(), &transaction_id, &portfolio_id)
+            .map_err(transaction_not_found)?;
 
-    match portfolio.update_name(&data.get_connection(), name) {
-        Ok(result) => HttpResponse::Ok().json(result),
-        Err(_) => HttpResponse::InternalServerError().body("Invalid email address"),
-    }
+    Ok(HttpResponse::Ok().json(TransactionView::from(transaction)))
 }
 
-pub async fn delete_portfolio(
+pub async fn create_transaction(
+    user: AuthenticatedUser,
     portfolio_id: web::Path<String>,
+    transaction: web::Json<NewTransaction>,
     data: web::Data<infrastructure::state::AppState>,
-) -> impl Responder {
-    #[derive(Serialize, Deserialize)]
-    struct PortfolioAndTickers {
-        portfolio: Portfolio,
-        tickers: Vec<Ticker>,
-    }
+) -> HandlerResult {
+    let portfolio_id = portfolio_id.into_inner();
+    let transaction = transaction.into_inner();
 
-    match Portfolio::delete_portfolio(&data.get_connection(), &portfolio_id) {
-        Ok(portfolio) => match Ticker::delete_tickers(&data.get_connection(), &portfolio_id) {
-            Ok(tickers) => HttpResponse::Ok().json(PortfolioAndTickers { portfolio, tickers }),
-            Err(err) => HttpResponse::BadRequest().body(format!("{:?}", err)),
-        },
-        Err(err) => HttpResponse::BadRequest().body(format!("{:?}", err)),
-    }
+    transaction.validate()?;
+
+    owned_portfolio(&data, &user, &portfolio_id)?;
+    ticker_in_portfolio(&data, &transaction.ticker_id, &portfolio_id)?;
+
+    let connection = data.get_connection();
+    let created = connection.transaction::<_, ApiError, _>(|| {
+        Portfolio::lock(&connection, &portfolio_id)?;
+        let mut ledger = Transaction::get_all_from_portfolio(&connection, &portfolio_id)?;
+        ledger.push(Transaction::new(portfolio_id.clone(), transaction.clone()));
+        check_ledger(&ledger)?;
+
+        Ok(transaction.create(portfolio_id, &connection)?)
+    })?;
+
+    Ok(HttpResponse::Created().json(TransactionView::from(created)))
 }
 
-pub async fn add_ticker(
+pub async fn update_transaction(
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    changes: web::Json<NewTransaction>,
     data: web::Data<infrastructure::state::AppState>,
-    ticker: web::Json<NewTicker>,
-) -> impl Responder {
-    let provider = yahoo::YahooConnector::new();
-    match provider.get_latest_quotes(&ticker.name, "1m").await {
-        Ok(_) => (),
-        Err(_) => {
-            return HttpResponse::BadRequest()
-                .body(format!("Ticker '{}' does not exist.", ticker.name))
+) -> HandlerResult {
+    let (portfolio_id, transaction_id) = path.into_inner();
+    let changes = changes.into_inner();
+
+    changes.validate()?;
+    owned_portfolio(&data, &user, &portfolio_id)?;
+    ticker_in_portfolio(&data, &changes.ticker_id, &portfolio_id)?;
+
+    let connection = data.get_connection();
+    let result = connection.transaction::<_, ApiError, _>(|| {
+        Portfolio::lock(&connection, &portfolio_id)?;
+        let transaction = Transaction::get_by_id(&connection, &transaction_id, &portfolio_id)
+            .map_err(transaction_not_found)?;
+
+        // The edited trade keeps its place among trades of the same day.
+        let mut updated = Transaction::new(portfolio_id.clone(), changes.clone());
+        updated.id = transaction.id.clone();
+        updated.created_at = transaction.created_at;
+        let mut ledger = Transaction::get_all_from_portfolio(&connection, &portfolio_id)?;
+        for existing in ledger.iter_mut() {
+            if existing.id == updated.id {
+                *existing = updated.clone();
+            }
         }
-    };
+        check_ledger(&ledger)?;
 
-    match NewTicker::create(
-        ticker.name.clone(),
-        ticker.portfolio_id.clone(),
-        &data.get_connection(),
-    ) {
-        Ok(created) => HttpResponse::Created().json(created),
-        Err(_) => HttpResponse::BadRequest().body("Ticker with that name already exists"),
-    }
+        Ok(transaction.update(&connection, changes)?)
+    })?;
+
+    Ok(HttpResponse::Ok().json(TransactionView::from(result)))
 }
 
-pub async fn delete_ticker(
+pub async fn delete_transaction(
+    user: AuthenticatedUser,
+    path: web::Path<(String, String)>,
     data: web::Data<infrastructure::state::AppState>,
-    ticker_id: web::Path<String>,
-) -> impl Responder {
-    match Ticker::delete_ticker(&data.get_connection(), &ticker_id) {
-        Ok(ticker) => HttpResponse::Ok().json(ticker),
-        Err(err) => HttpResponse::BadRequest().body(format!("Unable to delete ticker: {:?}", err)),
-    }
+) -> HandlerResult {
+    let (portfolio_id, transaction_id) = path.into_inner();
+    owned_portfolio(&data, &user, &portfolio_id)?;
+
+    let connection = data.get_connection();
+    let result = connection.transaction::<_, ApiError, _>(|| {
+        Portfolio::lock(&connection, &portfolio_id)?;
+        let transaction = Transaction::get_by_id(&connection, &transaction_id, &portfolio_id)
+            .map_err(transaction_not_found)?;
+
+        let mut ledger = Transaction::get_all_from_portfolio(&connection, &portfolio_id)?;
+        ledger.retain(|existing| existing.id != transaction.id);
+        check_ledger(&ledger)?;
+
+        Ok(Transaction::delete_transaction(
+            &connection,
+            &transaction.id,
+        )?)
+    })?;
+
+    Ok(HttpResponse::Ok().json(TransactionView::from(result)))
+}
+
+pub async fn get_positions(
+    user: AuthenticatedUser,
+    portfolio_id: web::Path<String>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    owned_portfolio(&data, &user, &portfolio_id)?;
+
+    let ledger = Transaction::get_all_from_portfolio(&data.get_connection(), &portfolio_id)?;
+    let positions = check_ledger(&ledger)?;
+    let tickers =
+        Ticker::get_all_from_portfolio(&data.get_connection(), portfolio_id.into_inner())?;
+
+    let views: Vec<PositionView> = positions
+        .into_iter()
+        .filter_map(|position| {
+            tickers
+                .iter()
+                .find(|ticker| ticker.id == position.ticker_id)
+                .map(|ticker| PositionView {
+                    ticker_id: position.ticker_id,
+                    symbol: ticker.name.clone(),
+                    quantity: position.quantity,
+                })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(views))
 }
 
 fn from_timestamp_to_datetime(timestamp: String) -> DateTime<Utc> {
@@ -288,18 +1449,11 @@ fn from_timestamp_to_datetime(timestamp: String) -> DateTime<Utc> {
     let naive = NaiveDateTime::from_timestamp(timestamp, 0);
     let datetime: DateTime<Utc> = DateTime::from_utc(naive, Utc);
 
-    return datetime;
-}
-
-#[derive(Deserialize, Serialize)]
-pub struct SearchedTicker {
-    pub symbol: String,
-    pub short_name: String,
-    pub long_name: String,
+    datetime
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct TickerView {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TickerQuoteView {
     id: String,
     name: String,
     symbol: String,
@@ -312,60 +1466,61 @@ pub struct TickerView {
     close: f64,
     date: DateTime<Utc>,
 }
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct IdOrSymbol {
     id: String,
     symbol: String,
 }
 
-pub async fn get_latest_ticker_info(id_or_symbol: web::Json<IdOrSymbol>) -> impl Responder {
-    let mut id = id_or_symbol.id.clone();
-    let symbol = id_or_symbol.symbol.clone();
+pub async fn get_latest_ticker_info(
+    id_or_symbol: web::Json<IdOrSymbol>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let id_or_symbol = id_or_symbol.into_inner();
+
+    ticker_quote(&data, id_or_symbol.id, id_or_symbol.symbol).await
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+pub struct QuoteQuery {
+    #[serde(default)]
+    ticker_id: Option<String>,
+}
+
+pub async fn get_ticker_quote(
+    symbol: web::Path<String>,
+    query: web::Query<QuoteQuery>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let id = query.into_inner().ticker_id.unwrap_or_default();
+
+    ticker_quote(&data, id, symbol.into_inner()).await
+}
+
+/// Latest quote and dividend for `symbol`; `id` is echoed back when it looks like a ticker ID.
+async fn ticker_quote(
+    data: &infrastructure::state::AppState,
+    mut id: String,
+    symbol: String,
+) -> HandlerResult {
     if id.len() != 36 {
         id = String::from("");
     };
 
-    let provider = yahoo::YahooConnector::new();
-
-    let name = match provider.search_ticker(&symbol).await {
-        Ok(items) => items.quotes[0].long_name.clone(),
-        Err(err) => {
-            return HttpResponse::BadRequest().body(format!("Ticker does not exist :{:?}", err))
-        }
-    };
+    let provider = data.provider();
 
-    let interval = None;
-    let range = None;
-    let quotes = match provider
-        .get_quote_range(&symbol, interval.unwrap_or("1d"), range.unwrap_or("6mo"))
-        .await
-    {
-        Ok(quotes) => quotes,
-        Err(err) => {
-            return HttpResponse::BadRequest().body(format!("Response  {}", &err.to_string()))
-        }
-    };
-    let quote = match quotes.last_quote() {
-        Ok(result) => result,
-        Err(err) => return HttpResponse::BadRequest().body(format!("Quote  {}", &err.to_string())),
+    let name = match provider.search(&symbol).await?.first() {
+        Some(item) => item.long_name.clone(),
+        None => return Err(ProviderError::NotFound(symbol).into()),
     };
 
-    let dividend = match quotes.dividends() {
-        Ok(mut dividends) => match dividends.pop() {
-            Some(result) => result,
-            None => Dividend {
-                amount: 0.0,
-                date: (0000000000),
-            },
-        },
-        Err(err) => {
-            return HttpResponse::BadRequest().body(format!("Dividend  {}", &err.to_string()))
-        }
-    };
+    let quotes = provider.quote_range(&symbol, "1d", "6mo").await?;
+    let quote = quotes.last_quote()?;
+    let dividend = quotes.last_dividend();
 
-    let ticker_view: TickerView = TickerView {
-        id: id,
-        name: name,
+    let ticker_view = TickerQuoteView {
+        id,
+        name,
         symbol: symbol.clone(),
         dividend_value: dividend.amount,
         dividend_date: from_timestamp_to_datetime(dividend.date.to_string()),
@@ -377,213 +1532,279 @@ pub async fn get_latest_ticker_info(id_or_symbol: web::Json<IdOrSymbol>) -> impl
         date: from_timestamp_to_datetime(quote.timestamp.to_string()),
     };
 
-    HttpResponse::Ok().json(ticker_view)
+    Ok(HttpResponse::Ok().json(ticker_view))
 }
 
-pub async fn ticker_search(stock: web::Path<String>) -> impl Responder {
-    let provider = yahoo::YahooConnector::new();
-
-    let results = match provider.search_ticker(stock.as_str()).await {
-        Ok(result) => result,
-        Err(_) => return HttpResponse::BadRequest().body("Ticker does not exist."),
-    };
+pub async fn get_ticker_history(
+    symbol: web::Path<String>,
+    query: web::Query<HistoryQuery>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let symbol = symbol.into_inner();
+
+    let request = query.validate(Utc::now()).map_err(ApiError::Validation)?;
+    let quotes = price_store::history(&data, &symbol, &request).await?;
+
+    let bars: Vec<BarView> = quotes
+        .iter()
+        .map(|quote| BarView {
+            date: from_timestamp_to_datetime(quote.timestamp.to_string()),
+            open: quote.open,
+            high: quote.high,
+            low: quote.low,
+            close: quote.close,
+            adjclose: quote.adjclose,
+            volume: quote.volume,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(HistoryView {
+        symbol,
+        interval: request.interval,
+        bars,
+    }))
+}
 
-    let item = &results.quotes[0];
+pub async fn sync_prices(
+    _admin: AdminUser,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let report = price_store::sync_all(&data).await.map_err(|err| {
+        log::error!("Price sync failed: {}", err);
+        ApiError::Internal(String::from("Price sync failed."))
+    })?;
 
-    let ticker: SearchedTicker = SearchedTicker {
-        symbol: item.symbol.clone(),
-        short_name: item.short_name.clone(),
-        long_name: item.long_name.clone(),
-    };
+    Ok(HttpResponse::Ok().json(report))
+}
 
-    HttpResponse::Ok().json(ticker)
+pub async fn cache_stats(
+    _admin: AdminUser,
+    data: web::Data<infrastructure::state::AppState>,
+) -> impl Responder {
+    HttpResponse::Ok().json(data.static_data.cache_stats.view())
 }
 
-pub async fn ticker_extensive_search(stock: web::Path<String>) -> impl Responder {
-    let provider = yahoo::YahooConnector::new();
+pub async fn ticker_search(
+    stock: web::Path<String>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let results = data.provider().search(stock.as_str()).await?;
+
+    // An empty result is a valid answer from a provider, not an error.
+    let ticker: SearchedTicker = results
+        .into_iter()
+        .next()
+        .ok_or_else(|| ApiError::from(ProviderError::NotFound(stock.to_string())))?;
 
-    let results = match provider.search_ticker(stock.as_str()).await {
-        Ok(result) => result,
-        Err(_) => return HttpResponse::BadRequest().body("Ticker does not exist."),
-    };
+    Ok(HttpResponse::Ok().json(ticker))
+}
 
-    let items = results.quotes;
+pub async fn ticker_extensive_search(
+    stock: web::Path<String>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let tickers: Vec<SearchedTicker> = data.provider().search(stock.as_str()).await?;
 
-    let mut tickers: Vec<SearchedTicker> = Vec::new();
+    Ok(HttpResponse::Ok().json(tickers))
+}
 
-    for item in items {
-        let ticker: SearchedTicker = SearchedTicker {
-            symbol: item.symbol.clone(),
-            short_name: item.short_name.clone(),
-            long_name: item.long_name.clone(),
-        };
+#[derive(serde::Deserialize, IntoParams)]
+pub struct SearchQuery {
+    q: String,
+}
 
-        tickers.push(ticker);
-    }
+pub async fn search_tickers(
+    query: web::Query<SearchQuery>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let tickers: Vec<SearchedTicker> = data.provider().search(query.q.trim()).await?;
 
-    HttpResponse::Ok().json(tickers)
+    Ok(HttpResponse::Ok().json(tickers))
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct PortfolioTickerView {
     id: String,
     name: String,
     symbol: String,
-    open: f64,
-    date: DateTime<Utc>,
+    exchange: String,
+    currency: String,
+    open: Option<f64>,
+    date: Option<DateTime<Utc>>,
+    quantity: f64,
+    cost_basis: f64,
+    market_value: Option<f64>,
+    unrealized_pnl: Option<f64>,
+    realized_pnl: f64,
+    // Set when market data for this row could not be fetched; price fields are then null.
+    error: Option<Problem>,
 }
 
-pub async fn tickers_from_portfolio(
-    portfolio_id: web::Path<String>,
-    data: web::Data<infrastructure::state::AppState>,
-) -> impl Responder {
-    let mut tickers_info: Vec<PortfolioTickerView> = Vec::new();
-
-    let tickers = match crate::models::ticker::Ticker::get_all_from_portfolio(
-        &data.get_connection(),
-        portfolio_id.into_inner(),
-    ) {
-        Ok(results) => results,
-        Err(_) => {
-            return HttpResponse::BadRequest()
-                .body("Portfolio ID does not exist or there is not any ticker.")
-        }
-    };
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PortfolioSummary {
+    id: String,
+    cost_basis_method: CostBasisMethod,
+    cost_basis: f64,
+    market_value: f64,
+    unrealized_pnl: f64,
+    realized_pnl: f64,
+    // Open positions without a price; they are left out of the totals above.
+    unpriced: Vec<UnpricedTicker>,
+}
 
-    let names = match get_stocks_name(&tickers).await {
-        Ok(result) => result,
-        Err(err) => {
-            return HttpResponse::BadRequest().body(format!("Ticker names: {}", &err.to_string()))
-        }
-    };
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UnpricedTicker {
+    id: String,
+    symbol: String,
+    error: Problem,
+}
 
-    let quotes = match get_stocks_values(&tickers, None, None).await {
-        Ok(result) => result,
-        Err(err) => {
-            return HttpResponse::BadRequest().body(format!("Ticker values: {}", &err.to_string()))
-        }
-    };
-    /*
-    let dividends = match get_stocks_dividends(&tickers, None, None).await {
-        Ok(result) => result,
-        Err(err) => return HttpResponse::BadRequest().body(format!("Ticker dividend: {}", &err.to_string())),
-    };
-    */
+/// Runs the portfolio's ledger through its cost basis method.
+fn portfolio_costs(
+    data: &infrastructure::state::AppState,
+    portfolio: &Portfolio,
+) -> Result<Vec<PositionCost>, ApiError> {
+    let ledger = Transaction::get_all_from_portfolio(&data.get_connection(), &portfolio.id)?;
 
-    for i in 0..tickers.len() {
-        let info = PortfolioTickerView {
-            id: tickers[i].id.clone(),
-            name: names[i].clone(),
-            symbol: tickers[i].name.clone(),
-            open: quotes[i].open,
-            date: from_timestamp_to_datetime(quotes[i].timestamp.to_string()),
-        };
+    accounting::cost_basis(&ledger, portfolio.cost_basis_method()).map_err(oversold)
+}
 
-        tickers_info.push(info);
+fn position_cost(costs: &[PositionCost], ticker_id: &str) -> PositionCost {
+    match costs.iter().find(|cost| cost.ticker_id == ticker_id) {
+        Some(cost) => cost.clone(),
+        None => PositionCost {
+            ticker_id: ticker_id.to_string(),
+            quantity: 0.0,
+            cost_basis: 0.0,
+            realized_pnl: 0.0,
+        },
     }
-
-    HttpResponse::Ok().json(tickers_info)
 }
 
-pub async fn get_stocks_name(tickers: &Vec<Ticker>) -> Result<Vec<String>, YahooError> {
-    let mut tickers_name: Vec<String> = Vec::new();
+pub async fn tickers_from_portfolio(
+    user: AuthenticatedUser,
+    portfolio_id: web::Path<String>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let portfolio = owned_portfolio(&data, &user, &portfolio_id)?;
+    let rows = Ticker::get_all_with_instruments(&data.get_connection(), &portfolio.id)?;
 
-    let provider = yahoo::YahooConnector::new();
+    Ok(HttpResponse::Ok().json(portfolio_ticker_views(&data, &portfolio, rows).await?))
+}
 
-    for ticker in tickers {
-        let name = match provider.search_ticker(&ticker.name).await {
-            Ok(result) => match result.quotes.first() {
-                Some(item) => item.long_name.clone(),
-                None => return Err(YahooError::EmptyDataSet),
-            },
-            Err(err) => return Err(err),
-        };
+pub async fn list_portfolio_tickers(
+    user: AuthenticatedUser,
+    portfolio_id: web::Path<String>,
+    query: web::Query<PageQuery>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let options = query.options(user.role == Role::Admin)?;
+    let portfolio = owned_portfolio(&data, &user, &portfolio_id)?;
+    let (rows, total) =
+        Ticker::page_with_instruments(&data.get_connection(), &portfolio.id, &options)?;
+    let tickers = portfolio_ticker_views(&data, &portfolio, rows).await?;
+
+    Ok(HttpResponse::Ok().json(Page::new(tickers, total, &options)))
+}
 
-        tickers_name.push(name);
-    }
+/// Prices the tickers and adds the portfolio's cost basis for each of them.
+async fn portfolio_ticker_views(
+    data: &infrastructure::state::AppState,
+    portfolio: &Portfolio,
+    rows: Vec<(Ticker, Instrument)>,
+) -> Result<Vec<PortfolioTickerView>, ApiError> {
+    let mut tickers_info: Vec<PortfolioTickerView> = Vec::new();
 
-    return Ok(tickers_name);
-}
+    let costs = portfolio_costs(data, portfolio)?;
 
-pub async fn get_stocks_values(
-    tickers: &Vec<Ticker>,
-    interval: Option<&str>,
-    range: Option<&str>,
-) -> Result<Vec<Quote>, YahooError> {
-    let mut tickers_values: Vec<Quote> = Vec::new();
+    let tickers: Vec<Ticker> = rows.iter().map(|(ticker, _)| ticker.clone()).collect();
+    let quotes = price_store::latest_quotes(data, &tickers).await;
 
-    let provider = yahoo::YahooConnector::new();
+    for ((ticker, instrument), quote) in rows.into_iter().zip(quotes) {
+        let cost = position_cost(&costs, &ticker.id);
+        let (quote, error) = match quote {
+            Ok(quote) => (Some(quote), None),
+            Err(err) => (None, Some(ApiError::from(err).problem())),
+        };
 
-    for ticker in tickers {
-        let quote = match provider
-            .get_quote_range(
-                &ticker.name,
-                interval.unwrap_or("1d"),
-                range.unwrap_or("6mo"),
-            )
-            .await
-        {
-            Ok(quotes) => match quotes.last_quote() {
-                Ok(result) => result,
-                Err(err) => return Err(err),
-            },
-            Err(err) => return Err(err),
+        let info = PortfolioTickerView {
+            id: ticker.id,
+            name: instrument.long_name,
+            symbol: ticker.name,
+            exchange: instrument.exchange,
+            currency: instrument.currency,
+            open: quote.as_ref().map(|quote| quote.open),
+            date: quote
+                .as_ref()
+                .map(|quote| from_timestamp_to_datetime(quote.timestamp.to_string())),
+            quantity: cost.quantity,
+            cost_basis: cost.cost_basis,
+            market_value: quote.as_ref().map(|quote| cost.market_value(quote.close)),
+            unrealized_pnl: quote.as_ref().map(|quote| cost.unrealized_pnl(quote.close)),
+            realized_pnl: cost.realized_pnl,
+            error,
         };
 
-        tickers_values.push(quote);
+        tickers_info.push(info);
     }
 
-    return Ok(tickers_values);
+    Ok(tickers_info)
 }
 
-pub async fn get_stocks_dividends(
-    tickers: &Vec<Ticker>,
-    interval: Option<&str>,
-    range: Option<&str>,
-) -> Result<Vec<Dividend>, YahooError> {
-    let mut tickers_dividend: Vec<Dividend> = Vec::new();
-
-    let provider = yahoo::YahooConnector::new();
-
-    for ticker in tickers {
-        let quotes = match provider
-            .get_quote_range(
-                &ticker.name,
-                interval.unwrap_or("1d"),
-                range.unwrap_or("6mo"),
-            )
-            .await
-        {
-            Ok(result) => result,
-            Err(err) => return Err(err),
-        };
+pub async fn portfolio_summary(
+    user: AuthenticatedUser,
+    portfolio_id: web::Path<String>,
+    data: web::Data<infrastructure::state::AppState>,
+) -> HandlerResult {
+    let portfolio = owned_portfolio(&data, &user, &portfolio_id)?;
+    let tickers = Ticker::get_all_from_portfolio(&data.get_connection(), portfolio.id.clone())?;
+    let costs = portfolio_costs(&data, &portfolio)?;
+
+    // Only open positions need a price; closed ones contribute realized P&L alone.
+    let held: Vec<Ticker> = tickers
+        .into_iter()
+        .filter(|ticker| position_cost(&costs, &ticker.id).quantity > 0.0)
+        .collect();
+
+    let quotes = price_store::latest_quotes(&data, &held).await;
+
+    let mut summary = PortfolioSummary {
+        id: portfolio.id.clone(),
+        cost_basis_method: portfolio.cost_basis_method(),
+        cost_basis: 0.0,
+        market_value: 0.0,
+        unrealized_pnl: 0.0,
+        realized_pnl: costs.iter().map(|cost| cost.realized_pnl).sum(),
+        unpriced: Vec::new(),
+    };
 
-        let dividend = match quotes.dividends() {
-            Ok(mut dividends) => match dividends.pop() {
-                Some(result) => result,
-                None => Dividend {
-                    amount: 0.0,
-                    date: (0000000000),
-                },
-            },
-            Err(err) => return Err(err),
+    for (ticker, quote) in held.into_iter().zip(quotes) {
+        let quote = match quote {
+            Ok(quote) => quote,
+            Err(err) => {
+                summary.unpriced.push(UnpricedTicker {
+                    id: ticker.id,
+                    symbol: ticker.name,
+                    error: ApiError::from(err).problem(),
+                });
+                continue;
+            }
         };
-
-        tickers_dividend.push(dividend);
+        let cost = position_cost(&costs, &ticker.id);
+        summary.cost_basis += cost.cost_basis;
+        summary.market_value += cost.market_value(quote.close);
+        summary.unrealized_pnl += cost.unrealized_pnl(quote.close);
     }
 
-    return Ok(tickers_dividend);
+    Ok(HttpResponse::Ok().json(summary))
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
 
-    use super::{
-        from_timestamp_to_datetime, get_stocks_dividends, get_stocks_name, get_stocks_values,
-        verify_email, verify_password, Ticker,
-    };
+    use super::{from_timestamp_to_datetime, verify_email, verify_password};
     use chrono::{DateTime, NaiveDate, Utc};
-    use yahoo_finance_api::Quote;
 
     #[test]
     fn test_verify_email_valid() {
@@ -702,152 +1923,4 @@ mod tests {
 
         assert_eq!(from_timestamp_to_datetime(timestamp_string), datetime);
     }
-
-    #[test]
-    fn test_get_stocks_name_valid() {
-        let ticker1: Ticker = Ticker::new(String::from("AAPL"), String::from("12345678"));
-        let ticker2: Ticker = Ticker::new(String::from("GS"), String::from("87654321"));
-        let tickers: Vec<Ticker> = vec![ticker1, ticker2];
-
-        let names: Vec<String> = vec![
-            String::from("Apple Inc."),
-            String::from("The Goldman Sachs Group, Inc."),
-        ];
-
-        let result = futures::executor::block_on(get_stocks_name(&tickers)).unwrap();
-
-        assert_eq!(result, names);
-    }
-
-    #[test]
-    fn test_get_stocks_name_invalid() {
-        // APPL does not exists, but he found some other tickers and first is AAPL
-        let ticker1: Ticker = Ticker::new(String::from("APPL"), String::from("12345678"));
-        let ticker2: Ticker = Ticker::new(String::from("GS"), String::from("87654321"));
-        let tickers: Vec<Ticker> = vec![ticker1, ticker2];
-
-        let names: Vec<String> = vec![String::from("The Goldman Sachs Group, Inc.")];
-
-        let result = futures::executor::block_on(get_stocks_name(&tickers)).unwrap();
-
-        assert_ne!(result, names);
-    }
-
-    #[test]
-    fn test_get_stocks_name_invalid2() {
-        // APPP does not exists
-        let ticker1: Ticker = Ticker::new(String::from("APPP"), String::from("12345678"));
-        let ticker2: Ticker = Ticker::new(String::from("GS"), String::from("87654321"));
-        let tickers: Vec<Ticker> = vec![ticker1, ticker2];
-
-        let result = futures::executor::block_on(get_stocks_name(&tickers));
-
-        assert!(result.is_err());
-    }
-
-    #[test]
-    fn test_get_stocks_values_valid() {
-        let provider = yahoo_finance_api::YahooConnector::new();
-
-        let mut quotes: Vec<Quote> = Vec::new();
-        let quote = match futures::executor::block_on(provider.get_quote_range("MSFT", "1d", "6mo"))
-            .unwrap()
-            .last_quote()
-        {
-            Ok(result) => result,
-            Err(err) => panic!("{:?}", err),
-        };
-        quotes.push(quote);
-        let quote = match futures::executor::block_on(provider.get_quote_range("IBM", "1d", "6mo"))
-            .unwrap()
-            .last_quote()
-        {
-            Ok(result) => result,
-            Err(err) => panic!("{:?}", err),
-        };
-        quotes.push(quote);
-
-        let ticker1: Ticker = Ticker::new(String::from("MSFT"), String::from("12345678"));
-        let ticker2: Ticker = Ticker::new(String::from("IBM"), String::from("87654321"));
-        let tickers: Vec<Ticker> = vec![ticker1, ticker2];
-
-        let result = futures::executor::block_on(get_stocks_values(&tickers, None, None)).unwrap();
-
-        assert_eq!(result, quotes);
-    }
-
-    #[test]
-    fn test_get_stocks_values_invalid() {
-        let provider = yahoo_finance_api::YahooConnector::new();
-
-        let mut quotes: Vec<Quote> = Vec::new();
-        let quote = match futures::executor::block_on(provider.get_quote_range("MSFT", "1d", "6mo"))
-            .unwrap()
-            .last_quote()
-        {
-            Ok(result) => result,
-            Err(err) => panic!("{:?}", err),
-        };
-        quotes.push(quote);
-        let quote = match futures::executor::block_on(provider.get_quote_range("IBM", "1d", "6mo"))
-            .unwrap()
-            .last_quote()
-        {
-            Ok(result) => result,
-            Err(err) => panic!("{:?}", err),
-        };
-        quotes.push(quote);
-
-        // IBMZ does not exists
-        let ticker1: Ticker = Ticker::new(String::from("MSFT"), String::from("12345678"));
-        let ticker2: Ticker = Ticker::new(String::from("IBMZ"), String::from("87654321"));
-        let tickers: Vec<Ticker> = vec![ticker1, ticker2];
-
-        let result = futures::executor::block_on(get_stocks_values(&tickers, None, None));
-
-        assert!(result.is_err());
-    }
-
-    #[test]
-    fn test_get_stocks_dividends_valid() {
-        let provider = yahoo_finance_api::YahooConnector::new();
-
-        let dividend =
-            match futures::executor::block_on(provider.get_quote_range("MSFT", "1d", "6mo"))
-                .unwrap()
-                .dividends()
-            {
-                Ok(dividends) => dividends.clone().pop().unwrap(),
-                Err(err) => panic!("{:?}", err),
-            };
-
-        let ticker: Ticker = Ticker::new(String::from("MSFT"), String::from("12345678"));
-        let tickers: Vec<Ticker> = vec![ticker];
-
-        let result = futures::executor::block_on(get_stocks_dividends(&tickers, None, None))
-            .unwrap()
-            .pop()
-            .unwrap();
-
-        let equal: bool;
-
-        if result.amount == dividend.amount && result.date == dividend.date {
-            equal = true;
-        } else {
-            equal = false;
-        };
-
-        assert!(equal);
-    }
-
-    #[test]
-    fn test_get_stocks_dividends_invalid() {
-        // IBMZ does not exists
-        let ticker: Ticker = Ticker::new(String::from("IBMZ"), String::from("12345678"));
-        let tickers: Vec<Ticker> = vec![ticker];
-
-        let result = futures::executor::block_on(get_stocks_dividends(&tickers, None, None));
-
-        assert!(result.is_err());
-    }
 }
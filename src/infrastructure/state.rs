@@ -1,15 +1,30 @@
+use crate::infrastructure::config::{
+    Config, DatabaseConfig, MailConfig, MailTransport, MarketDataSource,
+};
+use crate::infrastructure::keys::KeySet;
+use crate::infrastructure::mailer::{FileMailer, Mailer, MemoryMailer, SmtpMailer};
+use crate::infrastructure::market_data::{MarketDataProvider, MockProvider, YahooProvider};
+use crate::infrastructure::quote_cache::{CacheStats, CachedProvider};
+use crate::infrastructure::session::SessionSettings;
+use crate::infrastructure::throttle::LoginPolicy;
 use diesel::pg::PgConnection;
 use diesel::r2d2::ConnectionManager;
-use dotenv::dotenv;
 use r2d2::Pool;
-use std::env;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 pub type DbConnection = r2d2::PooledConnection<ConnectionManager<PgConnection>>;
 
 pub struct StaticData {
+    pub config: Config,
     pub db: DbPool,
+    pub provider: Arc<dyn MarketDataProvider>,
+    pub cache_stats: Arc<CacheStats>,
+    pub mailer: Arc<dyn Mailer>,
+    pub login_policy: LoginPolicy,
+    pub keys: KeySet,
+    pub session_settings: SessionSettings,
 }
 
 #[derive(Clone)]
@@ -18,28 +33,105 @@ pub struct AppState {
 }
 
 impl AppState {
+    pub fn config(&self) -> &Config {
+        &self.static_data.config
+    }
+
     pub fn get_connection(&self) -> DbConnection {
         self.static_data
             .db
             .get()
             .expect("Failed to retrieve DB connection from pool")
     }
+
+    pub fn provider(&self) -> &dyn MarketDataProvider {
+        self.static_data.provider.as_ref()
+    }
+
+    pub fn mailer(&self) -> &dyn Mailer {
+        self.static_data.mailer.as_ref()
+    }
+
+    pub fn login_policy(&self) -> &LoginPolicy {
+        &self.static_data.login_policy
+    }
+
+    pub fn keys(&self) -> &KeySet {
+        &self.static_data.keys
+    }
+
+    pub fn session_settings(&self) -> &SessionSettings {
+        &self.static_data.session_settings
+    }
 }
 
-pub fn initialize() -> AppState {
-    let db_pool = get_connection_pool();
+pub fn initialize(config: Config) -> AppState {
+    // Checked first: without a signing key no token could be issued or trusted.
+    let keys = KeySet::from_config(&config.jwt).unwrap_or_else(|e| panic!("{}", e));
+    let db_pool = get_connection_pool(&config.database);
+    let cache_stats = Arc::new(CacheStats::default());
+    let provider = CachedProvider::new(
+        get_market_data_provider(config.provider.source),
+        config.provider.cache_ttls(),
+        cache_stats.clone(),
+    );
 
     AppState {
-        static_data: Arc::new(StaticData { db: db_pool }),
+        static_data: Arc::new(StaticData {
+            db: db_pool,
+            provider: Arc::new(provider),
+            cache_stats,
+            mailer: get_mailer(&config.mail),
+            login_policy: LoginPolicy::from_config(&config.login),
+            keys,
+            session_settings: SessionSettings::from_config(&config.session),
+            config,
+        }),
     }
 }
 
-pub fn get_connection_pool() -> DbPool {
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
+pub fn get_connection_pool(config: &DatabaseConfig) -> DbPool {
+    let manager = ConnectionManager::<PgConnection>::new(&config.url);
+    let idle_timeout = match config.idle_timeout_seconds {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    };
 
     Pool::builder()
+        .max_size(config.pool_size)
+        .connection_timeout(Duration::from_secs(config.connection_timeout_seconds))
+        .idle_timeout(idle_timeout)
         .build(manager)
         .expect("Failed to create database connection pool.")
 }
+
+pub fn get_market_data_provider(source: MarketDataSource) -> Arc<dyn MarketDataProvider> {
+    match source {
+        MarketDataSource::Mock => Arc::new(MockProvider::with_sample_data()),
+        MarketDataSource::Yahoo => Arc::new(YahooProvider::new()),
+    }
+}
+
+/// Outgoing mail delivery; `file` drops messages into `dir` for development.
+pub fn get_mailer(config: &MailConfig) -> Arc<dyn Mailer> {
+    match config.transport {
+        MailTransport::Smtp => {
+            let credentials = config
+                .smtp_username
+                .clone()
+                .zip(config.smtp_password.clone());
+
+            Arc::new(
+                SmtpMailer::new(
+                    &config.smtp_host,
+                    config.smtp_port,
+                    &config.from,
+                    credentials,
+                )
+                .expect("Failed to configure the SMTP mailer"),
+            )
+        }
+        MailTransport::Memory => Arc::new(MemoryMailer::default()),
+        MailTransport::File => Arc::new(FileMailer::new(config.dir.clone())),
+    }
+}
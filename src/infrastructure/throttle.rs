@@ -0,0 +1,230 @@
+use crate::infrastructure::config::LoginConfig;
+use crate::infrastructure::error::ApiError;
+use crate::models::login_event::{Failures, LoginEvent};
+
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::HttpRequest;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use std::net::SocketAddr;
+
+/// Limits on failed sign-ins. Past `backoff_after` failures each attempt waits
+/// twice as long as the previous one, capped at `max_backoff`; at
+/// `lockout_after` failures the account (or address) is locked for `lockout`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoginPolicy {
+    pub backoff_after: i64,
+    pub max_backoff: Duration,
+    pub lockout_after: i64,
+    pub ip_lockout_after: i64,
+    pub lockout: Duration,
+}
+
+impl Default for LoginPolicy {
+    fn default() -> LoginPolicy {
+        LoginPolicy {
+            backoff_after: 3,
+            max_backoff: Duration::minutes(5),
+            lockout_after: 10,
+            ip_lockout_after: 50,
+            lockout: Duration::minutes(15),
+        }
+    }
+}
+
+impl LoginPolicy {
+    pub fn from_config(config: &LoginConfig) -> LoginPolicy {
+        LoginPolicy {
+            backoff_after: config.backoff_after,
+            lockout_after: config.lockout_after,
+            ip_lockout_after: config.ip_lockout_after,
+            lockout: Duration::minutes(config.lockout_minutes),
+            ..LoginPolicy::default()
+        }
+    }
+
+    /// How long the caller still has to wait, if at all, given the failures so far.
+    pub fn retry_after(
+        &self,
+        failures: Failures,
+        lockout_after: i64,
+        now: NaiveDateTime,
+    ) -> Option<Duration> {
+        let last = failures.last?;
+
+        let wait = if failures.count >= lockout_after {
+            self.lockout
+        } else if failures.count >= self.backoff_after {
+            let doublings = (failures.count - self.backoff_after).min(20) as u32;
+            std::cmp::min(Duration::seconds(1 << doublings), self.max_backoff)
+        } else {
+            return None;
+        };
+
+        let remaining = last + wait - now;
+        if remaining > Duration::zero() {
+            Some(remaining)
+        } else {
+            None
+        }
+    }
+}
+
+/// Where a sign-in attempt came from. The IP is the TCP peer: forwarding
+/// headers are not trusted since anyone can set them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &HttpRequest) -> ClientInfo {
+        ClientInfo::from_parts(req.peer_addr(), req.headers())
+    }
+
+    pub fn from_parts(peer_addr: Option<SocketAddr>, headers: &HeaderMap) -> ClientInfo {
+        ClientInfo {
+            ip: peer_addr.map(|addr| addr.ip().to_string()),
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|agent| agent.to_str().ok())
+                .map(String::from),
+        }
+    }
+}
+
+/// Refuses the attempt while the account or the client address is backing off
+/// or locked out. Checked before the password so throttled guesses learn nothing.
+pub fn check(
+    connection: &PgConnection,
+    policy: &LoginPolicy,
+    email: &str,
+    client: &ClientInfo,
+) -> Result<(), ApiError> {
+    let now = Utc::now().naive_utc();
+    let since = now - policy.lockout;
+
+    let mut wait = policy.retry_after(
+        LoginEvent::account_failures(connection, email, since)?,
+        policy.lockout_after,
+        now,
+    );
+    if let Some(ip) = &client.ip {
+        let ip_wait = policy.retry_after(
+            LoginEvent::ip_failures(connection, ip, since)?,
+            policy.ip_lockout_after,
+            now,
+        );
+        wait = std::cmp::max(wait, ip_wait);
+    }
+
+    match wait {
+        Some(wait) => {
+            let seconds = std::cmp::max(wait.num_seconds(), 1) as u64;
+            Err(ApiError::RateLimited(
+                format!(
+                    "Too many failed sign-in attempts. Try again in {} seconds.",
+                    seconds
+                ),
+                seconds,
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Adds the attempt to the audit trail. Failing to record must not fail the sign-in itself.
+pub fn record(
+    connection: &PgConnection,
+    user_id: Option<String>,
+    email: &str,
+    client: &ClientInfo,
+    method: &str,
+    success: bool,
+) {
+    let event = LoginEvent::new(
+        user_id,
+        email,
+        client.ip.clone(),
+        client.user_agent.clone(),
+        method,
+        success,
+    );
+
+    if let Err(e) = LoginEvent::record(connection, &event) {
+        log::error!("Could not record login event: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LoginPolicy;
+    use crate::models::login_event::Failures;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_no_wait_below_backoff_threshold() {
+        let policy = LoginPolicy::default();
+        let now = Utc::now().naive_utc();
+
+        let failures = Failures {
+            count: 2,
+            last: Some(now),
+        };
+
+        assert_eq!(
+            policy.retry_after(failures, policy.lockout_after, now),
+            None
+        );
+        assert_eq!(
+            policy.retry_after(
+                Failures {
+                    count: 0,
+                    last: None
+                },
+                policy.lockout_after,
+                now
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        let policy = LoginPolicy::default();
+        let now = Utc::now().naive_utc();
+
+        let wait = |count| {
+            policy.retry_after(
+                Failures {
+                    count,
+                    last: Some(now),
+                },
+                policy.lockout_after,
+                now,
+            )
+        };
+
+        assert_eq!(wait(3), Some(Duration::seconds(1)));
+        assert_eq!(wait(4), Some(Duration::seconds(2)));
+        assert_eq!(wait(6), Some(Duration::seconds(8)));
+        assert_eq!(wait(10), Some(policy.lockout));
+    }
+
+    #[test]
+    fn test_backoff_elapses() {
+        let policy = LoginPolicy::default();
+        let now = Utc::now().naive_utc();
+
+        let failures = Failures {
+            count: 4,
+            last: Some(now - Duration::seconds(5)),
+        };
+
+        assert_eq!(
+            policy.retry_after(failures, policy.lockout_after, now),
+            None
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use crate::models::accounting::CostBasisMethod;
+use crate::models::api_key::ApiKey;
+use crate::models::login_event::LoginEvent;
+use crate::models::portfolio::Portfolio;
+use crate::models::role::Role;
+use crate::models::ticker::Ticker;
+use crate::models::transaction::Transaction;
+use crate::models::user::User;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// What clients see of an account. Password hashes, TOTP secrets and
+/// bookkeeping columns never leave the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct UserView {
+    pub id: String,
+    pub email: String,
+    pub role: Role,
+    pub email_verified: bool,
+    pub totp_enabled: bool,
+}
+
+impl From<&User> for UserView {
+    fn from(user: &User) -> UserView {
+        UserView {
+            id: user.id.clone(),
+            email: user.email.clone(),
+            role: user.role(),
+            email_verified: user.email_verified,
+            totp_enabled: user.totp_enabled,
+        }
+    }
+}
+
+impl From<User> for UserView {
+    fn from(user: User) -> UserView {
+        UserView::from(&user)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct PortfolioView {
+    pub id: String,
+    pub name: String,
+    pub created_at: NaiveDate,
+    pub user_id: String,
+    pub cost_basis_method: CostBasisMethod,
+}
+
+impl From<Portfolio> for PortfolioView {
+    fn from(portfolio: Portfolio) -> PortfolioView {
+        PortfolioView {
+            cost_basis_method: portfolio.cost_basis_method(),
+            id: portfolio.id,
+            name: portfolio.name,
+            created_at: portfolio.created_at,
+            user_id: portfolio.user_id,
+        }
+    }
+}
+
+/// A ticker held in a portfolio; `name` is its symbol.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct TickerView {
+    pub id: String,
+    pub name: String,
+    pub portfolio_id: String,
+}
+
+impl From<Ticker> for TickerView {
+    fn from(ticker: Ticker) -> TickerView {
+        TickerView {
+            id: ticker.id,
+            name: ticker.name,
+            portfolio_id: ticker.portfolio_id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct TransactionView {
+    pub id: String,
+    pub portfolio_id: String,
+    pub ticker_id: String,
+    pub kind: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub fees: f64,
+    pub trade_date: NaiveDate,
+}
+
+impl From<Transaction> for TransactionView {
+    fn from(transaction: Transaction) -> TransactionView {
+        TransactionView {
+            id: transaction.id,
+            portfolio_id: transaction.portfolio_id,
+            ticker_id: transaction.ticker_id,
+            kind: transaction.kind,
+            quantity: transaction.quantity,
+            price: transaction.price,
+            fees: transaction.fees,
+            trade_date: transaction.trade_date,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct ApiKeyView {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl From<ApiKey> for ApiKeyView {
+    fn from(api_key: ApiKey) -> ApiKeyView {
+        ApiKeyView {
+            id: api_key.id,
+            name: api_key.name,
+            prefix: api_key.prefix,
+            scopes: api_key.scopes,
+            expires_at: api_key.expires_at,
+            last_used_at: api_key.last_used_at,
+            created_at: api_key.created_at,
+            revoked_at: api_key.revoked_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct LoginEventView {
+    pub id: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub method: String,
+    pub success: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<LoginEvent> for LoginEventView {
+    fn from(event: LoginEvent) -> LoginEventView {
+        LoginEventView {
+            id: event.id,
+            ip: event.ip,
+            user_agent: event.user_agent,
+            method: event.method,
+            success: event.success,
+            created_at: event.created_at,
+        }
+    }
+}
+
+/// Converts every row of a query result.
+pub fn all<T, V: From<T>>(rows: Vec<T>) -> Vec<V> {
+    rows.into_iter().map(V::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiKeyView, LoginEventView, PortfolioView, TickerView, TransactionView, UserView};
+    use crate::infrastructure::setup::{CreatedApiKey, DeletedPortfolio};
+    use crate::models::accounting::CostBasisMethod;
+    use crate::models::api_key::{ApiKey, Scope};
+    use crate::models::login_event::LoginEvent;
+    use crate::models::portfolio::Portfolio;
+    use crate::models::ticker::Ticker;
+    use crate::models::transaction::Transaction;
+    use crate::models::user::User;
+    use chrono::NaiveDate;
+    use serde_json::Value;
+
+    fn field_names(value: &Value, names: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                for (name, nested) in map {
+                    names.push(name.clone());
+                    field_names(nested, names);
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| field_names(item, names)),
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn test_no_response_type_has_a_password_field() {
+        let mut user = User::new(String::from("user@example.com"), String::from("$argon2id$"));
+        user.totp_secret = Some(String::from("SECRET"));
+        let date = NaiveDate::from_ymd(2022, 9, 1);
+        let portfolio = || Portfolio {
+            id: String::from("portfolio"),
+            name: String::from("Main"),
+            created_at: date,
+            is_deleted: false,
+            user_id: user.id.clone(),
+            cost_basis_method: String::from(CostBasisMethod::Fifo.as_str()),
+        };
+        let ticker = Ticker {
+            id: String::from("ticker"),
+            name: String::from("AAPL"),
+            portfolio_id: portfolio().id,
+            is_deleted: false,
+            created_at: date.and_hms(9, 30, 0),
+        };
+        let transaction = Transaction {
+            id: String::from("transaction"),
+            portfolio_id: portfolio().id,
+            ticker_id: ticker.id.clone(),
+            kind: String::from("buy"),
+            quantity: 1.0,
+            price: 100.0,
+            fees: 0.0,
+            trade_date: date,
+            is_deleted: false,
+            created_at: date.and_hms(9, 30, 0),
+        };
+        let (api_key, key) = ApiKey::new(&user.id, "script", &[Scope::PortfoliosRead], None);
+        let event = LoginEvent::new(
+            Some(user.id.clone()),
+            &user.email,
+            None,
+            None,
+            "password",
+            true,
+        );
+
+        let responses = vec![
+            serde_json::to_value(UserView::from(&user)).unwrap(),
+            serde_json::to_value(PortfolioView::from(portfolio())).unwrap(),
+            serde_json::to_value(TickerView::from(ticker.clone())).unwrap(),
+            serde_json::to_value(TransactionView::from(transaction)).unwrap(),
+            serde_json::to_value(ApiKeyView::from(api_key.clone())).unwrap(),
+            serde_json::to_value(LoginEventView::from(event)).unwrap(),
+            serde_json::to_value(CreatedApiKey {
+                key,
+                api_key: ApiKeyView::from(api_key),
+            })
+            .unwrap(),
+            serde_json::to_value(DeletedPortfolio {
+                portfolio: PortfolioView::from(portfolio()),
+                tickers: vec![TickerView::from(ticker)],
+            })
+            .unwrap(),
+        ];
+
+        for response in responses {
+            let mut names = Vec::new();
+            field_names(&response, &mut names);
+
+            for forbidden in ["password", "totp_secret", "secret_hash", "is_deleted"] {
+                assert!(
+                    !names.iter().any(|name| name.contains(forbidden)),
+                    "{} leaks '{}'",
+                    response,
+                    forbidden
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_user_view() {
+        let user = User::new(String::from("user@example.com"), String::from("hash"));
+        let view = UserView::from(&user);
+
+        assert_eq!(view.id, user.id);
+        assert_eq!(view.email, "user@example.com");
+        assert!(!view.email_verified);
+    }
+}
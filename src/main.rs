@@ -1,3 +1,6 @@
+// Diesel 1.x derives expand to impl blocks nested inside consts.
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate diesel;
 extern crate dotenv;
@@ -10,26 +13,78 @@ pub mod models;
 pub mod schema;
 
 use actix_cors::Cors;
-fn setup_cors() -> Cors {
-    Cors::default()
-        .send_wildcard()
-        .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+use infrastructure::config::{Config, CorsConfig};
+
+/// Listed origins may use cookie sessions: credentials are allowed and the CSRF
+/// token issued at sign-in is readable. A wildcard cannot carry credentials, so
+/// with `*` cookie sessions only work for same-origin pages.
+fn setup_cors(config: &CorsConfig) -> Cors {
+    let cors = if config.allows_any_origin() {
+        Cors::default().allow_any_origin().send_wildcard()
+    } else {
+        config
+            .allowed_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .supports_credentials()
+            .expose_headers(vec![infrastructure::session::CSRF_HEADER])
+    };
+
+    cors.allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
         .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
         .allowed_header(http::header::CONTENT_TYPE)
-        .max_age(3600)
+        .allowed_header(infrastructure::session::CSRF_HEADER)
+        .max_age(config.max_age_seconds)
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| {
+    let config = Config::load().unwrap_or_else(|e| panic!("{}", e));
+    env_logger::Builder::new()
+        .filter_level(config.server.log_level)
+        .init();
+    let bind = config.server.bind;
+    let workers = config.server.workers;
+
+    // One state for every worker so the pool and the quote cache are shared.
+    let state = infrastructure::state::initialize(config);
+
+    let sync_every = state.config().provider.sync_interval();
+    if !sync_every.is_zero() {
+        actix_web::rt::spawn(infrastructure::price_store::run_sync_loop(
+            state.clone(),
+            sync_every,
+        ));
+    }
+
+    let mut server = HttpServer::new(move || {
+        let config = state.config();
+        let sessions = state.session_settings();
         App::new()
-            .app_data(web::Data::new(infrastructure::state::initialize()))
+            .app_data(web::Data::new(state.clone()))
+            // Identity reads the session, so the session middleware is registered after it.
+            .wrap(sessions.identity_middleware())
+            .wrap(sessions.session_middleware())
             .wrap(Logger::default())
-            .app_data(web::JsonConfig::default().limit(4096))
-            .wrap(setup_cors())
-            .service(web::scope("/").configure(infrastructure::routes::setup_routes))
-    })
-    .bind(("127.0.0.1", 8080))?
-    .run()
-    .await
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(config.server.json_limit_bytes)
+                    .error_handler(|err, _| infrastructure::error::extractor_error(err)),
+            )
+            .app_data(
+                web::PathConfig::default()
+                    .error_handler(|err, _| infrastructure::error::extractor_error(err)),
+            )
+            .app_data(
+                web::QueryConfig::default()
+                    .error_handler(|err, _| infrastructure::error::extractor_error(err)),
+            )
+            .wrap(setup_cors(&config.cors))
+            .configure(infrastructure::routes::configure)
+    });
+    if let Some(workers) = workers {
+        server = server.workers(workers);
+    }
+
+    server.bind(bind)?.run().await
 }
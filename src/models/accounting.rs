@@ -0,0 +1,287 @@
+use crate::models::transaction::Transaction;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+const QUANTITY_EPSILON: f64 = 1e-9;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CostBasisMethod {
+    Fifo,
+    Lifo,
+    AverageCost,
+}
+
+impl CostBasisMethod {
+    pub fn parse(value: &str) -> Option<CostBasisMethod> {
+        match value {
+            "fifo" => Some(CostBasisMethod::Fifo),
+            "lifo" => Some(CostBasisMethod::Lifo),
+            "average_cost" => Some(CostBasisMethod::AverageCost),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CostBasisMethod::Fifo => "fifo",
+            CostBasisMethod::Lifo => "lifo",
+            CostBasisMethod::AverageCost => "average_cost",
+        }
+    }
+}
+
+/// Shares bought in a single trade that have not been sold yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lot {
+    pub quantity: f64,
+    pub unit_cost: f64,
+    pub trade_date: NaiveDate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PositionCost {
+    pub ticker_id: String,
+    pub quantity: f64,
+    pub cost_basis: f64,
+    pub realized_pnl: f64,
+}
+
+impl PositionCost {
+    pub fn market_value(&self, price: f64) -> f64 {
+        self.quantity * price
+    }
+
+    pub fn unrealized_pnl(&self, price: f64) -> f64 {
+        self.market_value(price) - self.cost_basis
+    }
+}
+
+struct LotBook {
+    ticker_id: String,
+    lots: Vec<Lot>,
+    realized_pnl: f64,
+}
+
+impl LotBook {
+    fn quantity(&self) -> f64 {
+        self.lots.iter().map(|lot| lot.quantity).sum()
+    }
+
+    fn cost_basis(&self) -> f64 {
+        self.lots
+            .iter()
+            .map(|lot| lot.quantity * lot.unit_cost)
+            .sum()
+    }
+
+    fn buy(&mut self, transaction: &Transaction, method: CostBasisMethod) {
+        // Buy fees are capitalised into the cost of the shares they bought.
+        let lot = Lot {
+            quantity: transaction.quantity,
+            unit_cost: (transaction.quantity * transaction.price + transaction.fees)
+                / transaction.quantity,
+            trade_date: transaction.trade_date,
+        };
+
+        if method == CostBasisMethod::AverageCost && !self.lots.is_empty() {
+            let quantity = self.quantity() + lot.quantity;
+            let cost = self.cost_basis() + lot.quantity * lot.unit_cost;
+            let trade_date = self.lots[0].trade_date;
+            self.lots = vec![Lot {
+                quantity,
+                unit_cost: cost / quantity,
+                trade_date,
+            }];
+        } else {
+            self.lots.push(lot);
+        }
+    }
+
+    fn sell(&mut self, transaction: &Transaction, method: CostBasisMethod) -> Result<(), String> {
+        if transaction.quantity > self.quantity() + QUANTITY_EPSILON {
+            return Err(transaction.id.clone());
+        }
+
+        let mut remaining = transaction.quantity;
+        let mut matched_cost = 0.0;
+
+        while remaining > QUANTITY_EPSILON {
+            let index = match method {
+                CostBasisMethod::Lifo => self.lots.len() - 1,
+                CostBasisMethod::Fifo | CostBasisMethod::AverageCost => 0,
+            };
+            let lot = &mut self.lots[index];
+            let taken = remaining.min(lot.quantity);
+
+            matched_cost += taken * lot.unit_cost;
+            lot.quantity -= taken;
+            remaining -= taken;
+
+            if lot.quantity <= QUANTITY_EPSILON {
+                self.lots.remove(index);
+            }
+        }
+
+        let proceeds = transaction.quantity * transaction.price - transaction.fees;
+        self.realized_pnl += proceeds - matched_cost;
+
+        Ok(())
+    }
+}
+
+/// Matches every sell in the ledger against earlier buys of the same ticker and
+/// returns quantity, remaining cost basis and realized P&L per ticker, in the
+/// order tickers first appear. Fails with the id of a sell that is not covered.
+pub fn cost_basis(
+    transactions: &[Transaction],
+    method: CostBasisMethod,
+) -> Result<Vec<PositionCost>, String> {
+    let mut ordered: Vec<&Transaction> = transactions.iter().collect();
+    ordered.sort_by(|a, b| a.ledger_key().cmp(&b.ledger_key()));
+
+    let mut books: Vec<LotBook> = Vec::new();
+
+    for transaction in ordered {
+        let index = match books
+            .iter()
+            .position(|book| book.ticker_id == transaction.ticker_id)
+        {
+            Some(index) => index,
+            None => {
+                books.push(LotBook {
+                    ticker_id: transaction.ticker_id.clone(),
+                    lots: Vec::new(),
+                    realized_pnl: 0.0,
+                });
+                books.len() - 1
+            }
+        };
+
+        if transaction.is_buy() {
+            books[index].buy(transaction, method);
+        } else {
+            books[index].sell(transaction, method)?;
+        }
+    }
+
+    Ok(books
+        .into_iter()
+        .map(|book| PositionCost {
+            quantity: book.quantity(),
+            cost_basis: book.cost_basis(),
+            ticker_id: book.ticker_id,
+            realized_pnl: book.realized_pnl,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cost_basis, CostBasisMethod};
+    use crate::models::transaction::{NewTransaction, Transaction, BUY, SELL};
+    use chrono::{Duration, NaiveDate};
+
+    fn transaction(kind: &str, quantity: f64, price: f64, fees: f64, day: u32) -> Transaction {
+        Transaction::new(
+            String::from("portfolio"),
+            NewTransaction {
+                ticker_id: String::from("aapl"),
+                kind: String::from(kind),
+                quantity,
+                price,
+                fees,
+                trade_date: NaiveDate::from_ymd(2022, 8, day),
+            },
+        )
+    }
+
+    fn ledger() -> Vec<Transaction> {
+        vec![
+            transaction(BUY, 10.0, 100.0, 0.0, 1),
+            transaction(BUY, 10.0, 120.0, 0.0, 2),
+            transaction(SELL, 15.0, 130.0, 0.0, 3),
+        ]
+    }
+
+    #[test]
+    fn test_cost_basis_fifo() {
+        let result = cost_basis(&ledger(), CostBasisMethod::Fifo).unwrap();
+
+        // Sold 10 @ 100 and 5 @ 120, keeping 5 @ 120
+        assert_eq!(result[0].quantity, 5.0);
+        assert_eq!(result[0].cost_basis, 600.0);
+        assert_eq!(result[0].realized_pnl, 1950.0 - 1600.0);
+    }
+
+    #[test]
+    fn test_cost_basis_lifo() {
+        let result = cost_basis(&ledger(), CostBasisMethod::Lifo).unwrap();
+
+        // Sold 10 @ 120 and 5 @ 100, keeping 5 @ 100
+        assert_eq!(result[0].quantity, 5.0);
+        assert_eq!(result[0].cost_basis, 500.0);
+        assert_eq!(result[0].realized_pnl, 1950.0 - 1700.0);
+    }
+
+    #[test]
+    fn test_cost_basis_average_cost() {
+        let result = cost_basis(&ledger(), CostBasisMethod::AverageCost).unwrap();
+
+        // Average cost is 110 per share
+        assert_eq!(result[0].quantity, 5.0);
+        assert_eq!(result[0].cost_basis, 550.0);
+        assert_eq!(result[0].realized_pnl, 1950.0 - 1650.0);
+    }
+
+    #[test]
+    fn test_cost_basis_same_day_lots() {
+        let first = transaction(BUY, 10.0, 100.0, 0.0, 1);
+        let mut second = transaction(BUY, 10.0, 120.0, 0.0, 1);
+        let mut sell = transaction(SELL, 15.0, 130.0, 0.0, 1);
+        second.created_at = first.created_at + Duration::seconds(1);
+        sell.created_at = first.created_at + Duration::seconds(2);
+
+        // Lots are matched in recording order, not in the order they are passed.
+        let ledger = vec![sell, second, first];
+        let fifo = cost_basis(&ledger, CostBasisMethod::Fifo).unwrap();
+        let lifo = cost_basis(&ledger, CostBasisMethod::Lifo).unwrap();
+
+        assert_eq!(fifo[0].cost_basis, 600.0);
+        assert_eq!(lifo[0].cost_basis, 500.0);
+    }
+
+    #[test]
+    fn test_cost_basis_fees() {
+        let transactions = vec![
+            transaction(BUY, 10.0, 100.0, 10.0, 1),
+            transaction(SELL, 10.0, 110.0, 5.0, 2),
+        ];
+
+        let result = cost_basis(&transactions, CostBasisMethod::Fifo).unwrap();
+
+        assert_eq!(result[0].quantity, 0.0);
+        assert_eq!(result[0].cost_basis, 0.0);
+        assert_eq!(result[0].realized_pnl, 1095.0 - 1010.0);
+    }
+
+    #[test]
+    fn test_cost_basis_oversold() {
+        let sell = transaction(SELL, 25.0, 130.0, 0.0, 3);
+        let transactions = vec![transaction(BUY, 10.0, 100.0, 0.0, 1), sell.clone()];
+
+        let result = cost_basis(&transactions, CostBasisMethod::Fifo);
+
+        assert_eq!(result, Err(sell.id));
+    }
+
+    #[test]
+    fn test_unrealized_pnl() {
+        let result = cost_basis(&ledger(), CostBasisMethod::Fifo).unwrap();
+
+        assert_eq!(result[0].market_value(140.0), 700.0);
+        assert_eq!(result[0].unrealized_pnl(140.0), 100.0);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::models::authentication::{hash_token, random_token};
+use crate::schema::api_keys;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// `last_used_at` is only written when it is older than this, to spare a write per request.
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+/// What an API key may do. Keys never get account, key or admin management.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    #[serde(rename = "portfolios:read")]
+    PortfoliosRead,
+    #[serde(rename = "portfolios:write")]
+    PortfoliosWrite,
+    #[serde(rename = "tickers:read")]
+    TickersRead,
+    #[serde(rename = "tickers:write")]
+    TickersWrite,
+}
+
+impl Scope {
+    pub fn parse(value: &str) -> Option<Scope> {
+        match value {
+            "portfolios:read" => Some(Scope::PortfoliosRead),
+            "portfolios:write" => Some(Scope::PortfoliosWrite),
+            "tickers:read" => Some(Scope::TickersRead),
+            "tickers:write" => Some(Scope::TickersWrite),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::PortfoliosRead => "portfolios:read",
+            Scope::PortfoliosWrite => "portfolios:write",
+            Scope::TickersRead => "tickers:read",
+            Scope::TickersWrite => "tickers:write",
+        }
+    }
+}
+
+/// A group of routes that API keys can be granted access to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScopeArea {
+    Portfolios,
+    Tickers,
+}
+
+impl ScopeArea {
+    /// Safe methods need the read scope, anything else the write scope.
+    pub fn scope_for(&self, read_only: bool) -> Scope {
+        match (self, read_only) {
+            (ScopeArea::Portfolios, true) => Scope::PortfoliosRead,
+            (ScopeArea::Portfolios, false) => Scope::PortfoliosWrite,
+            (ScopeArea::Tickers, true) => Scope::TickersRead,
+            (ScopeArea::Tickers, false) => Scope::TickersWrite,
+        }
+    }
+}
+
+/// A long-lived credential for scripts, presented as `Authorization: ApiKey sk_<prefix>_<secret>`.
+/// The prefix finds the row; only a hash of the secret is stored.
+#[derive(Queryable, PartialEq, Insertable, Debug, Clone)]
+#[table_name = "api_keys"]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub prefix: String,
+    pub secret_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+fn new_prefix() -> String {
+    let mut bytes = [0u8; 4];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Splits `sk_<prefix>_<secret>` into its prefix and secret.
+pub fn split_key(key: &str) -> Option<(&str, &str)> {
+    let rest = key.strip_prefix("sk_")?;
+    let (prefix, secret) = rest.split_once('_')?;
+
+    if prefix.is_empty() || secret.is_empty() {
+        None
+    } else {
+        Some((prefix, secret))
+    }
+}
+
+impl ApiKey {
+    /// Builds a key row and returns it with the full key, which is shown only once.
+    pub fn new(
+        user_id: &str,
+        name: &str,
+        scopes: &[Scope],
+        lifetime: Option<Duration>,
+    ) -> (ApiKey, String) {
+        let prefix = new_prefix();
+        let secret = random_token();
+        let now = Utc::now();
+
+        let api_key = ApiKey {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+            prefix: prefix.clone(),
+            secret_hash: hash_token(&secret),
+            scopes: scopes.iter().map(|s| String::from(s.as_str())).collect(),
+            expires_at: lifetime.map(|lifetime| (now + lifetime).naive_utc()),
+            last_used_at: None,
+            created_at: now.naive_utc(),
+            revoked_at: None,
+        };
+
+        (api_key, format!("sk_{}_{}", prefix, secret))
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.iter().any(|s| s == scope.as_str())
+    }
+
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires| expires > now)
+    }
+
+    pub fn create(connection: &PgConnection, api_key: &ApiKey) -> Result<ApiKey, result::Error> {
+        diesel::insert_into(api_keys::table)
+            .values(api_key)
+            .get_result::<ApiKey>(connection)
+    }
+
+    pub fn get_all_for_user(
+        connection: &PgConnection,
+        user_id: &str,
+    ) -> Result<Vec<ApiKey>, result::Error> {
+        api_keys::table
+            .filter(api_keys::user_id.eq(user_id))
+            .order(api_keys::created_at.desc())
+            .load::<ApiKey>(connection)
+    }
+
+    /// Looks up an active key by its full text.
+    pub fn authenticate(
+        connection: &PgConnection,
+        key: &str,
+    ) -> Result<Option<ApiKey>, result::Error> {
+        let (prefix, secret) = match split_key(key) {
+            Some(parts) => parts,
+            None => return Ok(None),
+        };
+
+        let api_key = match api_keys::table
+            .filter(api_keys::prefix.eq(prefix))
+            .first::<ApiKey>(connection)
+            .optional()?
+        {
+            Some(api_key) => api_key,
+            None => return Ok(None),
+        };
+
+        let now = Utc::now().naive_utc();
+        if api_key.secret_hash != hash_token(secret) || !api_key.is_active(now) {
+            return Ok(None);
+        }
+
+        let seen_before = now - Duration::seconds(LAST_USED_RESOLUTION_SECONDS);
+        diesel::update(
+            api_keys::table.find(&api_key.id).filter(
+                api_keys::last_used_at
+                    .is_null()
+                    .or(api_keys::last_used_at.lt(seen_before)),
+            ),
+        )
+        .set(api_keys::last_used_at.eq(now))
+        .execute(connection)?;
+
+        Ok(Some(api_key))
+    }
+
+    pub fn revoke(
+        connection: &PgConnection,
+        id: &str,
+        user_id: &str,
+    ) -> Result<ApiKey, result::Error> {
+        diesel::update(
+            api_keys::table
+                .find(id)
+                .filter(api_keys::user_id.eq(user_id))
+                .filter(api_keys::revoked_at.is_null()),
+        )
+        .set(api_keys::revoked_at.eq(Utc::now().naive_utc()))
+        .get_result::<ApiKey>(connection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_key, ApiKey, Scope, ScopeArea};
+    use crate::infrastructure::views::ApiKeyView;
+    use crate::models::authentication::hash_token;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_scope_round_trip() {
+        for scope in [
+            Scope::PortfoliosRead,
+            Scope::PortfoliosWrite,
+            Scope::TickersRead,
+            Scope::TickersWrite,
+        ] {
+            assert_eq!(Scope::parse(scope.as_str()), Some(scope));
+        }
+        assert_eq!(Scope::parse("users:write"), None);
+        assert_eq!(ScopeArea::Tickers.scope_for(false), Scope::TickersWrite);
+    }
+
+    #[test]
+    fn test_new_api_key() {
+        let (api_key, key) = ApiKey::new("user", "script", &[Scope::PortfoliosRead], None);
+
+        let (prefix, secret) = split_key(&key).unwrap();
+        assert_eq!(prefix, api_key.prefix);
+        assert_eq!(api_key.secret_hash, hash_token(secret));
+        assert!(api_key.has_scope(Scope::PortfoliosRead));
+        assert!(!api_key.has_scope(Scope::PortfoliosWrite));
+        assert!(!serde_json::to_string(&ApiKeyView::from(api_key))
+            .unwrap()
+            .contains("secret_hash"));
+    }
+
+    #[test]
+    fn test_api_key_is_active() {
+        let now = Utc::now().naive_utc();
+        let (mut api_key, _) = ApiKey::new("user", "script", &[], Some(Duration::days(1)));
+
+        assert!(api_key.is_active(now));
+        assert!(!api_key.is_active(now + Duration::days(2)));
+
+        api_key.revoked_at = Some(now);
+        assert!(!api_key.is_active(now));
+    }
+
+    #[test]
+    fn test_split_key() {
+        assert_eq!(split_key("sk_abcd_efgh"), Some(("abcd", "efgh")));
+        assert_eq!(split_key("sk_abcd_ef_gh"), Some(("abcd", "ef_gh")));
+        assert_eq!(split_key("abcd_efgh"), None);
+        assert_eq!(split_key("sk__efgh"), None);
+    }
+}
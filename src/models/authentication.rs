@@ -1,10 +1,15 @@
+use super::password::{self, HashParams};
+use super::role::Role;
 use super::user::User;
 use crate::diesel::result;
+use crate::infrastructure::keys::KeySet;
 use chrono::Utc;
-use jsonwebtoken;
-use pwhash::bcrypt;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
 
-#[derive(Queryable, PartialEq, Debug, serde::Deserialize)]
+#[derive(Queryable, PartialEq, Debug, serde::Deserialize, ToSchema)]
 pub struct AuthUser {
     pub email: String,
     pub password: String,
@@ -15,24 +20,33 @@ impl AuthUser {
         connection: &crate::diesel::PgConnection,
         email: &str,
         password: &str,
-    ) -> Result<(User, String), result::Error> {
-        let user = match User::get_by_email(connection, &email.to_string()) {
-            Ok(user) => user,
-            Err(err) => return Err(err),
-        };
+        params: &HashParams,
+    ) -> Result<User, result::Error> {
+        let mut user = User::get_by_email(connection, &email.to_string())?;
 
-        match AuthUser::verify(password.to_string(), &user) {
+        match AuthUser::verify(password, &user) {
             Ok(_) => (),
             Err(err) => return Err(err),
         };
 
-        let token = user.generate_jwt();
+        // Upgrade bcrypt or outdated Argon2 hashes while the plain password is at hand.
+        if password::needs_rehash(&user.password, params) {
+            match password::hash_with(password, params)
+                .map_err(|e| e.to_string())
+                .and_then(|hash| {
+                    User::set_password_hash(connection, &user.id, &hash).map_err(|e| e.to_string())
+                }) {
+                Ok(updated) => user = updated,
+                Err(e) => log::error!("Could not rehash password for {}: {}", user.id, e),
+            }
+        }
 
-        Ok((user, token))
+        Ok(user)
     }
 
-    fn verify(password: String, user: &User) -> Result<(), result::Error> {
-        if bcrypt::verify(password, &user.password) == true {
+    /// Deleted accounts are treated as unknown, whatever the password.
+    fn verify(password: &str, user: &User) -> Result<(), result::Error> {
+        if !user.is_deleted && password::verify(password, &user.password) {
             Ok(())
         } else {
             Err(result::Error::NotFound)
@@ -46,48 +60,164 @@ pub struct Claims {
     pub email: String,
     pub exp: i64,
     pub iat: i64,
+    // Tokens issued before roles existed carry none and act as plain users.
+    #[serde(default)]
+    pub role: Role,
+    // Identifies the token on the revocation list; older tokens have none and
+    // are refused.
+    #[serde(default)]
+    pub jti: String,
+    // Must match the user's current token_version.
+    #[serde(default)]
+    pub ver: i32,
 }
 
-pub fn generate(user: &crate::models::user::User) -> String {
-    let secret = match dotenv::var("JWT_SECRET") {
-        Ok(s) => s,
-        Err(_) => "".to_string(),
-    };
+/// A new opaque token: 32 random bytes, URL-safe base64.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
 
-    let duration = match dotenv::var("JWT_LIFETIME_IN_SECONDS") {
-        Ok(d) => d,
-        Err(_) => "300".to_string(),
-    };
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
 
-    let duration: i64 = duration.parse().unwrap();
-    let exp = Utc::now() + chrono::Duration::seconds(duration);
+/// Hex SHA-256 of a token, the only form in which refresh tokens are stored.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub fn generate(
+    keys: &KeySet,
+    user: &crate::models::user::User,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let exp = Utc::now() + keys.lifetime();
 
     let claims = Claims {
         sub: String::from(&user.id),
         email: String::from(&user.email),
         exp: exp.timestamp(),
         iat: Utc::now().timestamp(),
+        role: user.role(),
+        jti: Uuid::new_v4().to_string(),
+        ver: user.token_version,
+    };
+
+    keys.sign(&claims)
+}
+
+pub fn decode(keys: &KeySet, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    keys.verify::<Claims>(token, None)
+}
+
+/// How long the second login step may take after the password was accepted.
+pub const CHALLENGE_LIFETIME_SECONDS: i64 = 300;
+const CHALLENGE_AUDIENCE: &str = "login-challenge";
+
+/// Proof that the password step of a two-factor login succeeded. It lacks the
+/// `email` claim, so it can never pass for an access token.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ChallengeClaims {
+    pub sub: String,
+    pub aud: String,
+    pub exp: i64,
+    pub iat: i64,
+}
+
+pub fn generate_challenge(
+    keys: &KeySet,
+    user: &crate::models::user::User,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = Utc::now();
+    let claims = ChallengeClaims {
+        sub: String::from(&user.id),
+        aud: String::from(CHALLENGE_AUDIENCE),
+        exp: (now + chrono::Duration::seconds(CHALLENGE_LIFETIME_SECONDS)).timestamp(),
+        iat: now.timestamp(),
     };
 
-    jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &jsonwebtoken::EncodingKey::from_secret(&secret.as_bytes()),
-    )
-    .unwrap_or_default()
+    keys.sign(&claims)
 }
 
-pub fn verify(token: String) -> Result<crate::models::user::User, jsonwebtoken::errors::Error> {
-    let secret = match dotenv::var("JWT_SECRET") {
-        Ok(s) => s,
-        Err(_) => "".to_string(),
+pub fn decode_challenge(
+    keys: &KeySet,
+    token: &str,
+) -> Result<ChallengeClaims, jsonwebtoken::errors::Error> {
+    keys.verify::<ChallengeClaims>(token, Some(CHALLENGE_AUDIENCE))
+}
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode, decode_challenge, generate, generate_challenge, hash_token, random_token, AuthUser,
     };
+    use crate::infrastructure::keys::KeySet;
+    use crate::models::password::{self, HashParams};
+    use crate::models::user::User;
+
+    fn keys() -> KeySet {
+        KeySet::from_secret(
+            b"0123456789abcdef0123456789abcdef",
+            chrono::Duration::minutes(5),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_random_tokens_are_unique() {
+        let first = random_token();
+        let second = random_token();
 
-    let token_data = jsonwebtoken::decode::<Claims>(
-        &token,
-        &jsonwebtoken::DecodingKey::from_secret(secret.as_bytes()),
-        &jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256),
-    )?;
+        assert_ne!(first, second);
+        assert_eq!(first.len(), 43);
+    }
 
-    Ok(crate::models::user::User::from_jwt(&token_data.claims))
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(hash_token("abc"), hash_token("abc"));
+        assert_ne!(hash_token("abc"), hash_token("abd"));
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_token_carries_version() {
+        let mut user = User::new(String::from("user@example.com"), String::new());
+        user.token_version = 3;
+
+        let keys = keys();
+        let claims = decode(&keys, &generate(&keys, &user).unwrap()).unwrap();
+
+        assert_eq!(claims.sub, user.id);
+        assert_eq!(claims.ver, 3);
+    }
+
+    #[test]
+    fn test_challenge_is_not_an_access_token() {
+        let user = User::new(String::from("user@example.com"), String::new());
+
+        let keys = keys();
+
+        let challenge = generate_challenge(&keys, &user).unwrap();
+        assert_eq!(decode_challenge(&keys, &challenge).unwrap().sub, user.id);
+        assert!(decode(&keys, &challenge).is_err());
+
+        assert!(decode_challenge(&keys, &generate(&keys, &user).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_deleted_user_cannot_sign_in() {
+        let params = HashParams {
+            memory_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let hash = password::hash_with("Secret123!", &params).unwrap();
+        let mut user = User::new(String::from("user@example.com"), hash);
+
+        assert!(AuthUser::verify("Secret123!", &user).is_ok());
+        assert!(AuthUser::verify("wrong", &user).is_err());
+
+        user.is_deleted = true;
+        assert!(AuthUser::verify("Secret123!", &user).is_err());
+    }
 }
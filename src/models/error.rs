@@ -0,0 +1,40 @@
+use crate::models::password::HashError;
+
+use diesel::result;
+use std::fmt;
+
+/// Why a model operation failed. Handlers decide what each case means for
+/// the response.
+#[derive(Debug)]
+pub enum ModelError {
+    /// Another row already has the name or address being written.
+    Conflict(String),
+    /// A refresh token that is unknown, expired or already used.
+    InvalidToken(String),
+    Hash(HashError),
+    Database(result::Error),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::Conflict(detail) | ModelError::InvalidToken(detail) => {
+                write!(f, "{}", detail)
+            }
+            ModelError::Hash(err) => write!(f, "{}", err),
+            ModelError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<result::Error> for ModelError {
+    fn from(err: result::Error) -> Self {
+        ModelError::Database(err)
+    }
+}
+
+impl From<HashError> for ModelError {
+    fn from(err: HashError) -> Self {
+        ModelError::Hash(err)
+    }
+}
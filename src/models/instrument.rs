@@ -0,0 +1,113 @@
+use crate::infrastructure::market_data::InstrumentInfo;
+use crate::schema::instruments;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::pg::upsert::excluded;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result;
+use serde::{Deserialize, Serialize};
+
+/// Catalog details are re-fetched from the provider once they are older than this.
+pub const REFRESH_AFTER_DAYS: i64 = 7;
+
+#[derive(Queryable, PartialEq, Insertable, Serialize, Deserialize, Debug, Clone)]
+#[table_name = "instruments"]
+pub struct Instrument {
+    pub symbol: String,
+    pub short_name: String,
+    pub long_name: String,
+    pub exchange: String,
+    pub currency: String,
+    pub asset_type: String,
+    pub last_refreshed: NaiveDateTime,
+}
+
+impl Instrument {
+    pub fn from_info(info: InstrumentInfo) -> Instrument {
+        Instrument {
+            symbol: info.symbol,
+            short_name: info.short_name,
+            long_name: info.long_name,
+            exchange: info.exchange,
+            currency: info.currency,
+            asset_type: info.asset_type,
+            last_refreshed: Utc::now().naive_utc(),
+        }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.last_refreshed < (Utc::now() - Duration::days(REFRESH_AFTER_DAYS)).naive_utc()
+    }
+
+    pub fn get_by_symbol(
+        connection: &PgConnection,
+        symbol: &str,
+    ) -> Result<Option<Instrument>, result::Error> {
+        instruments::table
+            .find(symbol)
+            .first::<Instrument>(connection)
+            .optional()
+    }
+
+    pub fn get_stale(connection: &PgConnection) -> Result<Vec<Instrument>, result::Error> {
+        let refreshed_before = (Utc::now() - Duration::days(REFRESH_AFTER_DAYS)).naive_utc();
+
+        instruments::table
+            .filter(instruments::last_refreshed.lt(refreshed_before))
+            .order(instruments::symbol.asc())
+            .load::<Instrument>(connection)
+    }
+
+    /// Inserts the instrument or overwrites the stored details for its symbol.
+    pub fn upsert(
+        connection: &PgConnection,
+        instrument: &Instrument,
+    ) -> Result<Instrument, result::Error> {
+        diesel::insert_into(instruments::table)
+            .values(instrument)
+            .on_conflict(instruments::symbol)
+            .do_update()
+            .set((
+                instruments::short_name.eq(excluded(instruments::short_name)),
+                instruments::long_name.eq(excluded(instruments::long_name)),
+                instruments::exchange.eq(excluded(instruments::exchange)),
+                instruments::currency.eq(excluded(instruments::currency)),
+                instruments::asset_type.eq(excluded(instruments::asset_type)),
+                instruments::last_refreshed.eq(excluded(instruments::last_refreshed)),
+            ))
+            .get_result::<Instrument>(connection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Instrument, REFRESH_AFTER_DAYS};
+    use crate::infrastructure::market_data::InstrumentInfo;
+    use chrono::{Duration, Utc};
+
+    fn instrument() -> Instrument {
+        Instrument::from_info(InstrumentInfo {
+            symbol: String::from("AAPL"),
+            short_name: String::from("Apple Inc."),
+            long_name: String::from("Apple Inc."),
+            exchange: String::from("NMS"),
+            currency: String::from("USD"),
+            asset_type: String::from("EQUITY"),
+        })
+    }
+
+    #[test]
+    fn test_instrument_fresh() {
+        assert!(!instrument().is_stale());
+    }
+
+    #[test]
+    fn test_instrument_stale() {
+        let mut instrument = instrument();
+        instrument.last_refreshed =
+            (Utc::now() - Duration::days(REFRESH_AFTER_DAYS + 1)).naive_utc();
+
+        assert!(instrument.is_stale());
+    }
+}
@@ -0,0 +1,74 @@
+/// What a list can be sorted by. Users have no name, so `Name` sorts them by email.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortField {
+    Name,
+    CreatedAt,
+}
+
+/// One page of a list query. Ties in the sort field are broken by id, so pages
+/// never overlap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListOptions {
+    pub limit: i64,
+    pub offset: i64,
+    pub sort: SortField,
+    pub descending: bool,
+    pub name_contains: Option<String>,
+    pub include_deleted: bool,
+}
+
+impl Default for ListOptions {
+    fn default() -> ListOptions {
+        ListOptions {
+            limit: 50,
+            offset: 0,
+            sort: SortField::Name,
+            descending: false,
+            name_contains: None,
+            include_deleted: false,
+        }
+    }
+}
+
+impl ListOptions {
+    /// `ILIKE` pattern for the name filter, with the user's wildcards escaped.
+    pub fn name_pattern(&self) -> Option<String> {
+        let needle = self.name_contains.as_deref()?.trim();
+        if needle.is_empty() {
+            return None;
+        }
+
+        let mut escaped = String::with_capacity(needle.len());
+        for c in needle.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+
+        Some(format!("%{}%", escaped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListOptions;
+
+    fn named(name: &str) -> ListOptions {
+        ListOptions {
+            name_contains: Some(String::from(name)),
+            ..ListOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_name_pattern() {
+        assert_eq!(named("app").name_pattern().as_deref(), Some("%app%"));
+        assert_eq!(
+            named("50%_\\").name_pattern().as_deref(),
+            Some("%50\\%\\_\\\\%")
+        );
+        assert_eq!(named("  ").name_pattern(), None);
+        assert_eq!(ListOptions::default().name_pattern(), None);
+    }
+}
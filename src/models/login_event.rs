@@ -0,0 +1,120 @@
+use crate::schema::login_events;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::expression::dsl::max;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result;
+use uuid::Uuid;
+
+/// One sign-in attempt. `user_id` is `None` when the email matched no account.
+#[derive(Queryable, PartialEq, Insertable, Debug, Clone)]
+#[table_name = "login_events"]
+pub struct LoginEvent {
+    pub id: String,
+    pub user_id: Option<String>,
+    pub email: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub method: String,
+    pub success: bool,
+    pub created_at: NaiveDateTime,
+}
+
+/// Failed attempts counted for throttling, and when the latest of them happened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Failures {
+    pub count: i64,
+    pub last: Option<NaiveDateTime>,
+}
+
+impl LoginEvent {
+    pub fn new(
+        user_id: Option<String>,
+        email: &str,
+        ip: Option<String>,
+        user_agent: Option<String>,
+        method: &str,
+        success: bool,
+    ) -> LoginEvent {
+        LoginEvent {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            email: email.to_lowercase(),
+            ip,
+            user_agent,
+            method: method.to_string(),
+            success,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    pub fn record(connection: &PgConnection, event: &LoginEvent) -> Result<(), result::Error> {
+        diesel::insert_into(login_events::table)
+            .values(event)
+            .execute(connection)?;
+
+        Ok(())
+    }
+
+    /// Failures for the address since `since` or its last successful sign-in, whichever is later.
+    pub fn account_failures(
+        connection: &PgConnection,
+        email: &str,
+        since: NaiveDateTime,
+    ) -> Result<Failures, result::Error> {
+        let email = email.to_lowercase();
+        let last_success = login_events::table
+            .filter(login_events::email.eq(&email))
+            .filter(login_events::success.eq(true))
+            .select(max(login_events::created_at))
+            .first::<Option<NaiveDateTime>>(connection)?;
+        let since = match last_success {
+            Some(success) if success > since => success,
+            _ => since,
+        };
+
+        let failed = login_events::table
+            .filter(login_events::email.eq(&email))
+            .filter(login_events::success.eq(false))
+            .filter(login_events::created_at.gt(since));
+
+        Ok(Failures {
+            count: failed.count().get_result(connection)?,
+            last: failed
+                .select(max(login_events::created_at))
+                .first(connection)?,
+        })
+    }
+
+    /// Failures from the address since `since`, across every account.
+    pub fn ip_failures(
+        connection: &PgConnection,
+        ip: &str,
+        since: NaiveDateTime,
+    ) -> Result<Failures, result::Error> {
+        let failed = login_events::table
+            .filter(login_events::ip.eq(ip))
+            .filter(login_events::success.eq(false))
+            .filter(login_events::created_at.gt(since));
+
+        Ok(Failures {
+            count: failed.count().get_result(connection)?,
+            last: failed
+                .select(max(login_events::created_at))
+                .first(connection)?,
+        })
+    }
+
+    pub fn recent_for_user(
+        connection: &PgConnection,
+        user_id: &str,
+        limit: i64,
+    ) -> Result<Vec<LoginEvent>, result::Error> {
+        login_events::table
+            .filter(login_events::user_id.eq(user_id))
+            .order(login_events::created_at.desc())
+            .limit(limit)
+            .load::<LoginEvent>(connection)
+    }
+}
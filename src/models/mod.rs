@@ -1,4 +1,19 @@
+pub mod accounting;
+pub mod api_key;
 pub mod authentication;
+pub mod error;
+pub mod instrument;
+pub mod listing;
+pub mod login_event;
+pub mod one_time_token;
+pub mod password;
 pub mod portfolio;
+pub mod price_bar;
+pub mod recovery_code;
+pub mod refresh_token;
+pub mod revoked_token;
+pub mod role;
 pub mod ticker;
+pub mod totp;
+pub mod transaction;
 pub mod user;
@@ -0,0 +1,90 @@
+use crate::models::authentication::{hash_token, random_token};
+use crate::schema::one_time_tokens;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result;
+
+/// What a one-time token may be redeemed for; a token only works for its own purpose.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::VerifyEmail => "verify_email",
+            TokenPurpose::ResetPassword => "reset_password",
+        }
+    }
+}
+
+/// A single-use token mailed to the user. As with refresh tokens only the hash is kept.
+#[derive(Queryable, PartialEq, Insertable, Debug)]
+#[table_name = "one_time_tokens"]
+pub struct OneTimeToken {
+    pub token_hash: String,
+    pub user_id: String,
+    pub purpose: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+impl OneTimeToken {
+    /// Issues a token and drops any unused one the user still had for the same purpose.
+    pub fn issue(
+        connection: &PgConnection,
+        user_id: &str,
+        purpose: TokenPurpose,
+        lifetime: Duration,
+    ) -> Result<String, result::Error> {
+        let token = random_token();
+        let one_time_token = OneTimeToken {
+            token_hash: hash_token(&token),
+            user_id: user_id.to_string(),
+            purpose: String::from(purpose.as_str()),
+            expires_at: (Utc::now() + lifetime).naive_utc(),
+            used_at: None,
+        };
+
+        connection.transaction(|| {
+            diesel::delete(
+                one_time_tokens::table
+                    .filter(one_time_tokens::user_id.eq(user_id))
+                    .filter(one_time_tokens::purpose.eq(purpose.as_str()))
+                    .filter(one_time_tokens::used_at.is_null()),
+            )
+            .execute(connection)?;
+
+            diesel::insert_into(one_time_tokens::table)
+                .values(&one_time_token)
+                .execute(connection)
+        })?;
+
+        Ok(token)
+    }
+
+    /// Marks the token used and returns it, or `None` if it is unknown, expired,
+    /// already used or meant for something else.
+    pub fn consume(
+        connection: &PgConnection,
+        token: &str,
+        purpose: TokenPurpose,
+    ) -> Result<Option<OneTimeToken>, result::Error> {
+        let now = Utc::now().naive_utc();
+
+        diesel::update(
+            one_time_tokens::table
+                .filter(one_time_tokens::token_hash.eq(hash_token(token)))
+                .filter(one_time_tokens::purpose.eq(purpose.as_str()))
+                .filter(one_time_tokens::used_at.is_null())
+                .filter(one_time_tokens::expires_at.gt(now)),
+        )
+        .set(one_time_tokens::used_at.eq(now))
+        .get_result::<OneTimeToken>(connection)
+        .optional()
+    }
+}
@@ -0,0 +1,155 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use pwhash::bcrypt;
+use rand::rngs::OsRng;
+use serde::Deserialize;
+
+/// Argon2id cost. The defaults follow the OWASP recommendation of 19 MiB,
+/// two passes and one lane; raise them as hardware allows.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HashParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for HashParams {
+    fn default() -> HashParams {
+        HashParams {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl HashParams {
+    /// Refuses a cost Argon2 cannot run with, such as less memory than eight
+    /// KiB per lane.
+    pub fn validate(&self) -> Result<(), HashError> {
+        self.hasher()
+            .map(|_| ())
+            .map_err(|e| HashError(e.to_string()))
+    }
+
+    fn hasher(&self) -> Result<Argon2<'static>, argon2::Error> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)?;
+
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+#[derive(Debug)]
+pub struct HashError(String);
+
+impl std::fmt::Display for HashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unable to hash password: {}", self.0)
+    }
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    hash.starts_with("$2a$") || hash.starts_with("$2b$") || hash.starts_with("$2y$")
+}
+
+/// Argon2id PHC string for the password with the given cost.
+pub fn hash_with(password: &str, params: &HashParams) -> Result<String, HashError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    params
+        .hasher()
+        .map_err(|e| HashError(e.to_string()))?
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| HashError(e.to_string()))
+}
+
+/// Checks the password against an Argon2 hash or a legacy bcrypt one.
+pub fn verify(password: &str, hash: &str) -> bool {
+    if is_bcrypt(hash) {
+        return bcrypt::verify(password, hash);
+    }
+
+    match PasswordHash::new(hash) {
+        // The cost parameters are read from the hash itself.
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// True when the hash is bcrypt or Argon2 with a cost other than `params`, so it
+/// should be replaced the next time the plain password is at hand.
+pub fn needs_rehash(hash: &str, params: &HashParams) -> bool {
+    if is_bcrypt(hash) {
+        return true;
+    }
+
+    let parsed = match PasswordHash::new(hash) {
+        Ok(parsed) => parsed,
+        Err(_) => return true,
+    };
+    let stored = match Params::try_from(&parsed) {
+        Ok(stored) => stored,
+        Err(_) => return true,
+    };
+
+    parsed.algorithm.as_str() != "argon2id"
+        || parsed.version != Some(Version::V0x13 as u32)
+        || stored.m_cost() != params.memory_kib
+        || stored.t_cost() != params.iterations
+        || stored.p_cost() != params.parallelism
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_with, needs_rehash, verify, HashParams};
+    use pwhash::bcrypt;
+
+    // Cheap enough to keep the test run fast.
+    const TEST_PARAMS: HashParams = HashParams {
+        memory_kib: 1024,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn test_argon2id_round_trip() {
+        let hash = hash_with("Correct-Horse1", &TEST_PARAMS).unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert!(verify("Correct-Horse1", &hash));
+        assert!(!verify("Wrong-Horse1", &hash));
+    }
+
+    #[test]
+    fn test_verifies_legacy_bcrypt() {
+        let hash = bcrypt::hash("Correct-Horse1").unwrap();
+
+        assert!(verify("Correct-Horse1", &hash));
+        assert!(!verify("Wrong-Horse1", &hash));
+        assert!(needs_rehash(&hash, &TEST_PARAMS));
+    }
+
+    #[test]
+    fn test_needs_rehash_when_cost_changes() {
+        let hash = hash_with("Correct-Horse1", &TEST_PARAMS).unwrap();
+
+        assert!(!needs_rehash(&hash, &TEST_PARAMS));
+        assert!(needs_rehash(
+            &hash,
+            &HashParams {
+                iterations: 2,
+                ..TEST_PARAMS
+            }
+        ));
+    }
+
+    #[test]
+    fn test_garbage_hash_is_rejected() {
+        assert!(!verify("anything", "not-a-hash"));
+        assert!(needs_rehash("not-a-hash", &TEST_PARAMS));
+    }
+}
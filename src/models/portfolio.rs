@@ -1,3 +1,6 @@
+use crate::models::accounting::CostBasisMethod;
+use crate::models::error::ModelError;
+use crate::models::listing::{ListOptions, SortField};
 use crate::schema::portfolios;
 
 use chrono::{DateTime, NaiveDate, Utc};
@@ -5,9 +8,10 @@ use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::result;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Queryable, PartialEq, Insertable, Serialize, Deserialize, Debug)]
+#[derive(Queryable, PartialEq, Insertable, Debug)]
 #[table_name = "portfolios"]
 pub struct Portfolio {
     pub id: String,
@@ -15,6 +19,7 @@ pub struct Portfolio {
     pub created_at: NaiveDate,
     pub is_deleted: bool,
     pub user_id: String,
+    pub cost_basis_method: String,
 }
 
 impl Portfolio {
@@ -27,9 +32,14 @@ impl Portfolio {
             created_at: naive_date,
             is_deleted: false,
             user_id,
+            cost_basis_method: String::from(CostBasisMethod::Fifo.as_str()),
         }
     }
 
+    pub fn cost_basis_method(&self) -> CostBasisMethod {
+        CostBasisMethod::parse(&self.cost_basis_method).unwrap_or(CostBasisMethod::Fifo)
+    }
+
     pub fn get_all_from_user(
         connection: &PgConnection,
         user_id: &String,
@@ -40,6 +50,48 @@ impl Portfolio {
             .load::<Portfolio>(connection)
     }
 
+    /// One page of the user's portfolios and the number matching the filters.
+    pub fn page_from_user(
+        connection: &PgConnection,
+        user_id: &str,
+        options: &ListOptions,
+    ) -> Result<(Vec<Portfolio>, i64), result::Error> {
+        let filtered = || {
+            let mut query = portfolios::table
+                .filter(portfolios::user_id.eq(user_id))
+                .into_boxed();
+            if !options.include_deleted {
+                query = query.filter(portfolios::is_deleted.eq(false));
+            }
+            if let Some(pattern) = options.name_pattern() {
+                query = query.filter(portfolios::name.ilike(pattern));
+            }
+            query
+        };
+
+        let total = filtered().count().get_result::<i64>(connection)?;
+        let query = match (options.sort, options.descending) {
+            (SortField::Name, false) => {
+                filtered().order((portfolios::name.asc(), portfolios::id.asc()))
+            }
+            (SortField::Name, true) => {
+                filtered().order((portfolios::name.desc(), portfolios::id.desc()))
+            }
+            (SortField::CreatedAt, false) => {
+                filtered().order((portfolios::created_at.asc(), portfolios::id.asc()))
+            }
+            (SortField::CreatedAt, true) => {
+                filtered().order((portfolios::created_at.desc(), portfolios::id.desc()))
+            }
+        };
+        let portfolios = query
+            .limit(options.limit)
+            .offset(options.offset)
+            .load::<Portfolio>(connection)?;
+
+        Ok((portfolios, total))
+    }
+
     pub fn get_by_id(connection: &PgConnection, id: String) -> Result<Portfolio, result::Error> {
         match portfolios::table
             .filter(portfolios::id.eq(id))
@@ -51,6 +103,30 @@ impl Portfolio {
         }
     }
 
+    /// Locks the portfolio row until the surrounding transaction ends, so
+    /// ledger changes to one portfolio are checked and written one at a time.
+    pub fn lock(connection: &PgConnection, id: &String) -> Result<Portfolio, result::Error> {
+        portfolios::table
+            .find(id)
+            .filter(portfolios::is_deleted.eq(false))
+            .for_update()
+            .get_result::<Portfolio>(connection)
+    }
+
+    /// The portfolio, only if it belongs to `user_id`.
+    pub fn get_for_owner(
+        connection: &PgConnection,
+        id: &str,
+        user_id: &str,
+    ) -> Result<Option<Portfolio>, result::Error> {
+        portfolios::table
+            .filter(portfolios::id.eq(id))
+            .filter(portfolios::user_id.eq(user_id))
+            .filter(portfolios::is_deleted.eq(false))
+            .first::<Portfolio>(connection)
+            .optional()
+    }
+
     pub fn get_by_name(
         connection: &PgConnection,
         name: &String,
@@ -82,6 +158,17 @@ impl Portfolio {
         }
     }
 
+    pub fn update_cost_basis_method(
+        self,
+        connection: &PgConnection,
+        method: CostBasisMethod,
+    ) -> Result<Portfolio, result::Error> {
+        diesel::update(portfolios::table.find(self.id))
+            .filter(portfolios::is_deleted.eq(false))
+            .set(portfolios::cost_basis_method.eq(method.as_str()))
+            .get_result::<Portfolio>(connection)
+    }
+
     pub fn delete_portfolio(
         connection: &PgConnection,
         portfolio_id: &String,
@@ -97,25 +184,33 @@ impl Portfolio {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct NewPortfolio {
     pub name: String,
-    pub user_id: String,
+    // Defaults to the caller; naming anyone else is rejected.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub cost_basis_method: Option<CostBasisMethod>,
 }
 
 impl NewPortfolio {
     pub fn create(
         name: String,
         user_id: String,
+        cost_basis_method: CostBasisMethod,
         connection: &PgConnection,
-    ) -> Result<Portfolio, result::Error> {
-        let portfolio: Portfolio = Portfolio::new(name.clone(), user_id.clone());
+    ) -> Result<Portfolio, ModelError> {
+        let mut portfolio: Portfolio = Portfolio::new(name.clone(), user_id.clone());
+        portfolio.cost_basis_method = String::from(cost_basis_method.as_str());
 
-        match Portfolio::get_by_name(connection, &name, &user_id).unwrap() {
-            Some(_) => Err(result::Error::__Nonexhaustive),
-            None => diesel::insert_into(portfolios::table)
+        match Portfolio::get_by_name(connection, &name, &user_id)? {
+            Some(_) => Err(ModelError::Conflict(String::from(
+                "Portfolio with that name already exists",
+            ))),
+            None => Ok(diesel::insert_into(portfolios::table)
                 .values(&portfolio)
-                .get_result::<Portfolio>(connection),
+                .get_result::<Portfolio>(connection)?),
         }
     }
 }
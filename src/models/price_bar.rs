@@ -0,0 +1,135 @@
+use crate::infrastructure::market_data::Quote;
+use crate::schema::price_bars;
+
+use diesel::pg::upsert::excluded;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result;
+use serde::{Deserialize, Serialize};
+
+// Keeps each insert under Postgres' limit of 65535 bind parameters.
+const UPSERT_CHUNK: usize = 1000;
+
+#[derive(Queryable, PartialEq, Insertable, Serialize, Deserialize, Debug, Clone)]
+#[table_name = "price_bars"]
+pub struct PriceBar {
+    pub symbol: String,
+    pub interval: String,
+    pub timestamp: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub adjclose: f64,
+    pub volume: i64,
+}
+
+impl PriceBar {
+    pub fn from_quote(symbol: &str, interval: &str, quote: &Quote) -> PriceBar {
+        PriceBar {
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            timestamp: quote.timestamp as i64,
+            open: quote.open,
+            high: quote.high,
+            low: quote.low,
+            close: quote.close,
+            adjclose: quote.adjclose,
+            volume: quote.volume as i64,
+        }
+    }
+
+    pub fn to_quote(&self) -> Quote {
+        Quote {
+            timestamp: self.timestamp as u64,
+            open: self.open,
+            high: self.high,
+            low: self.low,
+            volume: self.volume as u64,
+            close: self.close,
+            adjclose: self.adjclose,
+        }
+    }
+
+    /// Bars for a symbol in ascending time order, bounded by `from` and `to` when given.
+    pub fn get_range(
+        connection: &PgConnection,
+        symbol: &str,
+        interval: &str,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Vec<PriceBar>, result::Error> {
+        let mut query = price_bars::table
+            .filter(price_bars::symbol.eq(symbol))
+            .filter(price_bars::interval.eq(interval))
+            .into_boxed();
+
+        if let Some(from) = from {
+            query = query.filter(price_bars::timestamp.ge(from));
+        }
+        if let Some(to) = to {
+            query = query.filter(price_bars::timestamp.le(to));
+        }
+
+        query
+            .order(price_bars::timestamp.asc())
+            .load::<PriceBar>(connection)
+    }
+
+    pub fn get_earliest(
+        connection: &PgConnection,
+        symbol: &str,
+        interval: &str,
+    ) -> Result<Option<PriceBar>, result::Error> {
+        price_bars::table
+            .filter(price_bars::symbol.eq(symbol))
+            .filter(price_bars::interval.eq(interval))
+            .order(price_bars::timestamp.asc())
+            .first::<PriceBar>(connection)
+            .optional()
+    }
+
+    pub fn get_latest(
+        connection: &PgConnection,
+        symbol: &str,
+        interval: &str,
+    ) -> Result<Option<PriceBar>, result::Error> {
+        price_bars::table
+            .filter(price_bars::symbol.eq(symbol))
+            .filter(price_bars::interval.eq(interval))
+            .order(price_bars::timestamp.desc())
+            .first::<PriceBar>(connection)
+            .optional()
+    }
+
+    /// Inserts the bars, overwriting any stored bar with the same key so a partial
+    /// bar for the current session is replaced once it closes.
+    pub fn upsert_many(
+        connection: &PgConnection,
+        bars: &[PriceBar],
+    ) -> Result<usize, result::Error> {
+        let mut written = 0;
+
+        for chunk in bars.chunks(UPSERT_CHUNK) {
+            written += diesel::insert_into(price_bars::table)
+                .values(chunk)
+                .on_conflict((
+                    price_bars::symbol,
+                    price_bars::interval,
+                    price_bars::timestamp,
+                ))
+                .do_update()
+                .set((
+                    price_bars::open.eq(excluded(price_bars::open)),
+                    price_bars::high.eq(excluded(price_bars::high)),
+                    price_bars::low.eq(excluded(price_bars::low)),
+                    price_bars::close.eq(excluded(price_bars::close)),
+                    price_bars::adjclose.eq(excluded(price_bars::adjclose)),
+                    price_bars::volume.eq(excluded(price_bars::volume)),
+                ))
+                .execute(connection)?;
+        }
+
+        Ok(written)
+    }
+}
@@ -0,0 +1,104 @@
+use crate::models::authentication::hash_token;
+use crate::schema::recovery_codes;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result;
+use rand::Rng;
+use uuid::Uuid;
+
+pub const CODES_PER_USER: usize = 10;
+
+const CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// A single-use fallback for a lost authenticator. Only the hash is stored.
+#[derive(Queryable, PartialEq, Insertable, Debug)]
+#[table_name = "recovery_codes"]
+pub struct RecoveryCode {
+    pub id: String,
+    pub user_id: String,
+    pub code_hash: String,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+/// Ten characters from an unambiguous alphabet, shown as `xxxxx-xxxxx`.
+pub fn new_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: String = (0..10)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect();
+
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+fn normalize(code: &str) -> String {
+    code.trim().to_lowercase()
+}
+
+impl RecoveryCode {
+    /// Replaces every code the user had with a fresh set and returns them in plain text.
+    pub fn regenerate(
+        connection: &PgConnection,
+        user_id: &str,
+    ) -> Result<Vec<String>, result::Error> {
+        let codes: Vec<String> = (0..CODES_PER_USER).map(|_| new_code()).collect();
+        let rows: Vec<RecoveryCode> = codes
+            .iter()
+            .map(|code| RecoveryCode {
+                id: Uuid::new_v4().to_string(),
+                user_id: user_id.to_string(),
+                code_hash: hash_token(&normalize(code)),
+                used_at: None,
+            })
+            .collect();
+
+        connection.transaction(|| {
+            RecoveryCode::delete_all(connection, user_id)?;
+
+            diesel::insert_into(recovery_codes::table)
+                .values(&rows)
+                .execute(connection)
+        })?;
+
+        Ok(codes)
+    }
+
+    /// Uses up the code if it is one of the user's unused codes.
+    pub fn redeem(
+        connection: &PgConnection,
+        user_id: &str,
+        code: &str,
+    ) -> Result<bool, result::Error> {
+        let redeemed = diesel::update(
+            recovery_codes::table
+                .filter(recovery_codes::user_id.eq(user_id))
+                .filter(recovery_codes::code_hash.eq(hash_token(&normalize(code))))
+                .filter(recovery_codes::used_at.is_null()),
+        )
+        .set(recovery_codes::used_at.eq(Utc::now().naive_utc()))
+        .execute(connection)?;
+
+        Ok(redeemed > 0)
+    }
+
+    pub fn delete_all(connection: &PgConnection, user_id: &str) -> Result<usize, result::Error> {
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+            .execute(connection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{new_code, normalize};
+
+    #[test]
+    fn test_new_code_format() {
+        let code = new_code();
+
+        assert_eq!(code.len(), 11);
+        assert_eq!(&code[5..6], "-");
+        assert_ne!(code, new_code());
+        assert_eq!(normalize(&format!(" {} ", code.to_uppercase())), code);
+    }
+}
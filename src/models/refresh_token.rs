@@ -0,0 +1,193 @@
+use crate::models::authentication::{hash_token, random_token};
+use crate::models::error::ModelError;
+use crate::schema::refresh_tokens;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result;
+use uuid::Uuid;
+
+#[derive(Queryable, PartialEq, Insertable, Debug, Clone)]
+#[table_name = "refresh_tokens"]
+pub struct RefreshToken {
+    pub id: String,
+    pub user_id: String,
+    pub family_id: String,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub replaced_by: Option<String>,
+}
+
+impl RefreshToken {
+    /// Builds a token row and returns it with the plain token, which is never stored.
+    pub fn new(
+        user_id: &str,
+        family_id: Option<&str>,
+        lifetime: Duration,
+    ) -> (RefreshToken, String) {
+        let token = random_token();
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now();
+
+        let refresh_token = RefreshToken {
+            family_id: family_id.map(String::from).unwrap_or_else(|| id.clone()),
+            id,
+            user_id: user_id.to_string(),
+            token_hash: hash_token(&token),
+            expires_at: (now + lifetime).naive_utc(),
+            created_at: now.naive_utc(),
+            revoked_at: None,
+            replaced_by: None,
+        };
+
+        (refresh_token, token)
+    }
+
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        self.revoked_at.is_none() && self.expires_at > now
+    }
+
+    /// Starts a new token family, as on login.
+    pub fn issue(
+        connection: &PgConnection,
+        user_id: &str,
+        lifetime: Duration,
+    ) -> Result<String, result::Error> {
+        let (refresh_token, token) = RefreshToken::new(user_id, None, lifetime);
+
+        diesel::insert_into(refresh_tokens::table)
+            .values(&refresh_token)
+            .execute(connection)?;
+
+        Ok(token)
+    }
+
+    pub fn get_by_token(
+        connection: &PgConnection,
+        token: &str,
+    ) -> Result<Option<RefreshToken>, result::Error> {
+        refresh_tokens::table
+            .filter(refresh_tokens::token_hash.eq(hash_token(token)))
+            .first::<RefreshToken>(connection)
+            .optional()
+    }
+
+    /// Exchanges a refresh token for its successor in the same family. Presenting
+    /// a token that was already rotated or revoked counts as reuse: the whole
+    /// family is revoked so neither the thief nor the owner can keep using it.
+    pub fn rotate(
+        connection: &PgConnection,
+        token: &str,
+        lifetime: Duration,
+    ) -> Result<(RefreshToken, String), ModelError> {
+        let invalid = || ModelError::InvalidToken(String::from("Invalid refresh token."));
+        let reused = || {
+            ModelError::InvalidToken(String::from(
+                "Refresh token was already used; please sign in again.",
+            ))
+        };
+
+        let current = RefreshToken::get_by_token(connection, token)?.ok_or_else(invalid)?;
+        let now = Utc::now().naive_utc();
+
+        if current.revoked_at.is_some() {
+            RefreshToken::revoke_family(connection, &current.family_id)?;
+            return Err(reused());
+        }
+        if !current.is_active(now) {
+            return Err(invalid());
+        }
+
+        let (next, next_token) =
+            RefreshToken::new(&current.user_id, Some(&current.family_id), lifetime);
+
+        let rotated = connection.transaction::<_, ModelError, _>(|| {
+            // Only one of two concurrent rotations of the same token may win.
+            let rotated = diesel::update(refresh_tokens::table.find(&current.id))
+                .filter(refresh_tokens::revoked_at.is_null())
+                .set((
+                    refresh_tokens::revoked_at.eq(now),
+                    refresh_tokens::replaced_by.eq(&next.id),
+                ))
+                .execute(connection)?;
+
+            if rotated == 0 {
+                return Ok(false);
+            }
+
+            diesel::insert_into(refresh_tokens::table)
+                .values(&next)
+                .execute(connection)?;
+
+            Ok(true)
+        })?;
+
+        // The loser is treated as reuse; revoking here, after the transaction
+        // has ended, keeps the revocation from being rolled back.
+        if !rotated {
+            RefreshToken::revoke_family(connection, &current.family_id)?;
+            return Err(reused());
+        }
+
+        Ok((next, next_token))
+    }
+
+    pub fn revoke_family(
+        connection: &PgConnection,
+        family_id: &str,
+    ) -> Result<usize, result::Error> {
+        diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::family_id.eq(family_id))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(connection)
+    }
+
+    pub fn revoke_all_for_user(
+        connection: &PgConnection,
+        user_id: &str,
+    ) -> Result<usize, result::Error> {
+        diesel::update(
+            refresh_tokens::table
+                .filter(refresh_tokens::user_id.eq(user_id))
+                .filter(refresh_tokens::revoked_at.is_null()),
+        )
+        .set(refresh_tokens::revoked_at.eq(Utc::now().naive_utc()))
+        .execute(connection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RefreshToken;
+    use crate::models::authentication::hash_token;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_random_token() {
+        let (first, token) = RefreshToken::new("user", None, Duration::days(30));
+        let (second, _) = RefreshToken::new("user", Some(&first.family_id), Duration::days(30));
+
+        assert_eq!(first.family_id, first.id);
+        assert_eq!(second.family_id, first.id);
+        assert_eq!(first.token_hash, hash_token(&token));
+        assert_ne!(first.token_hash, token);
+    }
+
+    #[test]
+    fn test_refresh_token_is_active() {
+        let now = Utc::now().naive_utc();
+        let (mut refresh_token, _) = RefreshToken::new("user", None, Duration::days(30));
+
+        assert!(refresh_token.is_active(now));
+        assert!(!refresh_token.is_active(refresh_token.expires_at + Duration::seconds(1)));
+
+        refresh_token.revoked_at = Some(now);
+        assert!(!refresh_token.is_active(now));
+    }
+}
@@ -0,0 +1,39 @@
+use crate::schema::revoked_tokens;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result;
+
+/// An access token withdrawn before its `exp`. Rows are only needed until then.
+#[derive(Queryable, PartialEq, Insertable, Debug)]
+#[table_name = "revoked_tokens"]
+pub struct RevokedToken {
+    pub jti: String,
+    pub expires_at: NaiveDateTime,
+}
+
+impl RevokedToken {
+    pub fn revoke(connection: &PgConnection, jti: &str, exp: i64) -> Result<(), result::Error> {
+        let revoked = RevokedToken {
+            jti: jti.to_string(),
+            expires_at: NaiveDateTime::from_timestamp(exp, 0),
+        };
+
+        diesel::delete(
+            revoked_tokens::table.filter(revoked_tokens::expires_at.lt(Utc::now().naive_utc())),
+        )
+        .execute(connection)?;
+
+        diesel::insert_into(revoked_tokens::table)
+            .values(&revoked)
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+
+        Ok(())
+    }
+
+    pub fn is_revoked(connection: &PgConnection, jti: &str) -> Result<bool, result::Error> {
+        diesel::select(diesel::dsl::exists(revoked_tokens::table.find(jti))).get_result(connection)
+    }
+}
@@ -1,18 +1,24 @@
-use crate::schema::tickers;
+use crate::models::error::ModelError;
+use crate::models::instrument::Instrument;
+use crate::models::listing::{ListOptions, SortField};
+use crate::schema::{instruments, portfolios, tickers};
 
+use chrono::{NaiveDateTime, Utc};
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::result;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Queryable, PartialEq, Insertable, Serialize, Deserialize, Debug, Clone)]
+#[derive(Queryable, PartialEq, Insertable, Debug, Clone)]
 #[table_name = "tickers"]
 pub struct Ticker {
     pub id: String,
     pub name: String,
     pub portfolio_id: String,
     pub is_deleted: bool,
+    pub created_at: NaiveDateTime,
 }
 
 impl Ticker {
@@ -22,6 +28,7 @@ impl Ticker {
             name,
             portfolio_id,
             is_deleted: false,
+            created_at: Utc::now().naive_utc(),
         }
     }
 
@@ -35,12 +42,75 @@ impl Ticker {
             .load::<Ticker>(connection)
     }
 
+    /// Tickers of a portfolio alongside their catalog entries.
+    pub fn get_all_with_instruments(
+        connection: &PgConnection,
+        portfolio_id: &str,
+    ) -> Result<Vec<(Ticker, Instrument)>, result::Error> {
+        tickers::table
+            .inner_join(instruments::table)
+            .filter(tickers::portfolio_id.eq(portfolio_id))
+            .filter(tickers::is_deleted.eq(false))
+            .order(tickers::name.asc())
+            .load::<(Ticker, Instrument)>(connection)
+    }
+
+    /// One page of `get_all_with_instruments` and the number of tickers
+    /// matching the filters. The name filter applies to the symbol.
+    pub fn page_with_instruments(
+        connection: &PgConnection,
+        portfolio_id: &str,
+        options: &ListOptions,
+    ) -> Result<(Vec<(Ticker, Instrument)>, i64), result::Error> {
+        let filtered = || {
+            let mut query = tickers::table
+                .inner_join(instruments::table)
+                .filter(tickers::portfolio_id.eq(portfolio_id))
+                .into_boxed();
+            if !options.include_deleted {
+                query = query.filter(tickers::is_deleted.eq(false));
+            }
+            if let Some(pattern) = options.name_pattern() {
+                query = query.filter(tickers::name.ilike(pattern));
+            }
+            query
+        };
+
+        let total = filtered().count().get_result::<i64>(connection)?;
+        let query = match (options.sort, options.descending) {
+            (SortField::Name, false) => filtered().order((tickers::name.asc(), tickers::id.asc())),
+            (SortField::Name, true) => filtered().order((tickers::name.desc(), tickers::id.desc())),
+            (SortField::CreatedAt, false) => {
+                filtered().order((tickers::created_at.asc(), tickers::id.asc()))
+            }
+            (SortField::CreatedAt, true) => {
+                filtered().order((tickers::created_at.desc(), tickers::id.desc()))
+            }
+        };
+        let rows = query
+            .limit(options.limit)
+            .offset(options.offset)
+            .load::<(Ticker, Instrument)>(connection)?;
+
+        Ok((rows, total))
+    }
+
+    /// Every symbol held in at least one portfolio.
+    pub fn get_all_symbols(connection: &PgConnection) -> Result<Vec<String>, result::Error> {
+        tickers::table
+            .filter(tickers::is_deleted.eq(false))
+            .select(tickers::name)
+            .distinct()
+            .order(tickers::name.asc())
+            .load::<String>(connection)
+    }
+
     pub fn get_by_id(
         connection: &PgConnection,
         ticker_id: &String,
     ) -> Result<Option<Ticker>, result::Error> {
         match tickers::table
-            .filter(tickers::portfolio_id.eq(ticker_id))
+            .filter(tickers::id.eq(ticker_id))
             .filter(tickers::is_deleted.eq(false))
             .load::<Ticker>(connection)
         {
@@ -49,6 +119,23 @@ impl Ticker {
         }
     }
 
+    /// The ticker, only if its portfolio belongs to `user_id`.
+    pub fn get_for_owner(
+        connection: &PgConnection,
+        ticker_id: &str,
+        user_id: &str,
+    ) -> Result<Option<Ticker>, result::Error> {
+        tickers::table
+            .inner_join(portfolios::table)
+            .filter(tickers::id.eq(ticker_id))
+            .filter(tickers::is_deleted.eq(false))
+            .filter(portfolios::user_id.eq(user_id))
+            .filter(portfolios::is_deleted.eq(false))
+            .select(tickers::all_columns)
+            .first::<Ticker>(connection)
+            .optional()
+    }
+
     pub fn get_by_name(
         connection: &PgConnection,
         name: &String,
@@ -94,7 +181,7 @@ impl Ticker {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct NewTicker {
     pub name: String,
     pub portfolio_id: String,
@@ -105,14 +192,16 @@ impl NewTicker {
         name: String,
         portfolio_id: String,
         connection: &PgConnection,
-    ) -> Result<Ticker, result::Error> {
+    ) -> Result<Ticker, ModelError> {
         let ticker: Ticker = Ticker::new(name.clone(), portfolio_id.clone());
 
-        match Ticker::get_by_name(connection, &name, &portfolio_id).unwrap() {
-            Some(_) => Err(result::Error::__Nonexhaustive),
-            None => diesel::insert_into(tickers::table)
+        match Ticker::get_by_name(connection, &name, &portfolio_id)? {
+            Some(_) => Err(ModelError::Conflict(String::from(
+                "Ticker with that name already exists",
+            ))),
+            None => Ok(diesel::insert_into(tickers::table)
                 .values(&ticker)
-                .get_result::<Ticker>(connection),
+                .get_result::<Ticker>(connection)?),
         }
     }
 }
@@ -0,0 +1,124 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+/// RFC 6238 defaults, which is what authenticator apps assume.
+pub const STEP_SECONDS: i64 = 30;
+pub const DIGITS: u32 = 6;
+/// Steps either side of the current one still accepted, for clock drift.
+pub const ALLOWED_DRIFT: i64 = 1;
+
+const ALPHABET: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+/// A new 160-bit shared secret, base32 encoded as authenticator apps expect.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+
+    base32::encode(ALPHABET, &bytes)
+}
+
+/// Key URI understood by authenticator apps, usually shown as a QR code.
+pub fn otpauth_uri(secret: &str, account: &str, issuer: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = encode_component(issuer),
+        account = encode_component(account),
+        secret = secret,
+        digits = DIGITS,
+        period = STEP_SECONDS,
+    )
+}
+
+fn encode_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+pub fn step_at(timestamp: i64) -> i64 {
+    timestamp.div_euclid(STEP_SECONDS)
+}
+
+/// The code for a given time step, or `None` if the secret is not valid base32.
+pub fn code_for_step(secret: &str, step: i64) -> Option<String> {
+    let key = base32::decode(ALPHABET, &secret.to_uppercase())?;
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).ok()?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Returns the time step the code belongs to when it is valid at `timestamp`.
+/// Callers reject steps they have already accepted so a code works only once.
+pub fn verify(secret: &str, code: &str, timestamp: i64) -> Option<i64> {
+    let code = code.trim();
+    let current = step_at(timestamp);
+
+    (current - ALLOWED_DRIFT..=current + ALLOWED_DRIFT)
+        .find(|step| code_for_step(secret, *step).as_deref() == Some(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{code_for_step, generate_secret, otpauth_uri, step_at, verify};
+
+    // "12345678901234567890", the RFC 6238 SHA-1 test key.
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_rfc_6238_vectors() {
+        assert_eq!(code_for_step(RFC_SECRET, step_at(59)).unwrap(), "287082");
+        assert_eq!(
+            code_for_step(RFC_SECRET, step_at(1111111109)).unwrap(),
+            "081804"
+        );
+        assert_eq!(
+            code_for_step(RFC_SECRET, step_at(1234567890)).unwrap(),
+            "005924"
+        );
+    }
+
+    #[test]
+    fn test_verify_allows_one_step_of_drift() {
+        assert_eq!(verify(RFC_SECRET, "287082", 59), Some(1));
+        assert_eq!(verify(RFC_SECRET, "287082", 89), Some(1));
+        assert_eq!(verify(RFC_SECRET, "287082", 150), None);
+        assert_eq!(verify(RFC_SECRET, "000000", 59), None);
+    }
+
+    #[test]
+    fn test_generated_secret_round_trips() {
+        let secret = generate_secret();
+
+        assert_eq!(secret.len(), 32);
+        assert!(code_for_step(&secret, 1).is_some());
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        assert_eq!(
+            otpauth_uri(RFC_SECRET, "user@example.com", "Stocks App"),
+            "otpauth://totp/Stocks%20App:user@example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=Stocks%20App&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
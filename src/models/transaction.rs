@@ -0,0 +1,314 @@
+use crate::schema::transactions;
+
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+pub const BUY: &str = "buy";
+pub const SELL: &str = "sell";
+
+// Quantities are fractional, so anything closer to zero than this is treated as flat.
+const QUANTITY_EPSILON: f64 = 1e-9;
+
+#[derive(Queryable, PartialEq, Insertable, Debug, Clone)]
+#[table_name = "transactions"]
+pub struct Transaction {
+    pub id: String,
+    pub portfolio_id: String,
+    pub ticker_id: String,
+    pub kind: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub fees: f64,
+    pub trade_date: NaiveDate,
+    pub is_deleted: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl Transaction {
+    pub fn new(portfolio_id: String, new_transaction: NewTransaction) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4().to_string(),
+            portfolio_id,
+            ticker_id: new_transaction.ticker_id,
+            kind: new_transaction.kind,
+            quantity: new_transaction.quantity,
+            price: new_transaction.price,
+            fees: new_transaction.fees,
+            trade_date: new_transaction.trade_date,
+            is_deleted: false,
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    /// Position in the ledger: trades of the same day replay in the order they
+    /// were recorded, and the id settles anything left.
+    pub fn ledger_key(&self) -> (NaiveDate, NaiveDateTime, &str) {
+        (self.trade_date, self.created_at, &self.id)
+    }
+
+    pub fn is_buy(&self) -> bool {
+        self.kind == BUY
+    }
+
+    /// Quantity with the sign applied: positive for buys, negative for sells.
+    pub fn signed_quantity(&self) -> f64 {
+        if self.is_buy() {
+            self.quantity
+        } else {
+            -self.quantity
+        }
+    }
+
+    pub fn get_all_from_portfolio(
+        connection: &PgConnection,
+        portfolio_id: &String,
+    ) -> Result<Vec<Transaction>, result::Error> {
+        transactions::table
+            .filter(transactions::portfolio_id.eq(portfolio_id))
+            .filter(transactions::is_deleted.eq(false))
+            .order((
+                transactions::trade_date.asc(),
+                transactions::created_at.asc(),
+                transactions::id.asc(),
+            ))
+            .load::<Transaction>(connection)
+    }
+
+    pub fn get_by_id(
+        connection: &PgConnection,
+        id: &String,
+        portfolio_id: &String,
+    ) -> Result<Transaction, result::Error> {
+        transactions::table
+            .filter(transactions::id.eq(id))
+            .filter(transactions::portfolio_id.eq(portfolio_id))
+            .filter(transactions::is_deleted.eq(false))
+            .get_result::<Transaction>(connection)
+    }
+
+    pub fn update(
+        self,
+        connection: &PgConnection,
+        changes: NewTransaction,
+    ) -> Result<Transaction, result::Error> {
+        diesel::update(transactions::table.find(self.id))
+            .filter(transactions::is_deleted.eq(false))
+            .set((
+                transactions::ticker_id.eq(changes.ticker_id),
+                transactions::kind.eq(changes.kind),
+                transactions::quantity.eq(changes.quantity),
+                transactions::price.eq(changes.price),
+                transactions::fees.eq(changes.fees),
+                transactions::trade_date.eq(changes.trade_date),
+            ))
+            .get_result::<Transaction>(connection)
+    }
+
+    pub fn delete_transaction(
+        connection: &PgConnection,
+        transaction_id: &String,
+    ) -> Result<Transaction, result::Error> {
+        diesel::update(transactions::table.find(transaction_id))
+            .filter(transactions::is_deleted.eq(false))
+            .set(transactions::is_deleted.eq(true))
+            .get_result::<Transaction>(connection)
+    }
+
+    pub fn delete_transactions(
+        connection: &PgConnection,
+        portfolio_id: &String,
+    ) -> Result<Vec<Transaction>, result::Error> {
+        diesel::update(transactions::table.filter(transactions::portfolio_id.eq(portfolio_id)))
+            .filter(transactions::is_deleted.eq(false))
+            .set(transactions::is_deleted.eq(true))
+            .get_results::<Transaction>(connection)
+    }
+
+    pub fn delete_from_ticker(
+        connection: &PgConnection,
+        ticker_id: &String,
+    ) -> Result<Vec<Transaction>, result::Error> {
+        diesel::update(transactions::table.filter(transactions::ticker_id.eq(ticker_id)))
+            .filter(transactions::is_deleted.eq(false))
+            .set(transactions::is_deleted.eq(true))
+            .get_results::<Transaction>(connection)
+    }
+}
+
+fn validate_kind(kind: &str) -> Result<(), ValidationError> {
+    if kind == BUY || kind == SELL {
+        Ok(())
+    } else {
+        Err(ValidationError::new("kind must be 'buy' or 'sell'"))
+    }
+}
+
+fn validate_quantity(quantity: f64) -> Result<(), ValidationError> {
+    if quantity > 0.0 {
+        Ok(())
+    } else {
+        Err(ValidationError::new("quantity must be positive"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate, Clone, ToSchema)]
+pub struct NewTransaction {
+    pub ticker_id: String,
+    #[validate(custom = "validate_kind")]
+    pub kind: String,
+    #[validate(custom = "validate_quantity")]
+    pub quantity: f64,
+    #[validate(range(min = 0.0))]
+    pub price: f64,
+    #[serde(default)]
+    #[validate(range(min = 0.0))]
+    pub fees: f64,
+    pub trade_date: NaiveDate,
+}
+
+impl NewTransaction {
+    pub fn create(
+        self,
+        portfolio_id: String,
+        connection: &PgConnection,
+    ) -> Result<Transaction, result::Error> {
+        let transaction: Transaction = Transaction::new(portfolio_id, self);
+
+        diesel::insert_into(transactions::table)
+            .values(&transaction)
+            .get_result::<Transaction>(connection)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Position {
+    pub ticker_id: String,
+    pub quantity: f64,
+}
+
+impl Position {
+    /// Replays the ledger in `ledger_key` order and returns every non-zero holding,
+    /// or the id of the first transaction that sells more than is held at that point.
+    pub fn from_transactions(transactions: &[Transaction]) -> Result<Vec<Position>, String> {
+        let mut ordered: Vec<&Transaction> = transactions.iter().collect();
+        ordered.sort_by(|a, b| a.ledger_key().cmp(&b.ledger_key()));
+
+        let mut positions: Vec<Position> = Vec::new();
+
+        for transaction in ordered {
+            let index = match positions
+                .iter()
+                .position(|position| position.ticker_id == transaction.ticker_id)
+            {
+                Some(index) => index,
+                None => {
+                    positions.push(Position {
+                        ticker_id: transaction.ticker_id.clone(),
+                        quantity: 0.0,
+                    });
+                    positions.len() - 1
+                }
+            };
+
+            positions[index].quantity += transaction.signed_quantity();
+
+            if positions[index].quantity < -QUANTITY_EPSILON {
+                return Err(transaction.id.clone());
+            }
+        }
+
+        Ok(positions
+            .into_iter()
+            .filter(|position| position.quantity > QUANTITY_EPSILON)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NewTransaction, Position, Transaction, BUY, SELL};
+    use chrono::{Duration, NaiveDate};
+
+    fn transaction(ticker_id: &str, kind: &str, quantity: f64, day: u32) -> Transaction {
+        Transaction::new(
+            String::from("portfolio"),
+            NewTransaction {
+                ticker_id: String::from(ticker_id),
+                kind: String::from(kind),
+                quantity,
+                price: 100.0,
+                fees: 0.0,
+                trade_date: NaiveDate::from_ymd(2022, 8, day),
+            },
+        )
+    }
+
+    #[test]
+    fn test_positions_from_transactions() {
+        let transactions = vec![
+            transaction("aapl", BUY, 10.0, 1),
+            transaction("gs", BUY, 5.0, 2),
+            transaction("aapl", SELL, 4.0, 3),
+        ];
+
+        let positions = Position::from_transactions(&transactions).unwrap();
+
+        assert_eq!(
+            positions,
+            vec![
+                Position {
+                    ticker_id: String::from("aapl"),
+                    quantity: 6.0
+                },
+                Position {
+                    ticker_id: String::from("gs"),
+                    quantity: 5.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_positions_skip_closed() {
+        let transactions = vec![
+            transaction("aapl", BUY, 10.0, 1),
+            transaction("aapl", SELL, 10.0, 2),
+        ];
+
+        let positions = Position::from_transactions(&transactions).unwrap();
+
+        assert!(positions.is_empty());
+    }
+
+    #[test]
+    fn test_positions_oversold() {
+        // The sell is recorded before the buy, so it is never covered
+        let sell = transaction("aapl", SELL, 5.0, 1);
+        let transactions = vec![transaction("aapl", BUY, 10.0, 2), sell.clone()];
+
+        let result = Position::from_transactions(&transactions);
+
+        assert_eq!(result, Err(sell.id));
+    }
+
+    #[test]
+    fn test_positions_same_day_follow_recording_order() {
+        let buy = transaction("aapl", BUY, 10.0, 1);
+        let mut sell = transaction("aapl", SELL, 10.0, 1);
+        sell.created_at = buy.created_at + Duration::seconds(1);
+
+        // As after editing the buy, which then comes last in the list.
+        let positions = Position::from_transactions(&[sell.clone(), buy.clone()]).unwrap();
+        assert!(positions.is_empty());
+
+        sell.created_at = buy.created_at - Duration::seconds(1);
+        let result = Position::from_transactions(&[buy, sell.clone()]);
+        assert_eq!(result, Err(sell.id));
+    }
+}
@@ -1,21 +1,36 @@
-use crate::models::authentication::Claims;
+use crate::models::error::ModelError;
+use crate::models::listing::{ListOptions, SortField};
+use crate::models::password::{self, HashParams};
+use crate::models::recovery_code::RecoveryCode;
+use crate::models::refresh_token::RefreshToken;
+use crate::models::role::Role;
+use crate::models::totp;
 use crate::schema::users;
 
+use chrono::{NaiveDateTime, Utc};
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::result;
-use pwhash::bcrypt;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 use validator::Validate;
 
-#[derive(Queryable, PartialEq, Insertable, Serialize, Deserialize)]
+/// Never serialized: responses use `UserView`, which leaves out the hashes.
+#[derive(Queryable, PartialEq, Insertable)]
 #[table_name = "users"]
 pub struct User {
     pub id: String,
     pub email: String,
     pub password: String,
     pub is_deleted: bool,
+    pub role: String,
+    pub email_verified: bool,
+    pub token_version: i32,
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+    pub created_at: NaiveDateTime,
 }
 
 impl User {
@@ -25,11 +40,58 @@ impl User {
             email,
             password,
             is_deleted: false,
+            role: String::from(Role::User.as_str()),
+            email_verified: false,
+            token_version: 0,
+            totp_secret: None,
+            totp_enabled: false,
+            totp_last_step: None,
+            created_at: Utc::now().naive_utc(),
         }
     }
 
-    pub fn get_all(connection: &PgConnection) -> Result<Vec<User>, result::Error> {
-        users::table.load::<User>(connection)
+    pub fn role(&self) -> Role {
+        Role::parse(&self.role).unwrap_or_default()
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role() == Role::Admin
+    }
+
+    /// One page of accounts and the number of accounts matching the filters.
+    /// The name filter and sort apply to the email address.
+    pub fn page(
+        connection: &PgConnection,
+        options: &ListOptions,
+    ) -> Result<(Vec<User>, i64), result::Error> {
+        let filtered = || {
+            let mut query = users::table.into_boxed();
+            if !options.include_deleted {
+                query = query.filter(users::is_deleted.eq(false));
+            }
+            if let Some(pattern) = options.name_pattern() {
+                query = query.filter(users::email.ilike(pattern));
+            }
+            query
+        };
+
+        let total = filtered().count().get_result::<i64>(connection)?;
+        let query = match (options.sort, options.descending) {
+            (SortField::Name, false) => filtered().order((users::email.asc(), users::id.asc())),
+            (SortField::Name, true) => filtered().order((users::email.desc(), users::id.desc())),
+            (SortField::CreatedAt, false) => {
+                filtered().order((users::created_at.asc(), users::id.asc()))
+            }
+            (SortField::CreatedAt, true) => {
+                filtered().order((users::created_at.desc(), users::id.desc()))
+            }
+        };
+        let users = query
+            .limit(options.limit)
+            .offset(options.offset)
+            .load::<User>(connection)?;
+
+        Ok((users, total))
     }
 
     pub fn get_by_id(connection: &PgConnection, id: &String) -> Result<User, result::Error> {
@@ -42,9 +104,11 @@ impl User {
         }
     }
 
+    /// The live account using `email`; deleted accounts have given it up.
     pub fn get_by_email(connection: &PgConnection, email: &String) -> Result<User, result::Error> {
         match users::table
             .filter(users::email.eq(email))
+            .filter(users::is_deleted.eq(false))
             .load::<User>(connection)
         {
             Ok(mut results) => match results.pop() {
@@ -60,8 +124,9 @@ impl User {
         connection: &PgConnection,
         email: String,
     ) -> Result<User, result::Error> {
+        // A new address has to be confirmed again.
         match diesel::update(users::table.find(self.id))
-            .set(users::email.eq(email))
+            .set((users::email.eq(email), users::email_verified.eq(false)))
             .get_result::<User>(connection)
         {
             Ok(user) => Ok(user),
@@ -73,19 +138,128 @@ impl User {
         self,
         connection: &PgConnection,
         password: String,
-    ) -> Result<(), result::Error> {
-        let hash_password = match bcrypt::hash(password) {
-            Ok(hashed) => Ok(hashed),
-            Err(err) => Err(err),
-        };
+        params: &HashParams,
+    ) -> Result<(), ModelError> {
+        let hash_password = password::hash_with(&password, params)?;
 
-        match diesel::update(users::table.find(self.id))
-            .set(users::password.eq(hash_password.unwrap()))
+        User::set_password_hash(connection, &self.id, &hash_password)?;
+
+        Ok(())
+    }
+
+    pub fn set_password_hash(
+        connection: &PgConnection,
+        user_id: &String,
+        hash: &str,
+    ) -> Result<User, result::Error> {
+        diesel::update(users::table.find(user_id))
+            .set(users::password.eq(hash))
             .get_result::<User>(connection)
-        {
-            Ok(_) => Ok(()),
-            Err(err) => Err(err),
+    }
+
+    pub fn mark_email_verified(
+        connection: &PgConnection,
+        user_id: &String,
+    ) -> Result<User, result::Error> {
+        diesel::update(users::table.find(user_id))
+            .set(users::email_verified.eq(true))
+            .get_result::<User>(connection)
+    }
+
+    /// Rejects every access token issued so far and ends all refresh sessions.
+    pub fn invalidate_sessions(
+        connection: &PgConnection,
+        user_id: &String,
+    ) -> Result<User, result::Error> {
+        connection.transaction(|| {
+            RefreshToken::revoke_all_for_user(connection, user_id)?;
+
+            diesel::update(users::table.find(user_id))
+                .set(users::token_version.eq(users::token_version + 1))
+                .get_result::<User>(connection)
+        })
+    }
+
+    /// Stores a new, not yet confirmed, TOTP secret.
+    pub fn start_totp_enrollment(
+        connection: &PgConnection,
+        user_id: &String,
+        secret: &str,
+    ) -> Result<User, result::Error> {
+        diesel::update(users::table.find(user_id))
+            .set((
+                users::totp_secret.eq(secret),
+                users::totp_enabled.eq(false),
+                users::totp_last_step.eq(None::<i64>),
+            ))
+            .get_result::<User>(connection)
+    }
+
+    pub fn enable_totp(connection: &PgConnection, user_id: &String) -> Result<User, result::Error> {
+        diesel::update(users::table.find(user_id))
+            .set(users::totp_enabled.eq(true))
+            .get_result::<User>(connection)
+    }
+
+    pub fn disable_totp(
+        connection: &PgConnection,
+        user_id: &String,
+    ) -> Result<User, result::Error> {
+        connection.transaction(|| {
+            RecoveryCode::delete_all(connection, user_id)?;
+
+            diesel::update(users::table.find(user_id))
+                .set((
+                    users::totp_secret.eq(None::<String>),
+                    users::totp_enabled.eq(false),
+                    users::totp_last_step.eq(None::<i64>),
+                ))
+                .get_result::<User>(connection)
+        })
+    }
+
+    /// Checks a TOTP code against the stored secret. Each time step is accepted
+    /// at most once, so an observed code cannot be replayed.
+    pub fn check_totp(&self, connection: &PgConnection, code: &str) -> Result<bool, result::Error> {
+        let secret = match &self.totp_secret {
+            Some(secret) => secret,
+            None => return Ok(false),
+        };
+        let step = match totp::verify(secret, code, Utc::now().timestamp()) {
+            Some(step) => step,
+            None => return Ok(false),
+        };
+
+        let accepted = diesel::update(
+            users::table.find(&self.id).filter(
+                users::totp_last_step
+                    .is_null()
+                    .or(users::totp_last_step.lt(step)),
+            ),
+        )
+        .set(users::totp_last_step.eq(step))
+        .execute(connection)?;
+
+        Ok(accepted > 0)
+    }
+
+    /// A TOTP code, or failing that one of the user's unused recovery codes.
+    pub fn check_second_factor(
+        &self,
+        connection: &PgConnection,
+        code: &str,
+    ) -> Result<bool, result::Error> {
+        if self.check_totp(connection, code)? {
+            return Ok(true);
         }
+
+        RecoveryCode::redeem(connection, &self.id, code)
+    }
+
+    pub fn update_role(self, connection: &PgConnection, role: Role) -> Result<User, result::Error> {
+        diesel::update(users::table.find(self.id))
+            .set(users::role.eq(role.as_str()))
+            .get_result::<User>(connection)
     }
 
     pub fn delete_user(connection: &PgConnection, user_id: &String) -> Result<User, result::Error> {
@@ -99,21 +273,15 @@ impl User {
         }
     }
 
-    pub fn generate_jwt(&self) -> String {
-        crate::models::authentication::generate(&self)
-    }
-
-    pub fn from_jwt(claims: &Claims) -> Self {
-        User {
-            id: String::from(&claims.sub),
-            email: String::from(&claims.email),
-            password: String::new(),
-            is_deleted: false,
-        }
+    pub fn generate_jwt(
+        &self,
+        keys: &crate::infrastructure::keys::KeySet,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        crate::models::authentication::generate(keys, self)
     }
 }
 
-#[derive(Serialize, Deserialize, Validate)]
+#[derive(Serialize, Deserialize, Validate, ToSchema)]
 pub struct NewUser {
     #[validate(email)]
     pub email: String,
@@ -125,19 +293,18 @@ impl NewUser {
     pub fn create(
         email: String,
         password: String,
+        params: &HashParams,
         connection: &PgConnection,
-    ) -> Result<User, result::Error> {
-        let hash_password = match bcrypt::hash(password) {
-            Ok(hashed) => hashed,
-            Err(err) => return Err(result::Error::__Nonexhaustive),
-        };
+    ) -> Result<User, ModelError> {
+        let hash_password = password::hash_with(&password, params)?;
         let user: User = User::new(email.clone(), hash_password);
 
-        match User::get_by_email(&connection, &email) {
-            Ok(_) => Err(result::Error::__Nonexhaustive),
-            Err(_) => diesel::insert_into(users::table)
+        match User::get_by_email(connection, &email) {
+            Ok(_) => Err(ModelError::Conflict(String::from("User already exists"))),
+            Err(result::Error::NotFound) => Ok(diesel::insert_into(users::table)
                 .values(&user)
-                .get_result::<User>(connection),
+                .get_result::<User>(connection)?),
+            Err(err) => Err(err.into()),
         }
     }
 }
@@ -1,3 +1,53 @@
+table! {
+    api_keys (id) {
+        id -> Varchar,
+        user_id -> Varchar,
+        name -> Varchar,
+        prefix -> Varchar,
+        secret_hash -> Varchar,
+        scopes -> Array<Text>,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    instruments (symbol) {
+        symbol -> Varchar,
+        short_name -> Varchar,
+        long_name -> Varchar,
+        exchange -> Varchar,
+        currency -> Varchar,
+        asset_type -> Varchar,
+        last_refreshed -> Timestamp,
+    }
+}
+
+table! {
+    login_events (id) {
+        id -> Varchar,
+        user_id -> Nullable<Varchar>,
+        email -> Varchar,
+        ip -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        method -> Varchar,
+        success -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    one_time_tokens (token_hash) {
+        token_hash -> Varchar,
+        user_id -> Varchar,
+        purpose -> Varchar,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     portfolios (id) {
         id -> Varchar,
@@ -5,6 +55,50 @@ table! {
         created_at -> Date,
         is_deleted -> Bool,
         user_id -> Varchar,
+        cost_basis_method -> Varchar,
+    }
+}
+
+table! {
+    price_bars (symbol, interval, timestamp) {
+        symbol -> Varchar,
+        interval -> Varchar,
+        timestamp -> Int8,
+        open -> Float8,
+        high -> Float8,
+        low -> Float8,
+        close -> Float8,
+        adjclose -> Float8,
+        volume -> Int8,
+    }
+}
+
+table! {
+    recovery_codes (id) {
+        id -> Varchar,
+        user_id -> Varchar,
+        code_hash -> Varchar,
+        used_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    refresh_tokens (id) {
+        id -> Varchar,
+        user_id -> Varchar,
+        family_id -> Varchar,
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        replaced_by -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    revoked_tokens (jti) {
+        jti -> Varchar,
+        expires_at -> Timestamp,
     }
 }
 
@@ -14,6 +108,22 @@ table! {
         name -> Varchar,
         portfolio_id -> Varchar,
         is_deleted -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    transactions (id) {
+        id -> Varchar,
+        portfolio_id -> Varchar,
+        ticker_id -> Varchar,
+        kind -> Varchar,
+        quantity -> Float8,
+        price -> Float8,
+        fees -> Float8,
+        trade_date -> Date,
+        is_deleted -> Bool,
+        created_at -> Timestamp,
     }
 }
 
@@ -23,10 +133,38 @@ table! {
         email -> Varchar,
         password -> Varchar,
         is_deleted -> Bool,
+        role -> Varchar,
+        email_verified -> Bool,
+        token_version -> Int4,
+        totp_secret -> Nullable<Varchar>,
+        totp_enabled -> Bool,
+        totp_last_step -> Nullable<Int8>,
+        created_at -> Timestamp,
     }
 }
 
+joinable!(api_keys -> users (user_id));
+joinable!(login_events -> users (user_id));
+joinable!(one_time_tokens -> users (user_id));
 joinable!(portfolios -> users (user_id));
+joinable!(recovery_codes -> users (user_id));
+joinable!(refresh_tokens -> users (user_id));
+joinable!(tickers -> instruments (name));
 joinable!(tickers -> portfolios (portfolio_id));
+joinable!(transactions -> portfolios (portfolio_id));
+joinable!(transactions -> tickers (ticker_id));
 
-allow_tables_to_appear_in_same_query!(portfolios, tickers, users,);
+allow_tables_to_appear_in_same_query!(
+    api_keys,
+    instruments,
+    login_events,
+    one_time_tokens,
+    portfolios,
+    price_bars,
+    recovery_codes,
+    refresh_tokens,
+    revoked_tokens,
+    tickers,
+    transactions,
+    users,
+);